@@ -0,0 +1,235 @@
+//! ファイルなどを最後まで解析して、フレーム毎の値とまとめを書き出す
+
+use std::{io::Write, str::FromStr};
+
+use anyhow::{bail, Error, Result};
+use serde::Serialize;
+
+use crate::{
+    loudness::{LoudnessMeter, LoudnessSummary},
+    util::App,
+};
+
+/// STFT のフレーム 1 つ分の結果
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FrameReport {
+    /// フレームの中心の時刻 (秒)
+    pub time: f64,
+    /// フレーム内のサンプルピーク (dBFS)。全チャンネルの最大
+    pub peak: f64,
+    /// フレーム内の RMS (dBFS)。全チャンネルの最大
+    pub rms: f64,
+    /// その時点までの momentary ラウドネス (LUFS)
+    pub momentary: Option<f64>,
+    /// その時点までの short-term ラウドネス (LUFS)
+    pub short_term: Option<f64>,
+    /// `App::data` と同じ帯域毎の値
+    pub spectrum: Vec<(f64, f64)>,
+}
+
+impl FrameReport {
+    pub fn from_app(app: &App) -> FrameReport {
+        let (peak, rms) = app
+            .frame()
+            .iter()
+            .map(|samples| {
+                let peak = samples.iter().fold(0.0f32, |max, s| max.max(s.abs())) as f64;
+                let power = samples.iter().map(|&s| (s as f64).powi(2)).sum::<f64>()
+                    / samples.len().max(1) as f64;
+                (peak, power.sqrt())
+            })
+            .fold((0.0f64, 0.0f64), |(peak, rms), (p, r)| {
+                (peak.max(p), rms.max(r))
+            });
+        let loudness = app.loudness();
+        FrameReport {
+            time: app.frame_time(),
+            peak: 20.0 * peak.log10(),
+            rms: 20.0 * rms.log10(),
+            momentary: loudness.and_then(LoudnessMeter::momentary),
+            short_term: loudness.and_then(LoudnessMeter::short_term),
+            spectrum: app.data().to_vec(),
+        }
+    }
+}
+
+/// 全体のまとめ
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AnalysisSummary {
+    /// 解析した STFT のフレーム数
+    pub frames: u64,
+    /// 最後のフレームの中心の時刻 (秒)
+    pub duration: f64,
+    /// 全フレームのサンプルピーク (dBFS)
+    pub sample_peak: f64,
+    #[serde(flatten)]
+    pub loudness: LoudnessSummary,
+    /// 全フレームのパワーの平均を `App::scale` で変換したもの
+    pub average_spectrum: Vec<(f64, f64)>,
+}
+
+/// `app` のソースを最後まで読んで、フレーム毎に `on_frame` を呼ぶ
+pub fn analyze(
+    app: &mut App,
+    mut on_frame: impl FnMut(FrameReport) -> Result<()>,
+) -> Result<AnalysisSummary> {
+    app.reset_smoothing();
+    app.reset_loudness();
+    let mut frames = 0;
+    let mut duration = 0.0;
+    let mut sample_peak = f64::NEG_INFINITY;
+    app.analyze_all(|app| {
+        let report = FrameReport::from_app(app);
+        frames += 1;
+        duration = report.time;
+        sample_peak = sample_peak.max(report.peak);
+        on_frame(report)
+    })?;
+    Ok(AnalysisSummary {
+        frames,
+        duration,
+        sample_peak,
+        loudness: app
+            .loudness()
+            .map(LoudnessMeter::summary)
+            .unwrap_or_default(),
+        average_spectrum: app.average().into_iter().next().unwrap_or_default(),
+    })
+}
+
+/// フレーム毎の書き出し方
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// 1 行に 1 つの JSON。最後の行がまとめ
+    #[default]
+    Json,
+    /// 1 行目がヘッダの CSV。まとめは最後に `# name,value` と `# average_spectrum…` の行で足す
+    Csv,
+}
+
+impl ReportFormat {
+    pub const VARIANTS: [ReportFormat; 2] = [ReportFormat::Json, ReportFormat::Csv];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReportFormat::Json => "json",
+            ReportFormat::Csv => "csv",
+        }
+    }
+}
+
+impl FromStr for ReportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match ReportFormat::VARIANTS
+            .into_iter()
+            .find(|format| format.name().eq_ignore_ascii_case(s))
+        {
+            Some(format) => Ok(format),
+            None => bail!("Unknown report format: {s}"),
+        }
+    }
+}
+
+/// JSON Lines の 1 行。`type` で見分ける。無限大や NaN は JSON に書けないので `null` になる
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum JsonLine<'a> {
+    Frame(&'a FrameReport),
+    Summary(&'a AnalysisSummary),
+}
+
+/// `FrameReport` と `AnalysisSummary` を `format` で書き出す
+pub struct ReportWriter<W: Write> {
+    writer: W,
+    format: ReportFormat,
+    header_written: bool,
+}
+
+impl<W: Write> ReportWriter<W> {
+    pub fn new(writer: W, format: ReportFormat) -> ReportWriter<W> {
+        ReportWriter {
+            writer,
+            format,
+            header_written: false,
+        }
+    }
+
+    pub fn frame(&mut self, report: &FrameReport) -> Result<()> {
+        match self.format {
+            ReportFormat::Json => self.json_line(&JsonLine::Frame(report))?,
+            ReportFormat::Csv => {
+                if !self.header_written {
+                    let bands = report
+                        .spectrum
+                        .iter()
+                        .map(|(freq, _)| format!(",{freq:.1}Hz"))
+                        .collect::<String>();
+                    writeln!(self.writer, "time,peak,rms,momentary,short_term{bands}")?;
+                    self.header_written = true;
+                }
+                let values = report
+                    .spectrum
+                    .iter()
+                    .map(|(_, value)| format!(",{value}"))
+                    .collect::<String>();
+                writeln!(
+                    self.writer,
+                    "{},{},{},{},{}{values}",
+                    report.time,
+                    report.peak,
+                    report.rms,
+                    csv_option(report.momentary),
+                    csv_option(report.short_term),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn summary(&mut self, summary: &AnalysisSummary) -> Result<()> {
+        match self.format {
+            ReportFormat::Json => self.json_line(&JsonLine::Summary(summary))?,
+            ReportFormat::Csv => {
+                // 表の列に合わないので、`#` で始まる名前と値の行にして最後に足す
+                let loudness = &summary.loudness;
+                let rows = [
+                    ("frames", summary.frames.to_string()),
+                    ("duration", summary.duration.to_string()),
+                    ("sample_peak", summary.sample_peak.to_string()),
+                    ("integrated", csv_option(loudness.integrated)),
+                    ("range", csv_option(loudness.range)),
+                    ("true_peak", csv_option(loudness.true_peak)),
+                    ("max_momentary", csv_option(loudness.max_momentary)),
+                    ("max_short_term", csv_option(loudness.max_short_term)),
+                ];
+                for (name, value) in rows {
+                    writeln!(self.writer, "# {name},{value}")?;
+                }
+                let average = summary
+                    .average_spectrum
+                    .iter()
+                    .map(|(_, value)| format!(",{value}"))
+                    .collect::<String>();
+                writeln!(self.writer, "# average_spectrum{average}")?;
+            }
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn json_line(&mut self, line: &JsonLine) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, line)?;
+        writeln!(self.writer)?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+fn csv_option(value: Option<f64>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
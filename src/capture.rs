@@ -0,0 +1,383 @@
+//! 専用のスレッドで音を取り込んで、上限付きのキューで渡す
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    str::FromStr,
+    sync::{mpsc, Arc, Condvar, Mutex, MutexGuard},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context as _, Error, Result};
+
+use crate::{
+    format::WaveFormatEx,
+    packet::{Packet, PacketInfo},
+    source::AudioSource,
+};
+
+/// 取り込みスレッドがソースを待つ最長の時間。これごとにキューが閉じられていないか見る
+const WAIT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// キューが一杯の時にどうするか
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// 空くまで取り込みスレッドを止める。その間はデバイスのバッファに溜まる
+    #[default]
+    Block,
+    /// 一番古いパケットを捨てる
+    DropOldest,
+    /// 入れようとしたパケットを捨てる
+    DropNewest,
+}
+
+impl Overflow {
+    pub const VARIANTS: [Overflow; 3] =
+        [Overflow::Block, Overflow::DropOldest, Overflow::DropNewest];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Overflow::Block => "block",
+            Overflow::DropOldest => "drop-oldest",
+            Overflow::DropNewest => "drop-newest",
+        }
+    }
+}
+
+impl FromStr for Overflow {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Overflow::VARIANTS
+            .into_iter()
+            .find(|overflow| overflow.name().eq_ignore_ascii_case(s))
+        {
+            Some(overflow) => Ok(overflow),
+            None => bail!("Unknown overflow policy: {s}"),
+        }
+    }
+}
+
+/// キューの出入りの記録
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    /// キューに入れたパケット
+    pub pushed: u64,
+    /// 取り出したパケット
+    pub popped: u64,
+    /// 溢れて捨てたパケット
+    pub dropped_packets: u64,
+    /// 溢れて捨てたバイト数
+    pub dropped_bytes: u64,
+    /// `Overflow::Block` で空くのを待った回数
+    pub blocked: u64,
+    /// 今入っているパケット
+    pub queued: usize,
+    /// 一番多く溜まった時のパケット数
+    pub high_water: usize,
+}
+
+impl fmt::Display for QueueStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} packets, {} dropped ({} bytes), {} blocked, {} queued at most",
+            self.pushed, self.dropped_packets, self.dropped_bytes, self.blocked, self.high_water
+        )
+    }
+}
+
+struct State {
+    packets: VecDeque<Packet>,
+    /// 捨てた後まだどのパケットにも付けていない分
+    dropped: PacketInfo,
+    /// 捨てたパケットに付いていた、まだ渡していないフォーマットの変化
+    dropped_format: Option<WaveFormatEx>,
+    closed: bool,
+    stats: QueueStats,
+}
+
+struct Shared {
+    capacity: usize,
+    overflow: Overflow,
+    state: Mutex<State>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+/// 取り込みスレッドから読む側にパケットを渡す上限付きのキュー。複製すると同じキューを指す
+#[derive(Clone)]
+pub struct PacketQueue {
+    shared: Arc<Shared>,
+}
+
+impl PacketQueue {
+    pub fn new(capacity: usize, overflow: Overflow) -> PacketQueue {
+        PacketQueue {
+            shared: Arc::new(Shared {
+                capacity: capacity.max(1),
+                overflow,
+                state: Mutex::new(State {
+                    packets: VecDeque::new(),
+                    dropped: PacketInfo::default(),
+                    dropped_format: None,
+                    closed: false,
+                    stats: QueueStats::default(),
+                }),
+                not_empty: Condvar::new(),
+                not_full: Condvar::new(),
+            }),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+
+    pub fn overflow(&self) -> Overflow {
+        self.shared.overflow
+    }
+
+    /// 一杯なら `overflow` に従う。閉じられていたら入れずに `false` を返す。
+    /// 捨てたパケットは、読む側で次に出てくるパケットの `dropped_packets` と `dropped_frames` に数える。
+    /// 捨てたパケットに付いていた `format` もそのパケットに移す
+    pub fn push(&self, packet: impl Into<Packet>) -> bool {
+        let mut packet = packet.into();
+        let mut state = self.lock();
+        if state.packets.len() >= self.shared.capacity && !state.closed {
+            match self.shared.overflow {
+                Overflow::Block => {
+                    state.stats.blocked += 1;
+                    while state.packets.len() >= self.shared.capacity && !state.closed {
+                        state = self
+                            .shared
+                            .not_full
+                            .wait(state)
+                            .unwrap_or_else(|e| e.into_inner());
+                    }
+                }
+                Overflow::DropOldest => {
+                    if let Some(oldest) = state.packets.pop_front() {
+                        state.stats.dropped_packets += 1;
+                        state.stats.dropped_bytes += oldest.data.len() as u64;
+                        match state.packets.front_mut() {
+                            Some(front) => front.add_dropped(&oldest),
+                            None => packet.add_dropped(&oldest),
+                        }
+                    }
+                }
+                Overflow::DropNewest => {
+                    state.stats.dropped_packets += 1;
+                    state.stats.dropped_bytes += packet.data.len() as u64;
+                    state.dropped.add_dropped(&packet.info);
+                    if packet.format.is_some() {
+                        state.dropped_format = packet.format;
+                    }
+                    return true;
+                }
+            }
+        }
+        if state.closed {
+            return false;
+        }
+        let dropped = std::mem::take(&mut state.dropped);
+        packet.info.dropped_packets += dropped.dropped_packets;
+        packet.info.dropped_frames += dropped.dropped_frames;
+        if let Some(format) = state.dropped_format.take() {
+            packet.format.get_or_insert(format);
+        }
+        state.packets.push_back(packet);
+        state.stats.pushed += 1;
+        state.stats.high_water = state.stats.high_water.max(state.packets.len());
+        self.shared.not_empty.notify_one();
+        true
+    }
+
+    pub fn try_pop(&self) -> Option<Packet> {
+        let mut state = self.lock();
+        let packet = state.packets.pop_front()?;
+        state.stats.popped += 1;
+        self.shared.not_full.notify_one();
+        Some(packet)
+    }
+
+    /// パケットが入るか、閉じられるか、`timeout` が過ぎるまで待つ。取り出せるパケットがあれば `true`
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.lock();
+        while state.packets.is_empty() && !state.closed {
+            let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+                break;
+            };
+            state = self
+                .shared
+                .not_empty
+                .wait_timeout(state, remaining)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        !state.packets.is_empty()
+    }
+
+    /// これ以上入れられないようにする。残っているパケットは取り出せる
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.shared.not_empty.notify_all();
+        self.shared.not_full.notify_all();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.lock().closed
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        let state = self.lock();
+        QueueStats {
+            queued: state.packets.len(),
+            ..state.stats
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // 片方が panic してももう片方は続けられる
+        self.shared.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 取り込みスレッドの中身。ソースが終わるかキューが閉じられるまで読んでキューに入れる
+pub fn pump(source: &mut dyn AudioSource, queue: &PacketQueue) -> Result<()> {
+    while !queue.is_closed() {
+        match source.get_packet()? {
+            Some(packet) => {
+                if !queue.push(packet) {
+                    break;
+                }
+            }
+            None if source.is_finished() => break,
+            None => source.wait(WAIT_TIMEOUT)?,
+        }
+    }
+    Ok(())
+}
+
+/// 専用のスレッドでソースを読むソース。`get_buffer` はキューから取り出すだけなので待たない
+pub struct CaptureThread {
+    wave_format: WaveFormatEx,
+    queue: PacketQueue,
+    handle: Option<JoinHandle<Result<()>>>,
+}
+
+impl CaptureThread {
+    /// 取り込みスレッドで `open` を呼んでソースを作る。
+    /// WASAPI のオブジェクトはスレッドを跨いで渡せないので、ソースはそのスレッドの中で作る
+    pub fn spawn<S, F>(open: F, queue: PacketQueue) -> Result<CaptureThread>
+    where
+        S: AudioSource,
+        F: FnOnce() -> Result<S> + Send + 'static,
+    {
+        let (format_sender, format_receiver) = mpsc::sync_channel(1);
+        let thread_queue = queue.clone();
+        let handle = thread::Builder::new()
+            .name("capture".to_string())
+            .spawn(move || {
+                let result = (|| {
+                    let mut source = match open() {
+                        Ok(source) => source,
+                        Err(e) => {
+                            let _ = format_sender.send(None);
+                            return Err(e);
+                        }
+                    };
+                    let _ = format_sender.send(Some(source.wave_format().clone()));
+                    pump(&mut source, &thread_queue)
+                })();
+                thread_queue.close();
+                result
+            })
+            .context("Failed to spawn capture thread.")?;
+
+        match format_receiver.recv() {
+            Ok(Some(wave_format)) => Ok(CaptureThread {
+                wave_format,
+                queue,
+                handle: Some(handle),
+            }),
+            // 開けなかった時のエラーはスレッドが返す
+            _ => match handle.join() {
+                Ok(Err(e)) => Err(e),
+                _ => bail!("Capture thread exited before opening the source."),
+            },
+        }
+    }
+
+    pub fn wave_format(&self) -> &WaveFormatEx {
+        &self.wave_format
+    }
+
+    pub fn queue(&self) -> &PacketQueue {
+        &self.queue
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        self.queue.stats()
+    }
+
+    /// 取り込みを止めてスレッドを待つ。取り込み中に起きたエラーはここで返る
+    pub fn stop(&mut self) -> Result<()> {
+        self.queue.close();
+        self.join()
+    }
+
+    fn join(&mut self) -> Result<()> {
+        match self.handle.take() {
+            Some(handle) => handle
+                .join()
+                .map_err(|_| anyhow!("Capture thread panicked."))?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl AudioSource for CaptureThread {
+    fn wave_format(&self) -> &WaveFormatEx {
+        CaptureThread::wave_format(self)
+    }
+
+    fn get_buffer(&mut self) -> Result<Option<Vec<u8>>> {
+        Ok(self.get_packet()?.map(|packet| packet.data))
+    }
+
+    /// 取り込みスレッドで付けた情報をそのまま返す。フォーマットが変わっていれば `wave_format` も変える
+    fn get_packet(&mut self) -> Result<Option<Packet>> {
+        if let Some(packet) = self.queue.try_pop() {
+            if let Some(format) = &packet.format {
+                self.wave_format = format.clone();
+            }
+            return Ok(Some(packet));
+        }
+        // 閉じられて空になったら、スレッドのエラーを拾う
+        if self.queue.is_closed() {
+            self.join()?;
+        }
+        Ok(None)
+    }
+
+    fn is_finished(&self) -> bool {
+        let stats = self.queue.stats();
+        self.queue.is_closed() && stats.queued == 0
+    }
+
+    fn wait(&mut self, timeout: Duration) -> Result<()> {
+        self.queue.wait(timeout);
+        Ok(())
+    }
+}
+
+impl Drop for CaptureThread {
+    fn drop(&mut self) {
+        if let Err(e) = self.stop() {
+            log::error!("Failed to stop capture thread: {e:?}");
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! 複数チャンネルをどう解析するか
+
+use std::str::FromStr;
+
+use anyhow::{bail, Error};
+
+/// 解析するチャンネルの選び方。モノラル入力では Right は Left と同じ、Side は無音になる
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChannelMix {
+    Left,
+    Right,
+    /// (L + R) / 2
+    #[default]
+    Mid,
+    /// (L - R) / 2
+    Side,
+    /// 全チャンネルのスペクトルのビン毎の最大値
+    Max,
+    /// チャンネル毎に別のスペクトル
+    All,
+}
+
+impl ChannelMix {
+    pub const VARIANTS: [ChannelMix; 6] = [
+        ChannelMix::Left,
+        ChannelMix::Right,
+        ChannelMix::Mid,
+        ChannelMix::Side,
+        ChannelMix::Max,
+        ChannelMix::All,
+    ];
+
+    /// FFT にかける系列をチャンネル毎のフレームから作る
+    pub fn series(&self, channels: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        let left = &channels[0];
+        let right = channels.get(1).unwrap_or(left);
+        match self {
+            ChannelMix::Left => vec![left.clone()],
+            ChannelMix::Right => vec![right.clone()],
+            ChannelMix::Mid => vec![left.iter().zip(right).map(|(l, r)| (l + r) / 2.0).collect()],
+            ChannelMix::Side => vec![left.iter().zip(right).map(|(l, r)| (l - r) / 2.0).collect()],
+            ChannelMix::Max | ChannelMix::All => channels.to_vec(),
+        }
+    }
+
+    /// `series` から求めたスペクトルをまとめる
+    pub fn combine(&self, spectra: Vec<Vec<(f64, f64)>>) -> Vec<Vec<(f64, f64)>> {
+        match self {
+            ChannelMix::Max => {
+                let mut spectra = spectra.into_iter();
+                let Some(mut max) = spectra.next() else {
+                    return vec![];
+                };
+                for spectrum in spectra {
+                    for ((_, max), (_, value)) in max.iter_mut().zip(spectrum) {
+                        *max = max.max(value);
+                    }
+                }
+                vec![max]
+            }
+            _ => spectra,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChannelMix::Left => "left",
+            ChannelMix::Right => "right",
+            ChannelMix::Mid => "mid",
+            ChannelMix::Side => "side",
+            ChannelMix::Max => "max",
+            ChannelMix::All => "all",
+        }
+    }
+}
+
+impl FromStr for ChannelMix {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match ChannelMix::VARIANTS
+            .into_iter()
+            .find(|mix| mix.name().eq_ignore_ascii_case(s))
+        {
+            Some(mix) => Ok(mix),
+            None => bail!("Unknown channel mix: {s}"),
+        }
+    }
+}
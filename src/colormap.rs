@@ -0,0 +1,81 @@
+//! スペクトログラムの値を色にする
+
+use std::str::FromStr;
+
+use anyhow::{bail, Error};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Colormap {
+    #[default]
+    Viridis,
+    Magma,
+    Grayscale,
+}
+
+/// matplotlib の viridis を等間隔に取ったもの
+const VIRIDIS: [u32; 9] = [
+    0x440154, 0x472d7b, 0x3b528b, 0x2c728e, 0x21918c, 0x28ae80, 0x5ec962, 0xaddc30, 0xfde725,
+];
+
+/// matplotlib の magma を等間隔に取ったもの
+const MAGMA: [u32; 10] = [
+    0x000004, 0x180f3d, 0x440f76, 0x721f81, 0x9e2f7f, 0xcd4071, 0xf1605d, 0xfd9668, 0xfeca8d,
+    0xfcfdbf,
+];
+
+const GRAYSCALE: [u32; 2] = [0x000000, 0xffffff];
+
+impl Colormap {
+    pub const VARIANTS: [Colormap; 3] = [Colormap::Viridis, Colormap::Magma, Colormap::Grayscale];
+
+    fn stops(&self) -> &'static [u32] {
+        match self {
+            Colormap::Viridis => &VIRIDIS,
+            Colormap::Magma => &MAGMA,
+            Colormap::Grayscale => &GRAYSCALE,
+        }
+    }
+
+    /// `0.0..=1.0` の値を `0x00RRGGBB` にする。範囲外は端の色
+    pub fn color(&self, t: f64) -> u32 {
+        let stops = self.stops();
+        let position = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) } * (stops.len() - 1) as f64;
+        let index = (position.floor() as usize).min(stops.len() - 2);
+        let fraction = position - index as f64;
+        let (from, to) = (stops[index], stops[index + 1]);
+        [16, 8, 0].into_iter().fold(0, |color, shift| {
+            let a = ((from >> shift) & 0xff) as f64;
+            let b = ((to >> shift) & 0xff) as f64;
+            let channel = (a + (b - a) * fraction).round() as u32;
+            color | (channel << shift)
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Colormap::Viridis => "viridis",
+            Colormap::Magma => "magma",
+            Colormap::Grayscale => "grayscale",
+        }
+    }
+
+    /// キー操作で順番に切り替える用
+    pub fn next(&self) -> Colormap {
+        let index = Colormap::VARIANTS.iter().position(|c| c == self).unwrap();
+        Colormap::VARIANTS[(index + 1) % Colormap::VARIANTS.len()]
+    }
+}
+
+impl FromStr for Colormap {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Colormap::VARIANTS
+            .into_iter()
+            .find(|colormap| colormap.name().eq_ignore_ascii_case(s))
+        {
+            Some(colormap) => Ok(colormap),
+            None => bail!("Unknown colormap: {s}"),
+        }
+    }
+}
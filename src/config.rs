@@ -0,0 +1,119 @@
+//! CLI の既定値を書き換える TOML の設定ファイル
+//!
+//! トップレベルのキーはそのオプションを持つ全部のサブコマンドに、`[record]` のような表の中のキーは
+//! そのサブコマンドだけに効く。キーはオプションの長い名前で、`fft-size` でも `fft_size` でもいい。
+//! 値は clap の既定値になるので、コマンドラインで指定したものが優先される。
+
+use std::{fs, path::Path, str::FromStr};
+
+use anyhow::{bail, ensure, Context as _, Error, Result};
+use clap::{Arg, Command, Id};
+use toml::{Table, Value};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    table: Table,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config: {}", path.display()))?;
+        text.parse()
+            .with_context(|| format!("Failed to parse config: {}", path.display()))
+    }
+
+    /// `command` とそのサブコマンドの既定値を書き換える。どのコマンドにも無いキーはエラーにする
+    pub fn apply(&self, mut command: Command) -> Result<Command> {
+        // 表の中の値が優先されるように、トップレベルのキーを先に当てる
+        for (name, value) in &self.table {
+            if value.is_table() {
+                continue;
+            }
+            let value = default_value(name, value)?;
+            let mut found = false;
+            if let Some(id) = find_arg(&command, name) {
+                command = command.mut_arg(id, |arg| with_default(arg, value.clone()));
+                found = true;
+            }
+            let targets = command
+                .get_subcommands()
+                .filter_map(|subcommand| {
+                    find_arg(subcommand, name).map(|id| (subcommand.get_name().to_string(), id))
+                })
+                .collect::<Vec<_>>();
+            for (subcommand, id) in targets {
+                command = command.mut_subcommand(subcommand, |subcommand| {
+                    subcommand.mut_arg(id, |arg| with_default(arg, value.clone()))
+                });
+                found = true;
+            }
+            ensure!(found, "Unknown option in config: {name}");
+        }
+
+        for (key, value) in &self.table {
+            let Value::Table(table) = value else {
+                continue;
+            };
+            let Some(subcommand) = command.find_subcommand(key) else {
+                bail!("Unknown subcommand in config: [{key}]");
+            };
+            let defaults = table
+                .iter()
+                .map(|(name, value)| match find_arg(subcommand, name) {
+                    Some(id) => Ok((id, default_value(name, value)?)),
+                    None => bail!("Unknown option in config: [{key}] {name}"),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            command = command.mut_subcommand(key, |mut subcommand| {
+                for (id, value) in defaults {
+                    subcommand = subcommand.mut_arg(id, |arg| with_default(arg, value));
+                }
+                subcommand
+            });
+        }
+        Ok(command)
+    }
+}
+
+impl FromStr for Config {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Config { table: s.parse()? })
+    }
+}
+
+/// 長い名前のあるオプションだけを対象にする
+fn find_arg(command: &Command, name: &str) -> Option<Id> {
+    let long = name.replace('_', "-");
+    command
+        .get_arguments()
+        .find(|arg| arg.get_long() == Some(long.as_str()))
+        .map(|arg| arg.get_id().clone())
+}
+
+/// 既定値があれば必須でなくてもいい
+fn with_default(arg: Arg, value: String) -> Arg {
+    arg.default_value(value).required(false)
+}
+
+fn default_value(name: &str, value: &Value) -> Result<String> {
+    Ok(match value {
+        Value::String(value) => value.clone(),
+        Value::Integer(value) => value.to_string(),
+        Value::Float(value) => value.to_string(),
+        Value::Boolean(value) => value.to_string(),
+        value => bail!("Unsupported value in config: {name} = {value}"),
+    })
+}
+
+/// `--frame-rate` のような 0 より大きい有限の数。設定ファイルの値も既定値としてここを通る
+pub fn positive_f64(s: &str) -> Result<f64> {
+    let value = s.parse::<f64>()?;
+    ensure!(
+        value.is_finite() && value > 0.0,
+        "Expected a positive finite number: {s}"
+    );
+    Ok(value)
+}
@@ -0,0 +1,359 @@
+//! cpal で取り込む。Linux では ALSA や PulseAudio の録音デバイスから取れて、
+//! 再生中の音は PulseAudio のモニターから取る
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, SyncSender, TryRecvError, TrySendError},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context as _, Error, Result};
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    FromSample, InputCallbackInfo, Sample, SampleFormat, SampleRate, SizedSample, Stream,
+    StreamConfig, StreamError, SupportedStreamConfig, SupportedStreamConfigRange,
+};
+
+use crate::{
+    device::{
+        default_data_flow, open_device, DataFlow, DeviceEnumerator, DeviceInfo, DeviceState, Role,
+    },
+    follow::Connector,
+    format::WaveFormatEx,
+    packet::{Packet, PacketInfo},
+    source::AudioSource,
+};
+
+/// cpal の既定のホストのデバイスの一覧。
+/// cpal のデバイスには ID が無いので、`capture:名前` のように向きと名前を ID にする
+pub struct CpalEnumerator {
+    host: cpal::Host,
+}
+
+impl CpalEnumerator {
+    pub fn new() -> Result<CpalEnumerator> {
+        Ok(CpalEnumerator {
+            host: cpal::default_host(),
+        })
+    }
+
+    fn endpoints(&self, data_flow: DataFlow) -> Result<Vec<cpal::Device>> {
+        let devices = match data_flow {
+            DataFlow::Render => self.host.output_devices()?.collect(),
+            DataFlow::Capture => self.host.input_devices()?.collect(),
+        };
+        Ok(devices)
+    }
+
+    fn default_endpoint(&self, data_flow: DataFlow) -> Option<cpal::Device> {
+        match data_flow {
+            DataFlow::Render => self.host.default_output_device(),
+            DataFlow::Capture => self.host.default_input_device(),
+        }
+    }
+}
+
+impl DeviceEnumerator for CpalEnumerator {
+    type Device = cpal::Device;
+
+    /// 再生、録音の順で並べる。cpal は使えるものしか返さないので全部 `Active` で、
+    /// 役割の区別も無いので既定のデバイスは全部の役割で既定にする
+    fn devices(&self) -> Result<Vec<DeviceInfo>> {
+        let mut devices = vec![];
+        for data_flow in DataFlow::VARIANTS {
+            let default = self
+                .default_endpoint(data_flow)
+                .and_then(|device| device.name().ok());
+            let endpoints = self
+                .endpoints(data_flow)
+                .context("Failed to enumerate audio endpoints.")?;
+            for device in endpoints {
+                // 名前が無いと開き直せない
+                let Ok(name) = device.name() else {
+                    continue;
+                };
+                let default_roles = if default.as_ref() == Some(&name) {
+                    Role::VARIANTS.to_vec()
+                } else {
+                    vec![]
+                };
+                devices.push(DeviceInfo {
+                    id: format!("{}:{name}", data_flow.name()),
+                    name,
+                    data_flow,
+                    state: DeviceState::Active,
+                    default_roles,
+                });
+            }
+        }
+        Ok(devices)
+    }
+
+    fn open(&self, id: &str) -> Result<cpal::Device> {
+        // ALSA の名前にも `:` が入るので最初の `:` で切る
+        let Some((data_flow, name)) = id.split_once(':') else {
+            bail!("Failed to open audio endpoint: {id}");
+        };
+        self.endpoints(data_flow.parse()?)?
+            .into_iter()
+            .find(|device| device.name().is_ok_and(|n| n == name))
+            .with_context(|| format!("Failed to open audio endpoint: {id}"))
+    }
+}
+
+/// ストリームのデバイスが外された
+#[derive(Debug)]
+pub struct EndpointLost;
+
+impl fmt::Display for EndpointLost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Audio endpoint is no longer available.")
+    }
+}
+
+impl std::error::Error for EndpointLost {}
+
+/// コールバックから読む側に渡すパケットを溜めておく数。溢れた分は捨てて、次に渡せたパケットに数える
+const CALLBACK_QUEUE: usize = 64;
+
+/// cpal の入力ストリームから取るソース。サンプルは何であれ 32bit float にして返す
+pub struct CpalSource {
+    wave_format: WaveFormatEx,
+    receiver: Receiver<Packet>,
+    /// `wait` で先に受け取ったパケット
+    pending: Option<Packet>,
+    /// デバイスが外された
+    lost: Arc<AtomicBool>,
+    /// drop すると止まる
+    _stream: Stream,
+}
+
+impl CpalSource {
+    /// 入力の既定の設定で開く。再生デバイスは cpal の WASAPI ならループバックで取れるが、
+    /// ALSA では開けないので PulseAudio のモニターを選ぶ
+    pub fn new(device: cpal::Device) -> Result<CpalSource> {
+        CpalSource::with_format(device, None)
+    }
+
+    /// `format` と同じチャンネル数とサンプリング周波数で開けるならそうする。無理なら既定の設定で開くので、
+    /// `wave_format` は `format` と違うことがある
+    pub fn with_format(device: cpal::Device, format: Option<&WaveFormatEx>) -> Result<CpalSource> {
+        let name = device.name().unwrap_or_default();
+        let supported = match format.and_then(|format| matching_config(&device, format)) {
+            Some(supported) => supported,
+            None => match device.default_input_config() {
+                Ok(supported) => supported,
+                Err(e) => device
+                    .default_output_config()
+                    .map_err(|_| e)
+                    .with_context(|| format!("Audio endpoint cannot be captured: {name}"))?,
+            },
+        };
+        let config = supported.config();
+        let wave_format = WaveFormatEx::ieee_float(config.channels, config.sample_rate.0);
+        if format.is_some_and(|format| *format != wave_format) {
+            log::info!("Opened {name} with its default config: {wave_format:?}");
+        }
+
+        let (sender, receiver) = mpsc::sync_channel(CALLBACK_QUEUE);
+        let lost = Arc::new(AtomicBool::new(false));
+        let stream = match supported.sample_format() {
+            SampleFormat::I8 => build_stream::<i8>(&device, &config, sender, lost.clone()),
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, sender, lost.clone()),
+            SampleFormat::I32 => build_stream::<i32>(&device, &config, sender, lost.clone()),
+            SampleFormat::I64 => build_stream::<i64>(&device, &config, sender, lost.clone()),
+            SampleFormat::U8 => build_stream::<u8>(&device, &config, sender, lost.clone()),
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, sender, lost.clone()),
+            SampleFormat::U32 => build_stream::<u32>(&device, &config, sender, lost.clone()),
+            SampleFormat::U64 => build_stream::<u64>(&device, &config, sender, lost.clone()),
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, sender, lost.clone()),
+            SampleFormat::F64 => build_stream::<f64>(&device, &config, sender, lost.clone()),
+            format => bail!("Unsupported sample format: {format}"),
+        }
+        .with_context(|| format!("Failed to open audio stream: {name}"))?;
+        stream.play().context("Failed to start audio stream.")?;
+
+        Ok(CpalSource {
+            wave_format,
+            receiver,
+            pending: None,
+            lost,
+            _stream: stream,
+        })
+    }
+}
+
+/// `format` とチャンネル数とサンプリング周波数が同じ設定
+fn matching_config(device: &cpal::Device, format: &WaveFormatEx) -> Option<SupportedStreamConfig> {
+    let matches = |range: &SupportedStreamConfigRange| {
+        range.channels() == format.channels
+            && (range.min_sample_rate().0..=range.max_sample_rate().0)
+                .contains(&format.samples_per_sec)
+    };
+    let range = device
+        .supported_input_configs()
+        .ok()
+        .and_then(|mut ranges| ranges.find(matches))
+        .or_else(|| {
+            device
+                .supported_output_configs()
+                .ok()
+                .and_then(|mut ranges| ranges.find(matches))
+        })?;
+    Some(range.with_sample_rate(SampleRate(format.samples_per_sec)))
+}
+
+/// `T` のサンプルを受け取って 32bit float のパケットにして `sender` に送るストリーム。
+/// 読む側が遅れて一杯なら待たずに捨てる
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    sender: SyncSender<Packet>,
+    lost: Arc<AtomicBool>,
+) -> Result<Stream>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let block_align = config.channels * 4;
+    // 捨てた後まだ渡せていない分
+    let mut dropped = PacketInfo::default();
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _: &InputCallbackInfo| {
+            let data = data
+                .iter()
+                .flat_map(|&sample| f32::from_sample(sample).to_le_bytes())
+                .collect();
+            let mut packet = Packet::new(data, block_align);
+            packet.info.dropped_packets = dropped.dropped_packets;
+            packet.info.dropped_frames = dropped.dropped_frames;
+            match sender.try_send(packet) {
+                Ok(()) => dropped = PacketInfo::default(),
+                Err(TrySendError::Full(packet)) => {
+                    dropped = PacketInfo::default();
+                    dropped.add_dropped(&packet.info);
+                }
+                // 読む側が居なくなったら捨てる
+                Err(TrySendError::Disconnected(_)) => {}
+            }
+        },
+        move |e| match e {
+            StreamError::DeviceNotAvailable => lost.store(true, Ordering::Relaxed),
+            e => log::warn!("Audio stream error: {e}"),
+        },
+        None,
+    )?;
+    Ok(stream)
+}
+
+impl AudioSource for CpalSource {
+    fn wave_format(&self) -> &WaveFormatEx {
+        &self.wave_format
+    }
+
+    fn get_buffer(&mut self) -> Result<Option<Vec<u8>>> {
+        Ok(self.get_packet()?.map(|packet| packet.data))
+    }
+
+    /// 取りこぼした分は `dropped_packets` と `dropped_frames` に入っている
+    fn get_packet(&mut self) -> Result<Option<Packet>> {
+        if let Some(packet) = self.pending.take() {
+            return Ok(Some(packet));
+        }
+        match self.receiver.try_recv() {
+            Ok(packet) => Ok(Some(packet)),
+            Err(TryRecvError::Empty) => {
+                if self.lost.load(Ordering::Relaxed) {
+                    return Err(EndpointLost.into());
+                }
+                Ok(None)
+            }
+            Err(TryRecvError::Disconnected) => bail!("Audio stream stopped."),
+        }
+    }
+
+    /// 次のパケットが来るまでチャンネルで待つ
+    fn wait(&mut self, timeout: Duration) -> Result<()> {
+        if self.pending.is_none() {
+            self.pending = self.receiver.recv_timeout(timeout).ok();
+        }
+        Ok(())
+    }
+}
+
+/// 既定のデバイスが変わっていないか確かめる間隔
+const DEFAULT_DEVICE_POLL: Duration = Duration::from_millis(500);
+
+/// `FollowSource` で cpal のデバイスを開き直す。`query` が既定のデバイスなら、
+/// 既定が変わったのを見つける度にそちらへ移る
+pub struct CpalConnector {
+    query: Option<String>,
+    /// 追いかける既定のデバイスの向き
+    follow: Option<DataFlow>,
+    enumerator: CpalEnumerator,
+    /// 開いているデバイスの ID と名前
+    id: Option<String>,
+    name: Option<String>,
+    checked_at: Instant,
+}
+
+impl CpalConnector {
+    /// `query` は `find_device` と同じ
+    pub fn new(query: Option<&str>) -> Result<CpalConnector> {
+        Ok(CpalConnector {
+            query: query.map(str::to_string),
+            follow: default_data_flow(query)?,
+            enumerator: CpalEnumerator::new()?,
+            id: None,
+            name: None,
+            checked_at: Instant::now(),
+        })
+    }
+}
+
+impl Connector for CpalConnector {
+    type Source = CpalSource;
+
+    fn connect(&mut self, format: Option<&WaveFormatEx>) -> Result<CpalSource> {
+        // 既定を追わないなら、番号が変わっても同じデバイスを開き直す
+        let query = match (&self.follow, &self.id) {
+            (None, Some(id)) => Some(id.as_str()),
+            _ => self.query.as_deref(),
+        };
+        let (info, device) = open_device(&self.enumerator, query)?;
+        let source = CpalSource::with_format(device, format)?;
+        self.name = Some(info.name);
+        self.id = Some(info.id);
+        self.checked_at = Instant::now();
+        Ok(source)
+    }
+
+    /// cpal には変更の通知が無いので、ときどき既定のデバイスの名前を見比べる
+    fn should_reconnect(&mut self) -> bool {
+        let Some(data_flow) = self.follow else {
+            return false;
+        };
+        if self.checked_at.elapsed() < DEFAULT_DEVICE_POLL {
+            return false;
+        }
+        self.checked_at = Instant::now();
+        // 既定のデバイスが無くなった時は、読む方で外れたのが分かる
+        match self
+            .enumerator
+            .default_endpoint(data_flow)
+            .and_then(|device| device.name().ok())
+        {
+            Some(name) => self.name.as_ref() != Some(&name),
+            None => false,
+        }
+    }
+
+    fn is_device_lost(&self, error: &Error) -> bool {
+        error.is::<EndpointLost>()
+    }
+}
@@ -0,0 +1,77 @@
+//! キャプチャしたバイト列をチャンネルごとの f32 に直す
+
+use anyhow::{ensure, Result};
+
+use crate::format::{SampleFormat, WaveFormatEx};
+
+/// `WaveFormatEx` に従ってインターリーブされたフレームを -1.0..1.0 に正規化して分離する
+#[derive(Clone, Debug)]
+pub struct Decoder {
+    sample_format: SampleFormat,
+    channels: usize,
+    block_align: usize,
+}
+
+impl Decoder {
+    pub fn new(wave_format: &WaveFormatEx) -> Result<Decoder> {
+        let sample_format = wave_format.sample_format()?;
+        let channels = wave_format.channels as usize;
+        let block_align = wave_format.block_align as usize;
+        ensure!(channels > 0, "No channels.");
+        ensure!(
+            block_align >= channels * sample_format.bytes(),
+            "Block align {block_align} is too small for {channels} channels of {sample_format:?}."
+        );
+        Ok(Decoder {
+            sample_format,
+            channels,
+            block_align,
+        })
+    }
+
+    pub fn sample_format(&self) -> SampleFormat {
+        self.sample_format
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// 端数のフレームは捨てる
+    pub fn decode(&self, bytes: &[u8]) -> Vec<Vec<f32>> {
+        let mut channels = vec![Vec::with_capacity(bytes.len() / self.block_align); self.channels];
+        self.decode_into(bytes, &mut channels);
+        channels
+    }
+
+    /// チャンネルごとのバッファに追記する
+    pub fn decode_into(&self, bytes: &[u8], channels: &mut [Vec<f32>]) {
+        let width = self.sample_format.bytes();
+        for frame in bytes.chunks_exact(self.block_align) {
+            for (channel, samples) in channels.iter_mut().enumerate().take(self.channels) {
+                let offset = channel * width;
+                samples.push(self.decode_sample(&frame[offset..offset + width]));
+            }
+        }
+    }
+
+    fn decode_sample(&self, bytes: &[u8]) -> f32 {
+        match self.sample_format {
+            SampleFormat::U8 => (bytes[0] as f32 - 128.0) / 128.0,
+            SampleFormat::I16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32_768.0,
+            SampleFormat::I24 => {
+                // 上位に詰めてから算術シフトで符号拡張する
+                let value = i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8;
+                value as f32 / 8_388_608.0
+            }
+            SampleFormat::I32 => {
+                let value = i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                (value as f64 / 2_147_483_648.0) as f32
+            }
+            SampleFormat::F32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            SampleFormat::F64 => f64::from_le_bytes([
+                bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+            ]) as f32,
+        }
+    }
+}
@@ -0,0 +1,335 @@
+//! デバイスの一覧と選び方。COM には触らないので、偽の一覧を渡して試せる
+
+use std::{io::Write, str::FromStr};
+
+use anyhow::{bail, ensure, Context as _, Error, Result};
+use serde::Serialize;
+
+/// 再生か録音か
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DataFlow {
+    Render,
+    Capture,
+}
+
+impl DataFlow {
+    pub const VARIANTS: [DataFlow; 2] = [DataFlow::Render, DataFlow::Capture];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DataFlow::Render => "render",
+            DataFlow::Capture => "capture",
+        }
+    }
+}
+
+impl FromStr for DataFlow {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match DataFlow::VARIANTS
+            .into_iter()
+            .find(|flow| flow.name().eq_ignore_ascii_case(s))
+        {
+            Some(flow) => Ok(flow),
+            None => bail!("Unknown data flow: {s}"),
+        }
+    }
+}
+
+/// どうやって音を取るか
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureMode {
+    /// 再生デバイスに流れている音をループバックで取る
+    Loopback,
+    /// マイクやライン入力などの録音デバイスから直接取る
+    Direct,
+}
+
+impl CaptureMode {
+    pub const VARIANTS: [CaptureMode; 2] = [CaptureMode::Loopback, CaptureMode::Direct];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CaptureMode::Loopback => "loopback",
+            CaptureMode::Direct => "direct",
+        }
+    }
+
+    /// 再生デバイスならループバック、録音デバイスなら直接取る
+    pub fn for_data_flow(data_flow: DataFlow) -> CaptureMode {
+        match data_flow {
+            DataFlow::Render => CaptureMode::Loopback,
+            DataFlow::Capture => CaptureMode::Direct,
+        }
+    }
+
+    /// このモードで開けるデバイスの向き
+    pub fn data_flow(&self) -> DataFlow {
+        match self {
+            CaptureMode::Loopback => DataFlow::Render,
+            CaptureMode::Direct => DataFlow::Capture,
+        }
+    }
+}
+
+/// 使えるかどうか。`Active` 以外は開けない
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeviceState {
+    Active,
+    Disabled,
+    NotPresent,
+    Unplugged,
+}
+
+impl DeviceState {
+    pub const VARIANTS: [DeviceState; 4] = [
+        DeviceState::Active,
+        DeviceState::Disabled,
+        DeviceState::NotPresent,
+        DeviceState::Unplugged,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DeviceState::Active => "active",
+            DeviceState::Disabled => "disabled",
+            DeviceState::NotPresent => "not-present",
+            DeviceState::Unplugged => "unplugged",
+        }
+    }
+}
+
+/// 既定のデバイスの役割
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Role {
+    Console,
+    Multimedia,
+    Communications,
+}
+
+impl Role {
+    pub const VARIANTS: [Role; 3] = [Role::Console, Role::Multimedia, Role::Communications];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Console => "console",
+            Role::Multimedia => "multimedia",
+            Role::Communications => "communications",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DeviceInfo {
+    pub id: String,
+    pub name: String,
+    pub data_flow: DataFlow,
+    pub state: DeviceState,
+    /// このデバイスが既定になっている役割
+    pub default_roles: Vec<Role>,
+}
+
+impl DeviceInfo {
+    pub fn is_default(&self, role: Role) -> bool {
+        self.default_roles.contains(&role)
+    }
+}
+
+/// デバイスを列挙して開くもの。WASAPI の実装は `wasapi::WasapiEnumerator`、
+/// cpal の実装は `cpal_source::CpalEnumerator`
+pub trait DeviceEnumerator {
+    type Device;
+
+    /// 全部のデバイス。この並びが `select_device` の番号になる
+    fn devices(&self) -> Result<Vec<DeviceInfo>>;
+
+    fn open(&self, id: &str) -> Result<Self::Device>;
+}
+
+/// `query` に合うデバイスを選ぶ。ID が一致するもの、`devices` の番号、名前の一部の順に試す。
+/// 名前で複数見つかったら有効なもの、名前が完全に一致するものに絞る
+pub fn select_device<'a>(devices: &'a [DeviceInfo], query: &str) -> Result<&'a DeviceInfo> {
+    if let Some(device) = devices.iter().find(|device| device.id == query) {
+        return Ok(device);
+    }
+    if let Ok(index) = query.parse::<usize>() {
+        return devices
+            .get(index)
+            .with_context(|| format!("No audio endpoint at index {index}"));
+    }
+
+    let lower = query.to_lowercase();
+    let mut candidates = devices
+        .iter()
+        .filter(|device| device.name.to_lowercase().contains(&lower))
+        .collect::<Vec<_>>();
+    if candidates.len() > 1 && candidates.iter().any(|d| d.state == DeviceState::Active) {
+        candidates.retain(|device| device.state == DeviceState::Active);
+    }
+    if candidates.len() > 1 && candidates.iter().any(|d| d.name.to_lowercase() == lower) {
+        candidates.retain(|device| device.name.to_lowercase() == lower);
+    }
+    match candidates.as_slice() {
+        [] => bail!("No audio endpoint matches: {query}"),
+        [device] => Ok(device),
+        _ => {
+            let names = candidates
+                .iter()
+                .map(|device| device.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            bail!("Ambiguous audio endpoint: {query} ({names})")
+        }
+    }
+}
+
+/// `data_flow` の既定のデバイス
+pub fn default_device(devices: &[DeviceInfo], data_flow: DataFlow) -> Result<&DeviceInfo> {
+    devices
+        .iter()
+        .find(|device| device.data_flow == data_flow && device.is_default(Role::Console))
+        .with_context(|| format!("No default {} endpoint", data_flow.name()))
+}
+
+/// `query` が既定のデバイスを指していればその向き。`None` か `default` なら再生、
+/// `default:capture` なら録音
+pub fn default_data_flow(query: Option<&str>) -> Result<Option<DataFlow>> {
+    let Some(query) = query else {
+        return Ok(Some(DataFlow::Render));
+    };
+    if query.eq_ignore_ascii_case("default") {
+        return Ok(Some(DataFlow::Render));
+    }
+    match query.split_once(':') {
+        Some((prefix, data_flow)) if prefix.eq_ignore_ascii_case("default") => {
+            Ok(Some(data_flow.parse()?))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// `query` で選んだ有効なデバイス。`None` か `default` なら既定の再生デバイス、
+/// `default:capture` なら既定の録音デバイス
+pub fn find_device<E: DeviceEnumerator>(enumerator: &E, query: Option<&str>) -> Result<DeviceInfo> {
+    let devices = enumerator.devices()?;
+    let info = match default_data_flow(query)? {
+        Some(data_flow) => default_device(&devices, data_flow)?,
+        None => select_device(&devices, query.unwrap_or_default())?,
+    };
+    ensure!(
+        info.state == DeviceState::Active,
+        "Audio endpoint is {}: {}",
+        info.state.name(),
+        info.name
+    );
+    Ok(info.clone())
+}
+
+/// `find_device` で選んだデバイスを開く
+pub fn open_device<E: DeviceEnumerator>(
+    enumerator: &E,
+    query: Option<&str>,
+) -> Result<(DeviceInfo, E::Device)> {
+    let info = find_device(enumerator, query)?;
+    let device = enumerator.open(&info.id)?;
+    Ok((info, device))
+}
+
+/// デバイスの一覧の書き出し方
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ListFormat {
+    /// 人が読む表
+    #[default]
+    Table,
+    /// JSON の配列
+    Json,
+}
+
+impl ListFormat {
+    pub const VARIANTS: [ListFormat; 2] = [ListFormat::Table, ListFormat::Json];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ListFormat::Table => "table",
+            ListFormat::Json => "json",
+        }
+    }
+}
+
+impl FromStr for ListFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match ListFormat::VARIANTS
+            .into_iter()
+            .find(|format| format.name().eq_ignore_ascii_case(s))
+        {
+            Some(format) => Ok(format),
+            None => bail!("Unknown list format: {s}"),
+        }
+    }
+}
+
+/// JSON の一覧の 1 台分。`devices` の番号を付ける
+#[derive(Serialize)]
+struct JsonEntry<'a> {
+    index: usize,
+    #[serde(flatten)]
+    device: &'a DeviceInfo,
+}
+
+/// `devices` を番号付きで書き出す
+pub fn write_devices(
+    mut writer: impl Write,
+    devices: &[DeviceInfo],
+    format: ListFormat,
+) -> Result<()> {
+    match format {
+        ListFormat::Table => {
+            writeln!(
+                writer,
+                "{:>3}  {:<8} {:<12} {:<24} {:<40} ID",
+                "#", "FLOW", "STATE", "DEFAULT", "NAME"
+            )?;
+            for (index, device) in devices.iter().enumerate() {
+                let roles = device
+                    .default_roles
+                    .iter()
+                    .map(Role::name)
+                    .collect::<Vec<_>>()
+                    .join(",");
+                writeln!(
+                    writer,
+                    "{index:>3}  {:<8} {:<12} {:<24} {:<40} {}",
+                    device.data_flow.name(),
+                    device.state.name(),
+                    if roles.is_empty() { "-" } else { &roles },
+                    device.name,
+                    device.id
+                )?;
+            }
+        }
+        ListFormat::Json => {
+            // 1 行に 1 台ずつ並べる
+            write!(writer, "[")?;
+            for (index, device) in devices.iter().enumerate() {
+                let entry = serde_json::to_string(&JsonEntry { index, device })?;
+                write!(
+                    writer,
+                    "{}{entry}",
+                    if index == 0 { "\n  " } else { ",\n  " }
+                )?;
+            }
+            if !devices.is_empty() {
+                writeln!(writer)?;
+            }
+            writeln!(writer, "]")?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,75 @@
+//! 実数入力の FFT。spectrum-analyzer (microfft) は 16384 点までなのでそれ以上も扱えるように自前で持つ
+
+use std::f64::consts::TAU;
+
+use anyhow::{ensure, Result};
+
+pub const MIN_SIZE: usize = 256;
+pub const MAX_SIZE: usize = 65536;
+
+/// サイズ毎の回転因子とビット反転の表を持っておく基数 2 の FFT
+#[derive(Clone, Debug)]
+pub struct Fft {
+    size: usize,
+    twiddles: Vec<(f64, f64)>,
+    bit_reversed: Vec<usize>,
+}
+
+impl Fft {
+    pub fn new(size: usize) -> Result<Fft> {
+        ensure!(
+            size.is_power_of_two() && (MIN_SIZE..=MAX_SIZE).contains(&size),
+            "FFT size must be a power of two between {MIN_SIZE} and {MAX_SIZE}: {size}"
+        );
+        let twiddles = (0..size / 2)
+            .map(|k| {
+                let angle = -TAU * k as f64 / size as f64;
+                (angle.cos(), angle.sin())
+            })
+            .collect();
+        let bits = size.trailing_zeros();
+        let bit_reversed = (0..size)
+            .map(|i| i.reverse_bits() >> (usize::BITS - bits))
+            .collect();
+        Ok(Fft {
+            size,
+            twiddles,
+            bit_reversed,
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// 0Hz からナイキスト周波数まで `size / 2 + 1` 個の |X[k]|
+    pub fn magnitudes(&self, samples: &[f32]) -> Vec<f64> {
+        assert_eq!(samples.len(), self.size, "Wrong number of samples.");
+        let mut re = vec![0.0; self.size];
+        let mut im = vec![0.0; self.size];
+        for (i, &j) in self.bit_reversed.iter().enumerate() {
+            re[j] = samples[i] as f64;
+        }
+
+        let mut len = 2;
+        while len <= self.size {
+            let half = len / 2;
+            let stride = self.size / len;
+            for start in (0..self.size).step_by(len) {
+                for k in 0..half {
+                    let (w_re, w_im) = self.twiddles[k * stride];
+                    let (a, b) = (start + k, start + k + half);
+                    let t_re = re[b] * w_re - im[b] * w_im;
+                    let t_im = re[b] * w_im + im[b] * w_re;
+                    re[b] = re[a] - t_re;
+                    im[b] = im[a] - t_im;
+                    re[a] += t_re;
+                    im[a] += t_im;
+                }
+            }
+            len *= 2;
+        }
+
+        (0..=self.size / 2).map(|k| re[k].hypot(im[k])).collect()
+    }
+}
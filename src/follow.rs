@@ -0,0 +1,254 @@
+//! デバイスが変わったり無くなったりしても、開き直して読み続けるソース
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Error, Result};
+
+use crate::{
+    format::{SampleFormat, WaveFormatEx},
+    packet::{Packet, PacketInfo},
+    source::AudioSource,
+};
+
+/// 開き直すのに失敗した後、次に試すまでの既定の間隔
+const RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 途切れていた間を埋める無音を一度に返す長さ
+const SILENCE_CHUNK: Duration = Duration::from_millis(100);
+
+/// `FollowSource` がデバイスを開き直すためのもの
+pub trait Connector {
+    type Source: AudioSource;
+
+    /// 読むべきデバイスを開く。開き直す時は `format` に前のフォーマットが来るので、できれば合わせる。
+    /// 合わせられなければ違うフォーマットで開いてよく、`FollowSource` が最初のパケットの `format` で知らせる
+    fn connect(&mut self, format: Option<&WaveFormatEx>) -> Result<Self::Source>;
+
+    /// 開いているものとは別のデバイスに移るべきなら `true`。既定のデバイスが変わった時など
+    fn should_reconnect(&mut self) -> bool {
+        false
+    }
+
+    /// `error` はデバイスが無くなったせいで、開き直せば続けられる
+    fn is_device_lost(&self, error: &Error) -> bool;
+}
+
+/// `FollowSource` の状態
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FollowState {
+    Connected,
+    /// デバイスが無いので開き直すのを待っている。`attempts` 回失敗した
+    Reconnecting {
+        attempts: u32,
+    },
+}
+
+/// `Connector` で開いたソースを読み、デバイスが無くなったら開き直して続けるソース。
+/// 途切れていた間は無音で埋め、開き直して最初のパケットに `reconnected` を付ける。
+/// フォーマットが変わっていたら、そのパケットの `format` に新しいフォーマットを入れる
+pub struct FollowSource<C: Connector> {
+    connector: C,
+    source: Option<C::Source>,
+    wave_format: WaveFormatEx,
+    state: FollowState,
+    retry_interval: Duration,
+    fill_silence: bool,
+    /// 途切れ始めた時刻
+    lost_at: Option<Instant>,
+    next_attempt: Instant,
+    /// まだ返していない無音のフレーム数
+    silence: u64,
+    /// 次に返すパケットに `reconnected` を付ける
+    mark_next: bool,
+    /// 次に返すパケットに付ける新しいフォーマット
+    changed_format: Option<WaveFormatEx>,
+    reconnects: u64,
+}
+
+impl<C: Connector> FollowSource<C> {
+    /// 最初の接続はここで行い、失敗したらそのまま返す
+    pub fn new(mut connector: C) -> Result<FollowSource<C>> {
+        let source = connector.connect(None)?;
+        Ok(FollowSource {
+            wave_format: source.wave_format().clone(),
+            connector,
+            source: Some(source),
+            state: FollowState::Connected,
+            retry_interval: RETRY_INTERVAL,
+            fill_silence: true,
+            lost_at: None,
+            next_attempt: Instant::now(),
+            silence: 0,
+            mark_next: false,
+            changed_format: None,
+            reconnects: 0,
+        })
+    }
+
+    /// 開き直すのに失敗した後、次に試すまでの間隔
+    pub fn with_retry_interval(mut self, interval: Duration) -> FollowSource<C> {
+        self.retry_interval = interval;
+        self
+    }
+
+    /// 途切れていた間を無音で埋めるか。既定では埋めるので、録音の長さが実際の時間と揃う
+    pub fn with_fill_silence(mut self, fill_silence: bool) -> FollowSource<C> {
+        self.fill_silence = fill_silence;
+        self
+    }
+
+    pub fn state(&self) -> FollowState {
+        self.state
+    }
+
+    pub fn connector(&self) -> &C {
+        &self.connector
+    }
+
+    /// 開き直した回数
+    pub fn reconnects(&self) -> u64 {
+        self.reconnects
+    }
+
+    fn disconnect(&mut self) {
+        self.source = None;
+        self.state = FollowState::Reconnecting { attempts: 0 };
+        self.lost_at = Some(Instant::now());
+        self.next_attempt = Instant::now();
+    }
+
+    /// 試す時間になっていれば開き直す。開けたら `true`
+    fn reconnect(&mut self) -> Result<bool> {
+        let FollowState::Reconnecting { attempts } = self.state else {
+            return Ok(true);
+        };
+        let now = Instant::now();
+        if now < self.next_attempt {
+            return Ok(false);
+        }
+        match self.connector.connect(Some(&self.wave_format)) {
+            Ok(source) => {
+                // 読む側が作り直せるように、変わったフォーマットは次のパケットで知らせる。
+                // 無音も新しいフォーマットで埋める
+                if *source.wave_format() != self.wave_format {
+                    log::warn!(
+                        "Audio format changed after reconnecting: {:?}",
+                        source.wave_format()
+                    );
+                    self.wave_format = source.wave_format().clone();
+                    self.changed_format = Some(self.wave_format.clone());
+                }
+                if self.fill_silence {
+                    let lost = self.lost_at.map(|at| now - at).unwrap_or_default();
+                    self.silence =
+                        (lost.as_secs_f64() * self.wave_format.samples_per_sec as f64) as u64;
+                }
+                log::info!(
+                    "Reconnected to audio endpoint after {} attempts.",
+                    attempts + 1
+                );
+                self.source = Some(source);
+                self.state = FollowState::Connected;
+                self.lost_at = None;
+                self.mark_next = true;
+                self.reconnects += 1;
+                Ok(true)
+            }
+            Err(e) => {
+                log::debug!("Failed to reconnect to audio endpoint: {e:#}");
+                self.state = FollowState::Reconnecting {
+                    attempts: attempts + 1,
+                };
+                self.next_attempt = now + self.retry_interval;
+                Ok(false)
+            }
+        }
+    }
+
+    /// 途切れていた間を埋める無音を少しずつ返す
+    fn next_silence(&mut self) -> Packet {
+        let chunk = (SILENCE_CHUNK.as_secs_f64() * self.wave_format.samples_per_sec as f64) as u64;
+        let frames = self.silence.min(chunk.max(1));
+        self.silence -= frames;
+        // 8bit PCM は符号無しなので無音は 0x80
+        let silence = match self.wave_format.sample_format() {
+            Ok(SampleFormat::U8) => 0x80,
+            _ => 0,
+        };
+        self.mark(Packet {
+            data: vec![silence; frames as usize * self.wave_format.block_align as usize],
+            info: PacketInfo {
+                frames,
+                silent: true,
+                ..PacketInfo::default()
+            },
+            format: None,
+        })
+    }
+
+    fn mark(&mut self, mut packet: Packet) -> Packet {
+        if self.mark_next {
+            packet.info.reconnected = true;
+            packet.format = self.changed_format.take();
+            self.mark_next = false;
+        }
+        packet
+    }
+}
+
+impl<C: Connector> AudioSource for FollowSource<C> {
+    fn wave_format(&self) -> &WaveFormatEx {
+        &self.wave_format
+    }
+
+    fn get_buffer(&mut self) -> Result<Option<Vec<u8>>> {
+        Ok(self.get_packet()?.map(|packet| packet.data))
+    }
+
+    fn get_packet(&mut self) -> Result<Option<Packet>> {
+        if self.source.is_some() && self.connector.should_reconnect() {
+            log::info!("Audio endpoint changed. Reconnecting.");
+            self.disconnect();
+        }
+        if !self.reconnect()? {
+            return Ok(None);
+        }
+        if self.silence > 0 {
+            return Ok(Some(self.next_silence()));
+        }
+        let Some(source) = &mut self.source else {
+            return Ok(None);
+        };
+        match source.get_packet() {
+            Ok(packet) => Ok(packet.map(|packet| self.mark(packet))),
+            Err(e) if self.connector.is_device_lost(&e) => {
+                log::warn!("Audio endpoint lost: {e:#}");
+                self.disconnect();
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.silence == 0 && self.source.as_ref().is_some_and(|s| s.is_finished())
+    }
+
+    /// 繋がっていればソースで待ち、繋がっていなければ次に開き直すまで眠る
+    fn wait(&mut self, timeout: Duration) -> Result<()> {
+        if self.silence > 0 {
+            return Ok(());
+        }
+        match &mut self.source {
+            Some(source) => source.wait(timeout),
+            None => {
+                let remaining = self.next_attempt.saturating_duration_since(Instant::now());
+                thread::sleep(remaining.min(timeout));
+                Ok(())
+            }
+        }
+    }
+}
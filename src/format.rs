@@ -0,0 +1,302 @@
+//! `WAVEFORMATEX` 相当のフォーマット情報
+
+use std::fmt;
+
+use anyhow::{bail, ensure, Result};
+
+pub const WAVE_FORMAT_PCM: u16 = 0x0001;
+pub const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+pub const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// `dwChannelMask` のスピーカーの位置。チャンネルはビットの低い順に並ぶ
+pub const SPEAKER_FRONT_LEFT: u32 = 0x1;
+pub const SPEAKER_FRONT_RIGHT: u32 = 0x2;
+pub const SPEAKER_FRONT_CENTER: u32 = 0x4;
+pub const SPEAKER_LOW_FREQUENCY: u32 = 0x8;
+pub const SPEAKER_BACK_LEFT: u32 = 0x10;
+pub const SPEAKER_BACK_RIGHT: u32 = 0x20;
+pub const SPEAKER_BACK_CENTER: u32 = 0x100;
+pub const SPEAKER_SIDE_LEFT: u32 = 0x200;
+pub const SPEAKER_SIDE_RIGHT: u32 = 0x400;
+
+/// Windows の `GUID` を u128 に詰めたもの (`GUID::to_u128` と同じ並び)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Guid(pub u128);
+
+impl Guid {
+    /// `KSDATAFORMAT_SUBTYPE_*` の共通部分
+    const SUBTYPE_BASE: u128 = 0x0000_0000_0000_0010_8000_00aa_0038_9b71;
+
+    pub const KSDATAFORMAT_SUBTYPE_PCM: Guid = Guid::from_format_tag(WAVE_FORMAT_PCM);
+    pub const KSDATAFORMAT_SUBTYPE_IEEE_FLOAT: Guid = Guid::from_format_tag(WAVE_FORMAT_IEEE_FLOAT);
+
+    pub const fn from_format_tag(format_tag: u16) -> Guid {
+        Guid(((format_tag as u128) << 96) | Guid::SUBTYPE_BASE)
+    }
+
+    /// `KSDATAFORMAT_SUBTYPE_*` であれば対応する `wFormatTag`
+    pub fn format_tag(&self) -> Option<u16> {
+        if self.0 & ((1 << 96) - 1) != Guid::SUBTYPE_BASE {
+            return None;
+        }
+        u16::try_from(self.0 >> 96).ok()
+    }
+
+    /// メモリ上の `GUID` と同じ並び (Data1..3 はリトルエンディアン)
+    pub fn from_bytes(bytes: [u8; 16]) -> Guid {
+        let data1 = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as u128;
+        let data2 = u16::from_le_bytes([bytes[4], bytes[5]]) as u128;
+        let data3 = u16::from_le_bytes([bytes[6], bytes[7]]) as u128;
+        let mut data4 = [0; 8];
+        data4.copy_from_slice(&bytes[8..]);
+        let data4 = u64::from_be_bytes(data4) as u128;
+        Guid(data1 << 96 | data2 << 80 | data3 << 64 | data4)
+    }
+
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let mut bytes = [0; 16];
+        bytes[0..4].copy_from_slice(&((self.0 >> 96) as u32).to_le_bytes());
+        bytes[4..6].copy_from_slice(&((self.0 >> 80) as u16).to_le_bytes());
+        bytes[6..8].copy_from_slice(&((self.0 >> 64) as u16).to_le_bytes());
+        bytes[8..].copy_from_slice(&(self.0 as u64).to_be_bytes());
+        bytes
+    }
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{{{:08X}-{:04X}-{:04X}-{:04X}-{:012X}}}",
+            (self.0 >> 96) as u32,
+            (self.0 >> 80) as u16,
+            (self.0 >> 64) as u16,
+            (self.0 >> 48) as u16,
+            self.0 & 0xFFFF_FFFF_FFFF,
+        )
+    }
+}
+
+/// `WAVEFORMATEXTENSIBLE` で `WAVEFORMATEX` の後ろに付いてくる部分
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WaveFormatExtensible {
+    pub valid_bits_per_sample: u16,
+    pub channel_mask: u32,
+    pub sub_format: Guid,
+}
+
+impl WaveFormatExtensible {
+    /// `cbSize` に入る拡張部分のバイト数
+    pub const SIZE: u16 = 22;
+
+    /// チャンネル数に対する `KSAUDIO_SPEAKER_*` の既定の並び
+    pub fn default_channel_mask(channels: u16) -> u32 {
+        match channels {
+            1 => 0x4,   // MONO: FRONT_CENTER
+            2 => 0x3,   // STEREO
+            4 => 0x33,  // QUAD
+            6 => 0x3F,  // 5.1
+            8 => 0x63F, // 7.1 SURROUND
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WaveFormatEx {
+    pub format_tag: u16,
+    pub channels: u16,
+    pub samples_per_sec: u32,
+    pub avg_bytes_per_sec: u32,
+    pub block_align: u16,
+    pub bits_per_sample: u16,
+    pub size: u16,
+    pub extensible: Option<WaveFormatExtensible>,
+}
+
+impl WaveFormatEx {
+    pub fn ieee_float(channels: u16, samples_per_sec: u32) -> WaveFormatEx {
+        let block_align = channels * 4;
+        WaveFormatEx {
+            format_tag: WAVE_FORMAT_IEEE_FLOAT,
+            channels,
+            samples_per_sec,
+            avg_bytes_per_sec: samples_per_sec * block_align as u32,
+            block_align,
+            bits_per_sample: 32,
+            size: 0,
+            extensible: None,
+        }
+    }
+
+    pub fn pcm(channels: u16, samples_per_sec: u32, bits_per_sample: u16) -> WaveFormatEx {
+        let block_align = channels * bits_per_sample.div_ceil(8);
+        WaveFormatEx {
+            format_tag: WAVE_FORMAT_PCM,
+            channels,
+            samples_per_sec,
+            avg_bytes_per_sec: samples_per_sec * block_align as u32,
+            block_align,
+            bits_per_sample,
+            size: 0,
+            extensible: None,
+        }
+    }
+
+    /// 拡張形式ならその `dwChannelMask`、そうでなければチャンネル数に対する既定の並び
+    pub fn channel_mask(&self) -> u32 {
+        match &self.extensible {
+            Some(extensible) if extensible.channel_mask != 0 => extensible.channel_mask,
+            _ => WaveFormatExtensible::default_channel_mask(self.channels),
+        }
+    }
+
+    /// 3ch 以上や 16bit を超える PCM は `WAVE_FORMAT_EXTENSIBLE` で表すべきとされている
+    pub fn requires_extensible(&self) -> bool {
+        self.extensible.is_none()
+            && (self.channels > 2
+                || (self.format_tag == WAVE_FORMAT_PCM && self.bits_per_sample > 16))
+    }
+
+    /// `WAVE_FORMAT_EXTENSIBLE` に包み直す。既に拡張形式ならそのまま
+    pub fn to_extensible(&self) -> WaveFormatEx {
+        if self.extensible.is_some() {
+            return self.clone();
+        }
+        WaveFormatEx {
+            format_tag: WAVE_FORMAT_EXTENSIBLE,
+            size: WaveFormatExtensible::SIZE,
+            extensible: Some(WaveFormatExtensible {
+                valid_bits_per_sample: self.bits_per_sample,
+                channel_mask: WaveFormatExtensible::default_channel_mask(self.channels),
+                sub_format: Guid::from_format_tag(self.format_tag),
+            }),
+            ..self.clone()
+        }
+    }
+
+    /// `WAVEFORMATEX` (拡張部分含む) もしくは WAV の `fmt ` チャンクの中身から読む。
+    /// `cbSize` の無い 16 バイトの `PCMWAVEFORMAT` も受け付ける
+    pub fn from_bytes(bytes: &[u8]) -> Result<WaveFormatEx> {
+        ensure!(
+            bytes.len() >= 16,
+            "WAVEFORMATEX is too short ({} bytes).",
+            bytes.len()
+        );
+        let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        let u32_at =
+            |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        let size = if bytes.len() >= 18 { u16_at(16) } else { 0 };
+        ensure!(
+            bytes.len() == 16 || bytes.len() >= 18 + size as usize,
+            "cbSize is {size} but only {} bytes follow.",
+            bytes.len().saturating_sub(18)
+        );
+        let format_tag = u16_at(0);
+        let extensible = if format_tag == WAVE_FORMAT_EXTENSIBLE {
+            ensure!(
+                size >= WaveFormatExtensible::SIZE,
+                "cbSize {size} is too small for WAVE_FORMAT_EXTENSIBLE."
+            );
+            let mut sub_format = [0; 16];
+            sub_format.copy_from_slice(&bytes[24..40]);
+            Some(WaveFormatExtensible {
+                valid_bits_per_sample: u16_at(18),
+                channel_mask: u32_at(20),
+                sub_format: Guid::from_bytes(sub_format),
+            })
+        } else {
+            None
+        };
+        Ok(WaveFormatEx {
+            format_tag,
+            channels: u16_at(2),
+            samples_per_sec: u32_at(4),
+            avg_bytes_per_sec: u32_at(8),
+            block_align: u16_at(12),
+            bits_per_sample: u16_at(14),
+            size,
+            extensible,
+        })
+    }
+
+    /// `from_bytes` の逆。拡張形式でない `cbSize` 分の追加データは 0 で埋める。
+    /// 拡張部分があれば `cbSize` が足りなくても切らずに、拡張部分が入るだけの `cbSize` にする
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let size = match &self.extensible {
+            Some(_) => self.size.max(WaveFormatExtensible::SIZE),
+            None => self.size,
+        };
+        let mut bytes = Vec::with_capacity(18 + size as usize);
+        bytes.extend(self.format_tag.to_le_bytes());
+        bytes.extend(self.channels.to_le_bytes());
+        bytes.extend(self.samples_per_sec.to_le_bytes());
+        bytes.extend(self.avg_bytes_per_sec.to_le_bytes());
+        bytes.extend(self.block_align.to_le_bytes());
+        bytes.extend(self.bits_per_sample.to_le_bytes());
+        bytes.extend(size.to_le_bytes());
+        if let Some(extensible) = &self.extensible {
+            bytes.extend(extensible.valid_bits_per_sample.to_le_bytes());
+            bytes.extend(extensible.channel_mask.to_le_bytes());
+            bytes.extend(extensible.sub_format.to_bytes());
+        }
+        bytes.resize(18 + size as usize, 0);
+        bytes
+    }
+
+    /// 拡張形式なら SubFormat を見た上での実際のフォーマットタグ
+    pub fn effective_format_tag(&self) -> Option<u16> {
+        match &self.extensible {
+            Some(extensible) if self.format_tag == WAVE_FORMAT_EXTENSIBLE => {
+                extensible.sub_format.format_tag()
+            }
+            _ => Some(self.format_tag),
+        }
+    }
+
+    pub fn sample_format(&self) -> Result<SampleFormat> {
+        SampleFormat::from_wave_format(self)
+    }
+}
+
+/// 1 サンプルのコンテナ型
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleFormat {
+    U8,
+    I16,
+    I24,
+    I32,
+    F32,
+    F64,
+}
+
+impl SampleFormat {
+    pub fn from_wave_format(format: &WaveFormatEx) -> Result<SampleFormat> {
+        let Some(format_tag) = format.effective_format_tag() else {
+            bail!("Unsupported SubFormat of WAVE_FORMAT_EXTENSIBLE.");
+        };
+        // 有効ビット数が小さくてもコンテナは左詰めなので bits_per_sample だけ見れば良い
+        let sample_format = match (format_tag, format.bits_per_sample) {
+            (WAVE_FORMAT_PCM, 8) => SampleFormat::U8,
+            (WAVE_FORMAT_PCM, 16) => SampleFormat::I16,
+            (WAVE_FORMAT_PCM, 24) => SampleFormat::I24,
+            (WAVE_FORMAT_PCM, 32) => SampleFormat::I32,
+            (WAVE_FORMAT_IEEE_FLOAT, 32) => SampleFormat::F32,
+            (WAVE_FORMAT_IEEE_FLOAT, 64) => SampleFormat::F64,
+            (format_tag, bits) => {
+                bail!("Unsupported sample format (tag: {format_tag:#06x}, bits: {bits}).")
+            }
+        };
+        Ok(sample_format)
+    }
+
+    pub fn bytes(self) -> usize {
+        match self {
+            SampleFormat::U8 => 1,
+            SampleFormat::I16 => 2,
+            SampleFormat::I24 => 3,
+            SampleFormat::I32 | SampleFormat::F32 => 4,
+            SampleFormat::F64 => 8,
+        }
+    }
+}
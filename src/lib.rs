@@ -0,0 +1,27 @@
+pub mod analysis;
+pub mod capture;
+pub mod channel;
+pub mod colormap;
+pub mod config;
+#[cfg(feature = "cpal")]
+pub mod cpal_source;
+pub mod decode;
+pub mod device;
+pub mod fft;
+pub mod follow;
+pub mod format;
+pub mod loudness;
+pub mod packet;
+pub mod plot;
+pub mod recorder;
+pub mod ring;
+pub mod scope;
+pub mod smoothing;
+pub mod source;
+pub mod spectrogram;
+pub mod spectrum;
+pub mod util;
+#[cfg(all(windows, feature = "wasapi"))]
+pub mod wasapi;
+pub mod window;
+pub mod writer;
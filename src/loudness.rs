@@ -0,0 +1,462 @@
+//! ITU-R BS.1770 / EBU R128 のラウドネス測定
+
+use std::{collections::VecDeque, f64::consts::PI, fmt};
+
+use serde::Serialize;
+
+use crate::format::{
+    WaveFormatExtensible, SPEAKER_BACK_LEFT, SPEAKER_BACK_RIGHT, SPEAKER_LOW_FREQUENCY,
+    SPEAKER_SIDE_LEFT, SPEAKER_SIDE_RIGHT,
+};
+
+/// ゲーティングブロックを進める間隔の 100ms を何個まとめるか
+const MOMENTARY_BLOCKS: usize = 4;
+const SHORT_TERM_BLOCKS: usize = 30;
+/// 絶対ゲート (LUFS)
+const ABSOLUTE_GATE: f64 = -70.0;
+/// integrated の相対ゲート (LU)
+const INTEGRATED_RELATIVE_GATE: f64 = -10.0;
+/// LRA の相対ゲート (LU)
+const RANGE_RELATIVE_GATE: f64 = -20.0;
+/// true-peak を求める補間フィルタの位相毎のタップ数
+const TRUE_PEAK_TAPS: usize = 12;
+/// ゲート用のヒストグラムのビンの幅 (LU)。絶対ゲートから +30 LUFS までを数える
+const HISTOGRAM_STEP: f64 = 0.1;
+const HISTOGRAM_BINS: usize = 1000;
+
+/// 平均パワーを LUFS にする
+fn to_lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+fn from_lufs(lufs: f64) -> f64 {
+    10f64.powf((lufs + 0.691) / 10.0)
+}
+
+/// 転置直接型 II の 2 次 IIR フィルタ
+#[derive(Clone, Copy, Debug, Default)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// K 特性の 2 段のフィルタ。BS.1770 の 48kHz の係数を任意のサンプリング周波数で作り直したもの
+fn k_weighting(samples_per_sec: u32) -> [Biquad; 2] {
+    let fs = samples_per_sec as f64;
+
+    // 頭部による高域の持ち上がりを模したシェルビング
+    let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / fs).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    // RLB 特性のハイパス
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / fs).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+    [shelf, high_pass]
+}
+
+/// チャンネル毎の重み。`channel_mask` の並びで LFE は除き、サラウンドは 1.41 倍、それ以外は 1。
+/// マスクのビットが足りないチャンネルは 1 にする
+fn channel_weights(channels: usize, channel_mask: u32) -> Vec<f64> {
+    let mut speakers = (0..32)
+        .map(|bit| 1 << bit)
+        .filter(|speaker| channel_mask & speaker != 0);
+    (0..channels)
+        .map(|_| match speakers.next() {
+            Some(SPEAKER_LOW_FREQUENCY) => 0.0,
+            Some(
+                SPEAKER_BACK_LEFT | SPEAKER_BACK_RIGHT | SPEAKER_SIDE_LEFT | SPEAKER_SIDE_RIGHT,
+            ) => 1.41,
+            _ => 1.0,
+        })
+        .collect()
+}
+
+/// ブロックのラウドネスのヒストグラム。libebur128 と同じく、何時間測っても大きさが変わらない。
+/// ビン毎にパワーの合計も持っておくので、平均はビンの代表値でなく元の値から求まる
+#[derive(Clone, Debug)]
+struct Histogram {
+    counts: Vec<u64>,
+    sums: Vec<f64>,
+    /// 絶対ゲートの下も含めた最大のパワー
+    max: Option<f64>,
+}
+
+impl Histogram {
+    fn new() -> Histogram {
+        Histogram {
+            counts: vec![0; HISTOGRAM_BINS],
+            sums: vec![0.0; HISTOGRAM_BINS],
+            max: None,
+        }
+    }
+
+    /// `lufs` が入るビン。範囲の外は端のビンにする
+    fn bin(lufs: f64) -> usize {
+        let bin = ((lufs - ABSOLUTE_GATE) / HISTOGRAM_STEP).floor();
+        bin.clamp(0.0, (HISTOGRAM_BINS - 1) as f64) as usize
+    }
+
+    /// ビンの真ん中 (LUFS)
+    fn center(bin: usize) -> f64 {
+        ABSOLUTE_GATE + (bin as f64 + 0.5) * HISTOGRAM_STEP
+    }
+
+    /// 絶対ゲートを通らないブロックは最大値にだけ使う
+    fn push(&mut self, power: f64) {
+        self.max = Some(self.max.map_or(power, |max| max.max(power)));
+        if power <= from_lufs(ABSOLUTE_GATE) {
+            return;
+        }
+        let bin = Histogram::bin(to_lufs(power));
+        self.counts[bin] += 1;
+        self.sums[bin] += power;
+    }
+
+    /// 相対ゲートの入るビン。そのビンから上がゲートを通る。絶対ゲートを通ったブロックが無ければ `None`
+    fn gate_bin(&self, relative_gate: f64) -> Option<usize> {
+        let count = self.counts.iter().sum::<u64>();
+        if count == 0 {
+            return None;
+        }
+        let mean = self.sums.iter().sum::<f64>() / count as f64;
+        Some(Histogram::bin(to_lufs(mean) + relative_gate))
+    }
+
+    /// ゲートを通ったブロックの平均パワー
+    fn gated_mean(&self, relative_gate: f64) -> Option<f64> {
+        let gate = self.gate_bin(relative_gate)?;
+        let count = self.counts[gate..].iter().sum::<u64>();
+        let sum = self.sums[gate..].iter().sum::<f64>();
+        (count > 0).then(|| sum / count as f64)
+    }
+
+    /// ゲートを通ったブロックのラウドネスの `percentiles` 点 (LUFS)。ビンの幅の精度になる
+    fn gated_percentiles<const N: usize>(
+        &self,
+        relative_gate: f64,
+        percentiles: [f64; N],
+    ) -> Option<[f64; N]> {
+        let gate = self.gate_bin(relative_gate)?;
+        let count = self.counts[gate..].iter().sum::<u64>();
+        if count == 0 {
+            return None;
+        }
+        Some(percentiles.map(|p| {
+            let index = ((count - 1) as f64 * p).round() as u64;
+            let mut seen = 0;
+            for (bin, &n) in self.counts.iter().enumerate().skip(gate) {
+                seen += n;
+                if seen > index {
+                    return Histogram::center(bin);
+                }
+            }
+            Histogram::center(HISTOGRAM_BINS - 1)
+        }))
+    }
+}
+
+/// 直近の `TRUE_PEAK_TAPS` サンプル。同じ値を 2 か所に書いて、新しい順に切れ目なく読めるようにする
+#[derive(Clone, Copy, Debug)]
+struct History {
+    samples: [f64; 2 * TRUE_PEAK_TAPS],
+    position: usize,
+}
+
+impl History {
+    const EMPTY: History = History {
+        samples: [0.0; 2 * TRUE_PEAK_TAPS],
+        position: 0,
+    };
+
+    /// `sample` を足して `x[n], x[n - 1], ...` の並びを返す
+    fn push(&mut self, sample: f64) -> &[f64] {
+        self.position = (self.position + TRUE_PEAK_TAPS - 1) % TRUE_PEAK_TAPS;
+        self.samples[self.position] = sample;
+        self.samples[self.position + TRUE_PEAK_TAPS] = sample;
+        &self.samples[self.position..self.position + TRUE_PEAK_TAPS]
+    }
+}
+
+/// 補間してサンプル間のピークを探す
+#[derive(Clone, Debug)]
+struct TruePeak {
+    /// `phases[p][j]` が `p / factor` だけずれた点を `x[n - j]` から補間する係数
+    phases: Vec<Vec<f64>>,
+    history: Vec<History>,
+    peak: f64,
+}
+
+impl TruePeak {
+    fn new(channels: usize, samples_per_sec: u32) -> TruePeak {
+        // 96kHz 以上ならそれほど細かく見なくてよい
+        let factor = match samples_per_sec {
+            0..=95_999 => 4,
+            96_000..=191_999 => 2,
+            _ => 1,
+        };
+        let taps = factor * TRUE_PEAK_TAPS;
+        let center = (taps - 1) as f64 / 2.0;
+        let coefficients = (0..taps)
+            .map(|n| {
+                let t = (n as f64 - center) / factor as f64;
+                let sinc = if t == 0.0 {
+                    1.0
+                } else {
+                    (PI * t).sin() / (PI * t)
+                };
+                let window = 0.5 - 0.5 * (2.0 * PI * (n as f64 + 0.5) / taps as f64).cos();
+                sinc * window
+            })
+            .collect::<Vec<_>>();
+        let phases = (0..factor)
+            .map(|p| {
+                let phase = (0..TRUE_PEAK_TAPS)
+                    .map(|j| coefficients[j * factor + p])
+                    .collect::<Vec<_>>();
+                // 直流の利得を 1 に揃える
+                let sum = phase.iter().sum::<f64>();
+                phase.into_iter().map(|c| c / sum).collect()
+            })
+            .collect();
+        TruePeak {
+            phases,
+            history: vec![History::EMPTY; channels],
+            peak: 0.0,
+        }
+    }
+
+    fn process(&mut self, channel: usize, sample: f64) {
+        let history = self.history[channel].push(sample);
+        self.peak = self.peak.max(sample.abs());
+        for phase in &self.phases {
+            let value = phase
+                .iter()
+                .zip(history.iter())
+                .map(|(c, x)| c * x)
+                .sum::<f64>();
+            self.peak = self.peak.max(value.abs());
+        }
+    }
+}
+
+/// 録音や解析の最後にまとめて出す値
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct LoudnessSummary {
+    /// LUFS
+    pub integrated: Option<f64>,
+    /// LU
+    pub range: Option<f64>,
+    /// dBTP
+    pub true_peak: Option<f64>,
+    /// LUFS
+    pub max_momentary: Option<f64>,
+    /// LUFS
+    pub max_short_term: Option<f64>,
+}
+
+impl fmt::Display for LoudnessSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = |value: Option<f64>, unit: &str| match value {
+            Some(value) => format!("{value:.1} {unit}"),
+            None => "-".to_string(),
+        };
+        write!(
+            f,
+            "integrated {}, range {}, true peak {}, max momentary {}, max short-term {}",
+            value(self.integrated, "LUFS"),
+            value(self.range, "LU"),
+            value(self.true_peak, "dBTP"),
+            value(self.max_momentary, "LUFS"),
+            value(self.max_short_term, "LUFS"),
+        )
+    }
+}
+
+/// デコードしたチャンネル毎のサンプルを順に入れていくラウドネスメーター。
+/// 値はまだ計算できるだけの長さが無ければ `None`、無音なら `-inf`。
+/// integrated と LRA はヒストグラムから求めるので、長く測ってもメモリも計算も増えない
+#[derive(Clone, Debug)]
+pub struct LoudnessMeter {
+    channels: usize,
+    samples_per_sec: u32,
+    channel_mask: u32,
+    weights: Vec<f64>,
+    filters: Vec<[Biquad; 2]>,
+    /// 100ms のサンプル数
+    block_len: usize,
+    block_frames: usize,
+    block_sum: f64,
+    /// 直近の 100ms 毎の平均パワー
+    blocks: VecDeque<f64>,
+    momentary: Histogram,
+    short_term: Histogram,
+    true_peak: TruePeak,
+}
+
+impl LoudnessMeter {
+    /// チャンネルはチャンネル数に対する既定の並びとみなす
+    pub fn new(channels: usize, samples_per_sec: u32) -> LoudnessMeter {
+        let channel_mask = WaveFormatExtensible::default_channel_mask(channels as u16);
+        LoudnessMeter {
+            channels,
+            samples_per_sec,
+            channel_mask,
+            weights: channel_weights(channels, channel_mask),
+            filters: vec![k_weighting(samples_per_sec); channels],
+            block_len: (samples_per_sec as usize / 10).max(1),
+            block_frames: 0,
+            block_sum: 0.0,
+            blocks: VecDeque::with_capacity(SHORT_TERM_BLOCKS),
+            momentary: Histogram::new(),
+            short_term: Histogram::new(),
+            true_peak: TruePeak::new(channels, samples_per_sec),
+        }
+    }
+
+    /// チャンネルの並び。`WaveFormatEx::channel_mask` を渡す
+    pub fn with_channel_mask(mut self, channel_mask: u32) -> LoudnessMeter {
+        self.channel_mask = channel_mask;
+        self.weights = channel_weights(self.channels, channel_mask);
+        self
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    pub fn channel_mask(&self) -> u32 {
+        self.channel_mask
+    }
+
+    pub fn samples_per_sec(&self) -> u32 {
+        self.samples_per_sec
+    }
+
+    /// 測定をやり直す
+    pub fn reset(&mut self) {
+        *self = LoudnessMeter::new(self.channels, self.samples_per_sec)
+            .with_channel_mask(self.channel_mask);
+    }
+
+    /// `Decoder::decode` が返すようなチャンネル毎のサンプル
+    pub fn process(&mut self, channels: &[Vec<f32>]) {
+        let frames = channels.iter().map(Vec::len).min().unwrap_or(0);
+        for i in 0..frames {
+            let mut sum = 0.0;
+            for (c, samples) in channels.iter().enumerate().take(self.channels) {
+                let sample = samples[i] as f64;
+                self.true_peak.process(c, sample);
+                let [shelf, high_pass] = &mut self.filters[c];
+                let weighted = high_pass.process(shelf.process(sample));
+                sum += self.weights[c] * weighted * weighted;
+            }
+            self.block_sum += sum;
+            self.block_frames += 1;
+            if self.block_frames == self.block_len {
+                self.push_block();
+            }
+        }
+    }
+
+    fn push_block(&mut self) {
+        if self.blocks.len() == SHORT_TERM_BLOCKS {
+            self.blocks.pop_front();
+        }
+        self.blocks
+            .push_back(self.block_sum / self.block_frames as f64);
+        self.block_sum = 0.0;
+        self.block_frames = 0;
+        if let Some(power) = self.mean_of_latest(MOMENTARY_BLOCKS) {
+            self.momentary.push(power);
+        }
+        if let Some(power) = self.mean_of_latest(SHORT_TERM_BLOCKS) {
+            self.short_term.push(power);
+        }
+    }
+
+    fn mean_of_latest(&self, n: usize) -> Option<f64> {
+        if self.blocks.len() < n {
+            return None;
+        }
+        Some(self.blocks.iter().rev().take(n).sum::<f64>() / n as f64)
+    }
+
+    /// 直近 400ms (LUFS)
+    pub fn momentary(&self) -> Option<f64> {
+        self.mean_of_latest(MOMENTARY_BLOCKS).map(to_lufs)
+    }
+
+    /// 直近 3s (LUFS)
+    pub fn short_term(&self) -> Option<f64> {
+        self.mean_of_latest(SHORT_TERM_BLOCKS).map(to_lufs)
+    }
+
+    /// ゲート付きの全体の平均 (LUFS)。ゲートを通るブロックが無ければ `None`
+    pub fn integrated(&self) -> Option<f64> {
+        self.momentary
+            .gated_mean(INTEGRATED_RELATIVE_GATE)
+            .map(to_lufs)
+    }
+
+    /// EBU Tech 3342 のラウドネスレンジ (LU)。short-term の 10% 点から 95% 点まで
+    pub fn loudness_range(&self) -> Option<f64> {
+        let [low, high] = self
+            .short_term
+            .gated_percentiles(RANGE_RELATIVE_GATE, [0.10, 0.95])?;
+        Some(high - low)
+    }
+
+    /// 今までの true-peak (dBTP)
+    pub fn true_peak(&self) -> Option<f64> {
+        if self.momentary.max.is_none() && self.blocks.is_empty() && self.block_frames == 0 {
+            return None;
+        }
+        Some(20.0 * self.true_peak.peak.log10())
+    }
+
+    pub fn max_momentary(&self) -> Option<f64> {
+        self.momentary.max.map(to_lufs)
+    }
+
+    pub fn max_short_term(&self) -> Option<f64> {
+        self.short_term.max.map(to_lufs)
+    }
+
+    pub fn summary(&self) -> LoudnessSummary {
+        LoudnessSummary {
+            integrated: self.integrated(),
+            range: self.loudness_range(),
+            true_peak: self.true_peak(),
+            max_momentary: self.max_momentary(),
+            max_short_term: self.max_short_term(),
+        }
+    }
+}
@@ -1,17 +1,427 @@
-mod util;
-
-use minifb::{Key, Window, WindowOptions};
+use clap::builder::RangedU64ValueParser;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
 use plotters::backend::{BGRXPixel, BitMapBackend};
 use plotters::prelude::*;
 use std::borrow::{Borrow, BorrowMut};
 use std::error::Error;
-use std::time::SystemTime;
-use util::{get_device, get_device_name, App, Client, Com};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+use windows_cap_audio::{
+    analysis::{self, ReportFormat, ReportWriter},
+    capture::{CaptureThread, Overflow, PacketQueue},
+    channel::ChannelMix,
+    colormap::Colormap,
+    config::{positive_f64, Config},
+    device::{default_data_flow, write_devices, CaptureMode, DeviceInfo, ListFormat},
+    fft::{MAX_SIZE, MIN_SIZE},
+    plot::{numbered_path, Axes, Plot, SERIES_COLORS},
+    recorder::{Limit, Recorder, WavFileSink},
+    scope::{correlation, goniometer, Oscilloscope},
+    smoothing::{Ballistics, PeakHold},
+    source::WavSource,
+    spectrogram::Spectrogram,
+    spectrum::{Binning, Scale},
+    util::App,
+    window::Window as FftWindow,
+};
+
 const W: usize = 800;
 const H: usize = 600;
 
 const FRAME_RATE: f64 = 30.0;
 
+/// 既定で表示する周波数の範囲
+const FREQUENCY_RANGE: (f64, f64) = (20.0, 20_000.0);
+
+/// ラウドネスメーターの範囲 (LUFS)
+const LOUDNESS_RANGE: (f64, f64) = (-60.0, 0.0);
+
+/// EBU R128 の目標値 (LUFS)
+const TARGET_LOUDNESS: f64 = -23.0;
+
+/// サブコマンドを省くと `visualize` になり、そのオプションをそのまま渡せる
+#[derive(Parser, Debug)]
+#[clap(args_conflicts_with_subcommands = true)]
+struct Cli {
+    /// 既定値を書き換える TOML の設定ファイル。コマンドラインで指定したものが優先される
+    #[clap(long, global = true)]
+    config: Option<PathBuf>,
+
+    #[clap(flatten)]
+    visualize: VisualizeArgs,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// 再生中の音やマイクの音のスペクトルなどを表示する。サブコマンドを省くとこれになる
+    Visualize(VisualizeArgs),
+    /// 再生中の音やマイクの音を WAV ファイルに録音する
+    Record(RecordArgs),
+    /// デバイスを一覧にする。左の番号は `--device` に渡せる
+    Devices(DevicesArgs),
+    /// WAV ファイルを最後まで解析してフレーム毎の値とまとめを書き出す
+    Analyze(AnalyzeArgs),
+    /// WAV ファイルを解析して PNG か SVG に描く
+    Render(RenderArgs),
+}
+
+impl Command {
+    fn spectrum(&self) -> Option<&SpectrumArgs> {
+        match self {
+            Command::Visualize(args) => Some(&args.spectrum),
+            Command::Analyze(args) => Some(&args.spectrum),
+            Command::Render(args) => Some(&args.spectrum),
+            Command::Record(_) | Command::Devices(_) => None,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct VisualizeArgs {
+    #[clap(flatten)]
+    capture: CaptureArgs,
+
+    /// ウィンドウの幅
+    #[clap(long, default_value_t = W, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    width: usize,
+
+    /// ウィンドウの高さ
+    #[clap(long, default_value_t = H, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    height: usize,
+
+    /// 描き直す頻度 (fps)
+    #[clap(long, default_value_t = FRAME_RATE, value_parser = positive_f64)]
+    frame_rate: f64,
+
+    #[clap(flatten)]
+    spectrum: SpectrumArgs,
+
+    /// 平滑化で上がる時の時定数
+    #[clap(long, default_value = "10ms", value_parser = |s: &str| duration_str::parse_std(s))]
+    attack: Duration,
+
+    /// 平滑化で下がる時の時定数
+    #[clap(long, default_value = "300ms", value_parser = |s: &str| duration_str::parse_std(s))]
+    release: Duration,
+
+    /// ピークを保持する時間
+    #[clap(long, default_value = "1s", value_parser = |s: &str| duration_str::parse_std(s))]
+    hold: Duration,
+
+    /// 保持が終わったピークが落ちる速さ (dB/s)
+    #[clap(long, default_value_t = 20.0)]
+    fall_rate: f64,
+
+    /// 最初の表示 (spectrum, waterfall, scope, goniometer, loudness)。S, O, G, M キーで切り替え
+    #[clap(long, default_value = "spectrum")]
+    view: View,
+
+    /// スペクトログラムの色 (viridis, magma, grayscale)。C キーで切り替え
+    #[clap(long, default_value = "viridis")]
+    colormap: Colormap,
+
+    /// スペクトログラムの横軸を対数にする。L キーで切り替え
+    #[clap(long)]
+    log_frequency: bool,
+
+    /// オシロスコープとゴニオメーターで表示する時間。←→ キーで変えられる
+    #[clap(long, default_value = "20ms", value_parser = |s: &str| duration_str::parse_std(s))]
+    timebase: Duration,
+
+    /// オシロスコープのトリガーレベル。PageUp, PageDown キーで変えられる
+    #[clap(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    trigger_level: f32,
+}
+
+/// 表示と録音で共通の取り込みの設定
+#[derive(Args, Debug)]
+struct CaptureArgs {
+    /// 使うデバイスの ID、名前の一部か `devices` の番号。無ければ WASAPI では既定の再生デバイス、
+    /// cpal では既定の録音デバイス。`default:capture` なら既定の録音デバイス。
+    /// 録音デバイスはループバックでなく直接取る
+    #[clap(long)]
+    device: Option<String>,
+
+    /// 取り込みスレッドから渡すパケットを溜めておく数
+    #[clap(long, default_value_t = 64)]
+    queue_size: usize,
+
+    /// 溜めきれない時にどうするか (block, drop-oldest, drop-newest)
+    #[clap(long, default_value = "block")]
+    overflow: Overflow,
+
+    /// 選んだデバイスが無くなったら開き直す。既定のデバイスを選んでいれば付けなくても、
+    /// 既定が変わった時にそちらへ移り、無くなったら開き直す。途切れていた間は無音で埋める
+    #[clap(long)]
+    follow: bool,
+}
+
+#[derive(Args, Debug)]
+struct RecordArgs {
+    #[clap(flatten)]
+    capture: CaptureArgs,
+
+    /// 出力先
+    #[clap(short, long)]
+    output: PathBuf,
+
+    /// 記録する期間
+    #[clap(short, long, default_value = "1m", value_parser = |s: &str| duration_str::parse_std(s))]
+    duration: Duration,
+}
+
+#[derive(Args, Debug)]
+struct DevicesArgs {
+    /// 書き出し方 (table, json)
+    #[clap(long, default_value = "table")]
+    format: ListFormat,
+}
+
+#[derive(Args, Debug)]
+struct AnalyzeArgs {
+    /// 解析する WAV ファイル
+    file: PathBuf,
+
+    /// フレーム毎の値の書き出し方 (json, csv)。csv ではまとめを `#` で始まる行で最後に足す
+    #[clap(long, default_value = "json")]
+    format: ReportFormat,
+
+    /// 書き出し先。無ければ標準出力
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+
+    #[clap(flatten)]
+    spectrum: SpectrumArgs,
+}
+
+#[derive(Args, Debug)]
+struct RenderArgs {
+    /// 解析する WAV ファイル
+    file: PathBuf,
+
+    /// 書き出し先 (.png, .svg)。frames では `frame.png` が `frame_000000.png` からの連番になる
+    #[clap(short, long)]
+    output: PathBuf,
+
+    /// 描くもの (average, spectrogram, frames)
+    #[clap(long, default_value = "average")]
+    mode: RenderMode,
+
+    /// 画像の幅
+    #[clap(long, default_value_t = W as u32, value_parser = clap::value_parser!(u32).range(1..))]
+    width: u32,
+
+    /// 画像の高さ
+    #[clap(long, default_value_t = H as u32, value_parser = clap::value_parser!(u32).range(1..))]
+    height: u32,
+
+    /// スペクトログラムの色 (viridis, magma, grayscale)
+    #[clap(long, default_value = "viridis")]
+    colormap: Colormap,
+
+    #[clap(flatten)]
+    spectrum: SpectrumArgs,
+}
+
+/// `render` で描くもの
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RenderMode {
+    /// ファイル全体の平均のスペクトル
+    Average,
+    /// ファイル全体のスペクトログラム
+    Spectrogram,
+    /// STFT のフレーム毎のスペクトルの連番
+    Frames,
+}
+
+impl RenderMode {
+    const VARIANTS: [RenderMode; 3] = [
+        RenderMode::Average,
+        RenderMode::Spectrogram,
+        RenderMode::Frames,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            RenderMode::Average => "average",
+            RenderMode::Spectrogram => "spectrogram",
+            RenderMode::Frames => "frames",
+        }
+    }
+}
+
+impl FromStr for RenderMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match RenderMode::VARIANTS
+            .into_iter()
+            .find(|mode| mode.name().eq_ignore_ascii_case(s))
+        {
+            Some(mode) => Ok(mode),
+            None => anyhow::bail!("Unknown render mode: {s}"),
+        }
+    }
+}
+
+/// 表示と解析で共通のスペクトルの設定
+#[derive(Args, Debug)]
+struct SpectrumArgs {
+    /// スペクトルのまとめ方 (linear[:N], log[:N], octave, third-octave)
+    #[clap(long, default_value = "third-octave")]
+    binning: Binning,
+
+    /// 解析するチャンネル (left, right, mid, side, max, all)
+    #[clap(long, default_value = "mid")]
+    mix: ChannelMix,
+
+    /// dB ではなくパワーのまま表示する
+    #[clap(long)]
+    power: bool,
+
+    /// 0dB とする dBFS
+    #[clap(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    reference: f64,
+
+    /// 表示する下限の dB
+    #[clap(long, default_value_t = -120.0, allow_negative_numbers = true)]
+    floor: f64,
+
+    /// 表示する上限の dB。既定はフルスケール (0 dBFS) の高さ
+    #[clap(long, allow_negative_numbers = true)]
+    ceiling: Option<f64>,
+
+    /// 表示する周波数の下限 (Hz)
+    #[clap(long, default_value_t = FREQUENCY_RANGE.0, value_parser = positive_f64)]
+    min_frequency: f64,
+
+    /// 表示する周波数の上限 (Hz)
+    #[clap(long, default_value_t = FREQUENCY_RANGE.1, value_parser = positive_f64)]
+    max_frequency: f64,
+
+    /// FFT のサイズ (256 から 65536 までの 2 の冪)。↑↓ キーで変えられる
+    #[clap(long, default_value_t = 2048)]
+    fft_size: usize,
+
+    /// 窓関数 (rectangular, hann, hamming, blackman, blackman-harris, flat-top)。W キーで切り替え
+    #[clap(long, default_value = "hann")]
+    window: FftWindow,
+
+    /// STFT のフレームを進める幅。既定は FFT サイズの半分
+    #[clap(long)]
+    hop: Option<usize>,
+}
+
+impl SpectrumArgs {
+    fn scale(&self) -> Scale {
+        if self.power {
+            Scale::Power
+        } else {
+            Scale::Decibel {
+                reference: self.reference,
+                floor: self.floor,
+            }
+        }
+    }
+
+    /// 対数の帯域なら横軸は log10(周波数) で持つ。周波数の下限が上限以上ならエラー
+    fn axes(&self) -> anyhow::Result<Axes> {
+        anyhow::ensure!(
+            self.min_frequency < self.max_frequency,
+            "--min-frequency must be below --max-frequency: {} >= {}",
+            self.min_frequency,
+            self.max_frequency
+        );
+        let value_range = match self.scale() {
+            Scale::Power => Scale::Power.range(),
+            scale => {
+                let (floor, full_scale) = scale.range();
+                (floor, self.ceiling.unwrap_or(full_scale))
+            }
+        };
+        Ok(Axes {
+            frequency_range: (self.min_frequency, self.max_frequency),
+            log_frequency: self.binning.is_logarithmic(),
+            value_range,
+        })
+    }
+
+    fn apply(&self, app: App) -> anyhow::Result<App> {
+        let (min, max) = (self.min_frequency, self.max_frequency);
+        let app = app
+            .with_binning(self.binning)
+            .with_scale(self.scale())
+            .with_mix(self.mix)
+            .with_window(self.window)
+            .with_frequency_range(min, max)
+            .with_fft_size(self.fft_size)?;
+        Ok(match self.hop {
+            Some(hop) => app.with_hop(hop),
+            None => app,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum View {
+    Spectrum,
+    Waterfall,
+    Scope,
+    Goniometer,
+    Loudness,
+}
+
+impl View {
+    const VARIANTS: [View; 5] = [
+        View::Spectrum,
+        View::Waterfall,
+        View::Scope,
+        View::Goniometer,
+        View::Loudness,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            View::Spectrum => "spectrum",
+            View::Waterfall => "waterfall",
+            View::Scope => "scope",
+            View::Goniometer => "goniometer",
+            View::Loudness => "loudness",
+        }
+    }
+
+    /// 同じキーをもう一度押したらスペクトルに戻る
+    fn toggle(self, view: View) -> View {
+        if self == view {
+            View::Spectrum
+        } else {
+            view
+        }
+    }
+}
+
+impl FromStr for View {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match View::VARIANTS
+            .into_iter()
+            .find(|view| view.name().eq_ignore_ascii_case(s))
+        {
+            Some(view) => Ok(view),
+            None => anyhow::bail!("Unknown view: {s}"),
+        }
+    }
+}
+
 struct BufferWrapper(Vec<u32>);
 impl Borrow<[u8]> for BufferWrapper {
     fn borrow(&self) -> &[u8] {
@@ -39,78 +449,617 @@ impl BorrowMut<[u32]> for BufferWrapper {
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let _com = Com::initialize()?;
-    let device = get_device()?;
-    let name = get_device_name(&device)?;
-
-    let client = Client::new(device)?;
-    let mut app = App::new(name.clone(), client);
-
-    let mut buf = BufferWrapper(vec![0u32; W * H]);
-
-    let mut window = Window::new(&name, W, H, WindowOptions::default())?;
-    let cs = {
-        let root = BitMapBackend::<BGRXPixel>::with_buffer_and_format(
-            buf.borrow_mut(),
-            (W as u32, H as u32),
-        )?
-        .into_drawing_area();
-        root.fill(&BLACK)?;
-
-        let mut chart = ChartBuilder::on(&root)
-            .margin(10)
-            .set_all_label_area_size(30)
-            .build_cartesian_2d(0.0..14_000.0, 0.0..1_000_000.0)?;
-
-        chart
-            .configure_mesh()
-            .label_style(("sans-serif", 15).into_font().color(&GREEN))
-            .axis_style(&GREEN)
-            .draw()?;
-
-        let cs = chart.into_chart_state();
-        root.present()?;
-        cs
+    match parse_command()? {
+        Command::Visualize(args) => visualize(&args),
+        Command::Record(args) => record(&args),
+        Command::Devices(args) => devices(&args),
+        Command::Analyze(args) => analyze(&args),
+        Command::Render(args) => render(&args),
+    }
+}
+
+/// コマンドラインを解析する。間違っていれば clap の説明を出して終わる
+fn parse_command() -> anyhow::Result<Command> {
+    match parse_args(std::env::args_os().collect()) {
+        Ok(command) => Ok(command),
+        Err(e) => match e.downcast::<clap::Error>() {
+            Ok(e) => e.exit(),
+            Err(e) => Err(e),
+        },
+    }
+}
+
+/// `--config` があればその値を既定値にしてから解析する。サブコマンドが無ければ `visualize` にする
+fn parse_args(mut args: Vec<OsString>) -> anyhow::Result<Command> {
+    let matches = Cli::command().ignore_errors(true).get_matches_from(&args);
+    let command = match matches.get_one::<PathBuf>("config") {
+        Some(path) => {
+            let command = Config::load(path)?.apply(Cli::command())?;
+            // 読み込んだ後は要らない。サブコマンドの前にあると visualize のオプション扱いでぶつかる
+            remove_config_arg(&mut args);
+            command
+        }
+        None => Cli::command(),
+    };
+    let mut matches = command.clone().try_get_matches_from(&args)?;
+    if matches.subcommand().is_none() {
+        // 設定ファイルの `[visualize]` も効くように、サブコマンドとして解析し直す
+        args.insert(1.min(args.len()), "visualize".into());
+        matches = command.try_get_matches_from(&args)?;
+    }
+    let cli = Cli::from_arg_matches(&matches)?;
+    let command = cli.command.expect("visualize is the default subcommand");
+    // 周波数の上限と下限のように、値の組み合わせはここで確かめる
+    if let Some(spectrum) = command.spectrum() {
+        spectrum.axes()?;
+    }
+    Ok(command)
+}
+
+/// `--config PATH` と `--config=PATH` を外す。`--` より後ろは値なので触らない
+fn remove_config_arg(args: &mut Vec<OsString>) {
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].to_str() {
+            Some("--") => break,
+            Some("--config") => {
+                args.drain(i..(i + 2).min(args.len()));
+            }
+            Some(arg) if arg.starts_with("--config=") => {
+                args.remove(i);
+            }
+            _ => i += 1,
+        }
+    }
+}
+
+/// 選んだデバイスから専用のスレッドで取り込む
+fn spawn_capture(args: &CaptureArgs) -> anyhow::Result<(DeviceInfo, CaptureThread)> {
+    let query = args.device.as_deref().unwrap_or(backend::DEFAULT_DEVICE);
+    let info = backend::find(query)?;
+    let queue = PacketQueue::new(args.queue_size, args.overflow);
+    let capture = if args.follow || default_data_flow(Some(query))?.is_some() {
+        backend::spawn_follow(query.to_string(), queue)?
+    } else {
+        backend::spawn(info.id.clone(), queue)?
+    };
+    Ok((info, capture))
+}
+
+/// Windows で `wasapi` があれば WASAPI で取り込む
+#[cfg(all(windows, feature = "wasapi"))]
+mod backend {
+    use anyhow::Result;
+    use windows_cap_audio::{
+        capture::{CaptureThread, PacketQueue},
+        device::{find_device, DeviceEnumerator, DeviceInfo},
+        follow::FollowSource,
+        wasapi::{Client, Com, WasapiConnector, WasapiEnumerator},
+    };
+
+    /// 再生中の音をループバックで取る
+    pub const DEFAULT_DEVICE: &str = "default";
+
+    pub fn devices() -> Result<Vec<DeviceInfo>> {
+        let _com = Com::initialize()?;
+        WasapiEnumerator::new()?.devices()
+    }
+
+    pub fn find(query: &str) -> Result<DeviceInfo> {
+        let _com = Com::initialize()?;
+        find_device(&WasapiEnumerator::new()?, Some(query))
+    }
+
+    pub fn spawn(id: String, queue: PacketQueue) -> Result<CaptureThread> {
+        CaptureThread::spawn(
+            move || {
+                let _com = Com::initialize()?;
+                Client::new(WasapiEnumerator::new()?.open(&id)?)
+            },
+            queue,
+        )
+    }
+
+    pub fn spawn_follow(query: String, queue: PacketQueue) -> Result<CaptureThread> {
+        CaptureThread::spawn(
+            move || FollowSource::new(WasapiConnector::new(Some(&query))?),
+            queue,
+        )
+    }
+}
+
+/// それ以外で `cpal` があれば cpal で取り込む
+#[cfg(all(feature = "cpal", not(all(windows, feature = "wasapi"))))]
+mod backend {
+    use anyhow::Result;
+    use windows_cap_audio::{
+        capture::{CaptureThread, PacketQueue},
+        cpal_source::{CpalConnector, CpalEnumerator, CpalSource},
+        device::{find_device, DeviceEnumerator, DeviceInfo},
+        follow::FollowSource,
+    };
+
+    /// 再生デバイスからは取れないので既定の録音デバイス。PulseAudio ならモニターを既定にしておく
+    pub const DEFAULT_DEVICE: &str = "default:capture";
+
+    pub fn devices() -> Result<Vec<DeviceInfo>> {
+        CpalEnumerator::new()?.devices()
+    }
+
+    pub fn find(query: &str) -> Result<DeviceInfo> {
+        find_device(&CpalEnumerator::new()?, Some(query))
+    }
+
+    /// cpal のストリームもスレッドを跨げないことがあるので、取り込みスレッドで開く
+    pub fn spawn(id: String, queue: PacketQueue) -> Result<CaptureThread> {
+        CaptureThread::spawn(
+            move || CpalSource::new(CpalEnumerator::new()?.open(&id)?),
+            queue,
+        )
+    }
+
+    pub fn spawn_follow(query: String, queue: PacketQueue) -> Result<CaptureThread> {
+        CaptureThread::spawn(
+            move || FollowSource::new(CpalConnector::new(Some(&query))?),
+            queue,
+        )
+    }
+}
+
+/// どちらも無ければ WAV ファイルの解析だけできる
+#[cfg(not(any(all(windows, feature = "wasapi"), feature = "cpal")))]
+mod backend {
+    use anyhow::{bail, Result};
+    use windows_cap_audio::{
+        capture::{CaptureThread, PacketQueue},
+        device::DeviceInfo,
     };
 
+    const MISSING: &str = "No capture backend. Build with the `wasapi` or `cpal` feature.";
+
+    pub const DEFAULT_DEVICE: &str = "default";
+
+    pub fn devices() -> Result<Vec<DeviceInfo>> {
+        bail!(MISSING)
+    }
+
+    pub fn find(_query: &str) -> Result<DeviceInfo> {
+        bail!(MISSING)
+    }
+
+    pub fn spawn(_id: String, _queue: PacketQueue) -> Result<CaptureThread> {
+        bail!(MISSING)
+    }
+
+    pub fn spawn_follow(_query: String, _queue: PacketQueue) -> Result<CaptureThread> {
+        bail!(MISSING)
+    }
+}
+
+fn record(args: &RecordArgs) -> Result<(), Box<dyn Error>> {
+    let (info, capture) = spawn_capture(&args.capture)?;
+    eprintln!("Device: {}", info.name);
+    eprintln!(
+        "Mode: {}",
+        CaptureMode::for_data_flow(info.data_flow).name()
+    );
+    eprintln!("Format: {:#?}", capture.wave_format());
+
+    let queue = capture.queue().clone();
+    let mut recorder = Recorder::new(capture, WavFileSink::new(&args.output))?
+        .with_limit(Limit::Duration(args.duration))
+        .with_loudness();
+    recorder.run()?;
+    eprintln!("Recorded: {:?}", recorder.recorded());
+    if let Some(loudness) = recorder.loudness() {
+        eprintln!("Loudness: {}", loudness.summary());
+    }
+    eprintln!("Capture: {}", recorder.capture_stats());
+    eprintln!("Queue: {}", queue.stats());
+    Ok(())
+}
+
+fn devices(args: &DevicesArgs) -> Result<(), Box<dyn Error>> {
+    let devices = backend::devices()?;
+    write_devices(std::io::stdout().lock(), &devices, args.format)?;
+    Ok(())
+}
+
+fn analyze(args: &AnalyzeArgs) -> Result<(), Box<dyn Error>> {
+    let source = WavSource::open(&args.file)?;
+    let mut app = args
+        .spectrum
+        .apply(App::new(args.file.display().to_string(), source))?;
+    let output: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(std::io::stdout().lock())),
+    };
+    let mut writer = ReportWriter::new(output, args.format);
+    let summary = analysis::analyze(&mut app, |report| writer.frame(&report))?;
+    writer.summary(&summary)?;
+    eprintln!(
+        "{}: {} frames, {:.2} s, sample peak {:.1} dBFS",
+        app.name(),
+        summary.frames,
+        summary.duration,
+        summary.sample_peak
+    );
+    eprintln!("{}", summary.loudness);
+    Ok(())
+}
+
+fn render(args: &RenderArgs) -> Result<(), Box<dyn Error>> {
+    let source = WavSource::open(&args.file)?;
+    let mut app = args
+        .spectrum
+        .apply(App::new(args.file.display().to_string(), source))?;
+    let axes = args.spectrum.axes()?;
+    let size = (args.width, args.height);
+    match args.mode {
+        RenderMode::Average => {
+            let summary = analysis::analyze(&mut app, |_| Ok(()))?;
+            Plot::Spectrum {
+                axes,
+                spectra: &[summary.average_spectrum],
+                peaks: &[],
+                average: &[],
+            }
+            .save(&args.output, size)?;
+        }
+        RenderMode::Spectrogram => {
+            let mut rows = vec![];
+            let summary = analysis::analyze(&mut app, |report| {
+                rows.push(report.spectrum);
+                Ok(())
+            })?;
+            let mut spectrogram = Spectrogram::new(rows.len());
+            for row in rows {
+                spectrogram.push(row);
+            }
+            Plot::Spectrogram {
+                axes,
+                colormap: args.colormap,
+                spectrogram: &spectrogram,
+                time_range: (0.0, summary.duration),
+            }
+            .save(&args.output, size)?;
+        }
+        RenderMode::Frames => {
+            let mut index = 0;
+            analysis::analyze(&mut app, |report| {
+                Plot::Spectrum {
+                    axes,
+                    spectra: &[report.spectrum],
+                    peaks: &[],
+                    average: &[],
+                }
+                .save(&numbered_path(&args.output, index), size)?;
+                index += 1;
+                Ok(())
+            })?;
+        }
+    }
+    Ok(())
+}
+
+fn visualize(cli: &VisualizeArgs) -> Result<(), Box<dyn Error>> {
+    let (info, capture) = spawn_capture(&cli.capture)?;
+    let name = info.name;
+
+    let mut app = cli
+        .spectrum
+        .apply(App::new(name.clone(), capture))?
+        .with_ballistics(Ballistics::new(cli.attack, cli.release))
+        .with_peak_hold(PeakHold::new(cli.hold, cli.fall_rate));
+
+    let axes = cli.spectrum.axes()?;
+
+    let (width, height) = (cli.width, cli.height);
+    let mut buf = BufferWrapper(vec![0u32; width * height]);
+
+    let mut window = Window::new(&name, width, height, WindowOptions::default())?;
+
     let start_ts = SystemTime::now();
     let mut last_flushed = 0.0;
+    // P でピーク、A で長時間平均の表示を切り替える
+    let mut show_peaks = true;
+    let mut show_average = false;
+    let mut view = cli.view;
+    let mut scope = Oscilloscope::new(cli.timebase, cli.trigger_level);
+    let mut colormap = cli.colormap;
+    let mut log_frequency = cli.log_frequency;
 
     while window.is_open() && !window.is_key_down(Key::Escape) {
         let epoch = SystemTime::now()
             .duration_since(start_ts)
             .unwrap()
             .as_secs_f64();
-        app.on_tick();
+        for key in window.get_keys_pressed(KeyRepeat::No) {
+            match key {
+                Key::W => app.set_window(app.window().next()),
+                Key::P => show_peaks = !show_peaks,
+                Key::A => show_average = !show_average,
+                Key::R if view == View::Loudness => app.reset_loudness(),
+                Key::R => app.reset_smoothing(),
+                Key::S => view = view.toggle(View::Waterfall),
+                Key::O => view = view.toggle(View::Scope),
+                Key::G => view = view.toggle(View::Goniometer),
+                Key::M => view = view.toggle(View::Loudness),
+                Key::Left => scope.set_timebase(scope.timebase() / 2),
+                Key::Right => scope.set_timebase(scope.timebase() * 2),
+                Key::PageUp => scope.set_trigger_level((scope.trigger_level() + 0.05).min(1.0)),
+                Key::PageDown => scope.set_trigger_level((scope.trigger_level() - 0.05).max(-1.0)),
+                Key::C => colormap = colormap.next(),
+                Key::L => log_frequency = !log_frequency,
+                Key::Up if app.fft_size() < MAX_SIZE => app.set_fft_size(app.fft_size() * 2)?,
+                Key::Down if app.fft_size() > MIN_SIZE => app.set_fft_size(app.fft_size() / 2)?,
+                _ => {}
+            }
+        }
+        // 既定のデバイスを追っていれば無くなっても開き直すので、ここに来るのは続けられない時だけ
+        app.on_tick().map_err(|e| e.context("Capture stopped."))?;
 
-        if epoch - last_flushed > 1.0 / FRAME_RATE {
+        if epoch - last_flushed > 1.0 / cli.frame_rate {
+            let status;
             {
                 let root = BitMapBackend::<BGRXPixel>::with_buffer_and_format(
                     buf.borrow_mut(),
-                    (W as u32, H as u32),
+                    (width as u32, height as u32),
                 )?
                 .into_drawing_area();
-                {
-                    let data = app.data();
+                root.fill(&BLACK)?;
+                match view {
+                    View::Spectrum => {
+                        let peaks = if show_peaks { app.peaks() } else { vec![] };
+                        let average = if show_average { app.average() } else { vec![] };
+                        Plot::Spectrum {
+                            axes,
+                            spectra: &app.smoothed(),
+                            peaks: &peaks,
+                            average: &average,
+                        }
+                        .draw(&root)?;
+                        status = format!(
+                            "FFT {} / {} / {:.2} Hz",
+                            app.fft_size(),
+                            app.window().name(),
+                            app.frequency_resolution()
+                        );
+                    }
+                    View::Waterfall => {
+                        let seconds = app.spectrogram().capacity() as f64 * app.frame_interval();
+                        Plot::Spectrogram {
+                            axes: Axes {
+                                log_frequency,
+                                ..axes
+                            },
+                            colormap,
+                            spectrogram: app.spectrogram(),
+                            time_range: (-seconds, 0.0),
+                        }
+                        .draw(&root)?;
+                        status = format!(
+                            "FFT {} / {} / {:.2} Hz / {}",
+                            app.fft_size(),
+                            app.window().name(),
+                            app.frequency_resolution(),
+                            colormap.name()
+                        );
+                    }
+                    View::Scope => {
+                        let rate = app.samples_per_sec();
+                        let capture = scope.capture(&app.waveform(scope.frames(rate) * 2), rate);
+                        let millis = scope.timebase().as_secs_f64() * 1_000.0;
+                        let mut chart = ChartBuilder::on(&root)
+                            .margin(10)
+                            .set_all_label_area_size(30)
+                            .build_cartesian_2d(0.0..millis, -1.0..1.0)?;
+                        chart
+                            .configure_mesh()
+                            .label_style(("sans-serif", 15).into_font().color(&GREEN))
+                            .x_label_formatter(&|x| format!("{x:.1}ms"))
+                            .axis_style(GREEN)
+                            .bold_line_style(GREEN.mix(0.2))
+                            .light_line_style(TRANSPARENT)
+                            .draw()?;
+
+                        let level = scope.trigger_level() as f64;
+                        chart.draw_series(LineSeries::new(
+                            [(0.0, level), (millis, level)],
+                            RED.mix(0.5),
+                        ))?;
+                        let dt = 1_000.0 / rate as f64;
+                        for (samples, color) in
+                            capture.channels.iter().zip(SERIES_COLORS.iter().cycle())
+                        {
+                            let series = LineSeries::new(
+                                samples
+                                    .iter()
+                                    .enumerate()
+                                    .map(|(i, &v)| (i as f64 * dt, v as f64)),
+                                color,
+                            );
+                            chart.draw_series(series)?;
+                        }
+                        status = format!(
+                            "{:.1} ms / trigger {:+.2} / {}",
+                            millis,
+                            scope.trigger_level(),
+                            if capture.triggered {
+                                "triggered"
+                            } else {
+                                "auto"
+                            }
+                        );
+                    }
+                    View::Goniometer => {
+                        let samples = app.waveform(scope.frames(app.samples_per_sec()));
+                        let left = samples.first().cloned().unwrap_or_default();
+                        let right = samples.get(1).cloned().unwrap_or_else(|| left.clone());
+                        let correlation = correlation(&left, &right);
 
-                    let mut chart = cs.clone().restore(&root);
-                    chart.plotting_area().fill(&BLACK)?;
+                        let (meter, area) = root.split_vertically(80);
+                        let mut chart = ChartBuilder::on(&meter)
+                            .margin(10)
+                            .margin_top(50)
+                            .set_label_area_size(LabelAreaPosition::Left, 30)
+                            .build_cartesian_2d(-1.0..1.0, 0.0..1.0)?;
+                        chart.draw_series([Rectangle::new(
+                            [(0.0, 0.0), (correlation, 1.0)],
+                            if correlation < 0.0 { RED } else { GREEN }.filled(),
+                        )])?;
+                        chart.draw_series(LineSeries::new([(0.0, 0.0), (0.0, 1.0)], WHITE))?;
 
-                    chart
-                        .configure_mesh()
-                        .bold_line_style(&GREEN.mix(0.2))
-                        .light_line_style(&TRANSPARENT)
-                        .draw()?;
+                        // 縦横の比を揃える
+                        let (width, height) = area.dim_in_pixel();
+                        let aspect = width as f64 / height as f64;
+                        let mut chart = ChartBuilder::on(&area)
+                            .margin(10)
+                            .build_cartesian_2d(-aspect..aspect, -1.0..1.0)?;
+                        for (from, to) in [((-1.0, 1.0), (1.0, -1.0)), ((-1.0, -1.0), (1.0, 1.0))] {
+                            chart.draw_series(LineSeries::new([from, to], GREEN.mix(0.2)))?;
+                        }
+                        let style = ("sans-serif", 15).into_font().color(&GREEN);
+                        chart.draw_series([
+                            Text::new("L", (-0.75, 0.75), style.clone()),
+                            Text::new("R", (0.72, 0.75), style),
+                        ])?;
+                        chart.draw_series(
+                            goniometer(&left, &right)
+                                .into_iter()
+                                .map(|(x, y)| Pixel::new((x as f64, y as f64), GREEN.mix(0.6))),
+                        )?;
+                        status = format!("correlation {correlation:+.2}");
+                    }
+                    View::Loudness => {
+                        let loudness = app.loudness();
+                        let meters = [
+                            ("M", loudness.and_then(|l| l.momentary())),
+                            ("S", loudness.and_then(|l| l.short_term())),
+                            ("I", loudness.and_then(|l| l.integrated())),
+                        ];
+                        let (low, high) = LOUDNESS_RANGE;
+                        let mut chart = ChartBuilder::on(&root)
+                            .margin(10)
+                            .margin_top(60)
+                            .set_all_label_area_size(30)
+                            .build_cartesian_2d(low..high, 0.0..meters.len() as f64)?;
+                        chart
+                            .configure_mesh()
+                            .disable_y_mesh()
+                            .disable_y_axis()
+                            .label_style(("sans-serif", 15).into_font().color(&GREEN))
+                            .x_label_formatter(&|x| format!("{x:.0}"))
+                            .axis_style(GREEN)
+                            .bold_line_style(GREEN.mix(0.2))
+                            .light_line_style(TRANSPARENT)
+                            .draw()?;
+                        for (i, (label, value)) in meters.iter().enumerate() {
+                            let y = (meters.len() - 1 - i) as f64;
+                            if let Some(value) = value {
+                                let color = if *value > TARGET_LOUDNESS {
+                                    YELLOW
+                                } else {
+                                    GREEN
+                                };
+                                chart.draw_series([Rectangle::new(
+                                    [(low, y + 0.2), (value.clamp(low, high), y + 0.8)],
+                                    color.filled(),
+                                )])?;
+                            }
+                            chart.draw_series([Text::new(
+                                *label,
+                                (low + 1.0, y + 0.9),
+                                ("sans-serif", 20).into_font().color(&WHITE),
+                            )])?;
+                        }
+                        chart.draw_series(LineSeries::new(
+                            [
+                                (TARGET_LOUDNESS, 0.0),
+                                (TARGET_LOUDNESS, meters.len() as f64),
+                            ],
+                            RED.mix(0.6),
+                        ))?;
 
-                    let series = LineSeries::new(data.iter().copied(), &GREEN);
-                    chart.draw_series(series)?;
+                        let value = |value: Option<f64>| match value {
+                            Some(value) => format!("{value:.1}"),
+                            None => "-".to_string(),
+                        };
+                        status = format!(
+                            "M {} / S {} / I {} LUFS / LRA {} LU / TP {} dBTP",
+                            value(meters[0].1),
+                            value(meters[1].1),
+                            value(meters[2].1),
+                            value(loudness.and_then(|l| l.loudness_range())),
+                            value(loudness.and_then(|l| l.true_peak())),
+                        );
+                    }
                 }
+                root.draw(&Text::new(
+                    status,
+                    (50, 45),
+                    ("sans-serif", 15).into_font().color(&GREEN),
+                ))?;
+                // 取りこぼしがあったら目立たせる
+                let capture = app.capture_stats();
+                root.draw(&Text::new(
+                    format!(
+                        "{} gaps ({} frames lost) / {} overruns / {} reconnects",
+                        capture.gaps, capture.lost_frames, capture.overruns, capture.reconnects
+                    ),
+                    (50, 25),
+                    ("sans-serif", 15)
+                        .into_font()
+                        .color(if capture.has_glitches() {
+                            &YELLOW
+                        } else {
+                            &GREEN
+                        }),
+                ))?;
                 root.present()?;
             }
 
-            window.update_with_buffer(buf.borrow(), W, H)?;
+            window.update_with_buffer(buf.borrow(), width, height)?;
             last_flushed = epoch;
+        } else {
+            // 取り込みは別のスレッドなので、次に描くまで寝ていても取りこぼさない
+            let next = last_flushed + 1.0 / cli.frame_rate;
+            std::thread::sleep(Duration::from_secs_f64((next - epoch).max(0.0)));
         }
     }
+    eprintln!("Capture: {}", app.capture_stats());
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> anyhow::Result<Command> {
+        parse_args(args.iter().map(OsString::from).collect())
+    }
+
+    #[test]
+    fn rejects_invalid_frequency_range() {
+        let invalid: [&[&str]; 4] = [
+            &["--min-frequency", "0"],
+            &["--max-frequency", "nan"],
+            &["--min-frequency", "2000", "--max-frequency", "1000"],
+            &["--min-frequency", "1000", "--max-frequency", "1000"],
+        ];
+        for options in invalid {
+            let render = [&["app", "render", "t.wav", "-o", "o.png"], options].concat();
+            assert!(parse(&render).is_err(), "{options:?}");
+            let analyze = [&["app", "analyze", "t.wav"], options].concat();
+            assert!(parse(&analyze).is_err(), "{options:?}");
+        }
+        let Command::Render(args) = parse(&["app", "render", "t.wav", "-o", "o.png"]).unwrap()
+        else {
+            panic!("not a render command");
+        };
+        assert_eq!(
+            args.spectrum.axes().unwrap().frequency_range,
+            FREQUENCY_RANGE
+        );
+    }
+}
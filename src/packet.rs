@@ -0,0 +1,151 @@
+//! 取り込んだパケットと、そこに付いてくる位置や時刻、取りこぼしのまとめ
+
+use std::fmt;
+
+use crate::format::WaveFormatEx;
+
+/// パケットに付いてくる情報。WASAPI なら `GetBuffer` が返すもの
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PacketInfo {
+    pub frames: u64,
+    /// ストリームの先頭からのフレーム位置。分からなければ `None`
+    pub device_position: Option<u64>,
+    /// 最初のフレームを取った時刻。QPC を 100ns 単位にしたもの
+    pub qpc_position: Option<u64>,
+    /// 前のパケットとの間で音が途切れた。取り込みが追い付かずにデバイスのバッファが溢れると立つ
+    pub discontinuity: bool,
+    /// 無音として扱うパケット。中身は無音の値 (8bit PCM なら 0x80、それ以外は 0) で埋めてある
+    pub silent: bool,
+    /// `qpc_position` が当てにならない
+    pub timestamp_error: bool,
+    /// デバイスを開き直して最初のパケット。前のパケットとの間は途切れていて、位置も数え直しになる
+    pub reconnected: bool,
+    /// このパケットの前に、キューが溢れるなどして捨てたパケット数。位置が無くても取りこぼしが分かる
+    pub dropped_packets: u64,
+    /// 捨てたパケットのフレーム数。分からなければ 0
+    pub dropped_frames: u64,
+}
+
+impl PacketInfo {
+    /// このパケットの前で `info` のパケットを捨てた
+    pub fn add_dropped(&mut self, info: &PacketInfo) {
+        self.dropped_packets += 1 + info.dropped_packets;
+        self.dropped_frames += info.frames + info.dropped_frames;
+    }
+}
+
+/// 取り込んだバイト列と、その情報
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Packet {
+    pub data: Vec<u8>,
+    pub info: PacketInfo,
+    /// 開き直したデバイスのフォーマットが前と違った。このパケットからはこのフォーマットで読む
+    pub format: Option<WaveFormatEx>,
+}
+
+impl Packet {
+    /// 位置も時刻も分からないパケット。フレーム数だけ `block_align` から数える
+    pub fn new(data: Vec<u8>, block_align: u16) -> Packet {
+        let info = PacketInfo {
+            frames: (data.len() / block_align.max(1) as usize) as u64,
+            ..PacketInfo::default()
+        };
+        Packet {
+            data,
+            info,
+            format: None,
+        }
+    }
+
+    /// このパケットの前で `dropped` を捨てた。フォーマットが変わった知らせは捨てずに引き継ぐ
+    pub fn add_dropped(&mut self, dropped: &Packet) {
+        self.info.add_dropped(&dropped.info);
+        if self.format.is_none() {
+            self.format = dropped.format.clone();
+        }
+    }
+}
+
+/// 何も分からないパケット。フレーム数も 0 のまま
+impl From<Vec<u8>> for Packet {
+    fn from(data: Vec<u8>) -> Packet {
+        Packet {
+            data,
+            info: PacketInfo::default(),
+            format: None,
+        }
+    }
+}
+
+/// 取り込んだパケットの情報のまとめ
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CaptureStats {
+    pub packets: u64,
+    pub frames: u64,
+    /// デバイスの位置が飛んでいたか、手前でパケットを捨てていた回数。キューで捨てた分もここに出る
+    pub gaps: u64,
+    /// 飛んでいた分か捨てた分のフレーム数。両方分かる時は多い方
+    pub lost_frames: u64,
+    /// デバイスが途切れを知らせた回数
+    pub overruns: u64,
+    pub silent_packets: u64,
+    pub timestamp_errors: u64,
+    /// デバイスを開き直した回数
+    pub reconnects: u64,
+    /// 次のパケットが来るはずの位置
+    next_position: Option<u64>,
+}
+
+impl CaptureStats {
+    pub fn record(&mut self, info: &PacketInfo) {
+        // 最初のパケットは始めたばかりなので途切れたことになっている
+        if info.discontinuity && self.packets > 0 {
+            self.overruns += 1;
+        }
+        if info.reconnected {
+            self.reconnects += 1;
+            self.next_position = None;
+        }
+        let mut skipped = None;
+        if let Some(position) = info.device_position {
+            if let Some(next) = self.next_position.filter(|&next| position > next) {
+                skipped = Some(position - next);
+            }
+            self.next_position = Some(position + info.frames);
+        }
+        // 位置で分かる分とキューで捨てた分は同じ途切れなので 1 回に数える
+        if info.dropped_packets > 0 {
+            skipped = Some(skipped.unwrap_or(0).max(info.dropped_frames));
+        }
+        if let Some(skipped) = skipped {
+            self.gaps += 1;
+            self.lost_frames += skipped;
+        }
+        self.packets += 1;
+        self.frames += info.frames;
+        self.silent_packets += info.silent as u64;
+        self.timestamp_errors += info.timestamp_error as u64;
+    }
+
+    /// 何か取りこぼした
+    pub fn has_glitches(&self) -> bool {
+        self.gaps > 0 || self.overruns > 0 || self.reconnects > 0
+    }
+}
+
+impl fmt::Display for CaptureStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} packets, {} frames, {} gaps ({} frames lost), {} overruns, {} reconnects, {} silent, {} timestamp errors",
+            self.packets,
+            self.frames,
+            self.gaps,
+            self.lost_frames,
+            self.overruns,
+            self.reconnects,
+            self.silent_packets,
+            self.timestamp_errors
+        )
+    }
+}
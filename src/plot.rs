@@ -0,0 +1,199 @@
+//! plotters でスペクトルやスペクトログラムを描く。ウィンドウにもファイルにも同じものを描く
+
+use std::{iter, path::Path};
+
+use anyhow::{bail, Result};
+use plotters::{coord::Shift, prelude::*};
+
+use crate::{
+    colormap::Colormap,
+    spectrogram::{Rendering, Spectrogram},
+};
+
+/// 対数の横軸で描く周波数の下限。0 Hz 以下は log10 が有限にならない
+const MIN_LOG_FREQUENCY: f64 = 1.0;
+
+/// チャンネル毎に表示する時の色
+pub const SERIES_COLORS: [RGBColor; 4] = [GREEN, CYAN, MAGENTA, YELLOW];
+
+/// 横軸の周波数と縦軸の値の範囲
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Axes {
+    pub frequency_range: (f64, f64),
+    /// 横軸を log10(周波数) で持つ
+    pub log_frequency: bool,
+    pub value_range: (f64, f64),
+}
+
+impl Axes {
+    fn x(&self, freq: f64) -> f64 {
+        if self.log_frequency {
+            freq.max(MIN_LOG_FREQUENCY).log10()
+        } else {
+            freq
+        }
+    }
+
+    fn x_label(&self, x: f64) -> String {
+        format!("{:.0}", if self.log_frequency { 10f64.powf(x) } else { x })
+    }
+
+    /// 横軸の範囲。対数なら log10(周波数) で、1 Hz より下は 1 Hz にする
+    pub fn x_range(&self) -> std::ops::Range<f64> {
+        let (min, max) = self.frequency_range;
+        self.x(min)..self.x(max)
+    }
+}
+
+/// 描くもの
+pub enum Plot<'a> {
+    /// 重ねて描くスペクトル。空なら描かない
+    Spectrum {
+        axes: Axes,
+        /// 線で描く
+        spectra: &'a [Vec<(f64, f64)>],
+        /// 点で描く
+        peaks: &'a [Vec<(f64, f64)>],
+        /// 白い線で描く
+        average: &'a [Vec<(f64, f64)>],
+    },
+    /// 新しい行が上のスペクトログラム。縦軸は `time_range` で、上が `time_range.1`
+    Spectrogram {
+        axes: Axes,
+        colormap: Colormap,
+        spectrogram: &'a Spectrogram,
+        time_range: (f64, f64),
+    },
+}
+
+impl Plot<'_> {
+    /// `root` を黒く塗ってから描く
+    pub fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> Result<()>
+    where
+        DB::ErrorType: 'static,
+    {
+        root.fill(&BLACK)?;
+        match self {
+            Plot::Spectrum {
+                axes,
+                spectra,
+                peaks,
+                average,
+            } => {
+                let (y_min, y_max) = axes.value_range;
+                let mut chart = ChartBuilder::on(root)
+                    .margin(10)
+                    .set_all_label_area_size(30)
+                    .build_cartesian_2d(axes.x_range(), y_min..y_max)?;
+                chart
+                    .configure_mesh()
+                    .label_style(("sans-serif", 15).into_font().color(&GREEN))
+                    .x_label_formatter(&|x| axes.x_label(*x))
+                    .axis_style(GREEN)
+                    .bold_line_style(GREEN.mix(0.2))
+                    .light_line_style(TRANSPARENT)
+                    .draw()?;
+
+                for data in average.iter() {
+                    let series = LineSeries::new(
+                        data.iter().map(|&(freq, value)| (axes.x(freq), value)),
+                        WHITE.mix(0.6),
+                    );
+                    chart.draw_series(series)?;
+                }
+                for (data, color) in peaks.iter().zip(SERIES_COLORS.iter().cycle()) {
+                    let series = data.iter().map(|&(freq, value)| {
+                        Circle::new((axes.x(freq), value), 2, color.mix(0.6).filled())
+                    });
+                    chart.draw_series(series)?;
+                }
+                for (data, color) in spectra.iter().zip(SERIES_COLORS.iter().cycle()) {
+                    let series = LineSeries::new(
+                        data.iter().map(|&(freq, value)| (axes.x(freq), value)),
+                        color,
+                    );
+                    chart.draw_series(series)?;
+                }
+            }
+            Plot::Spectrogram {
+                axes,
+                colormap,
+                spectrogram,
+                time_range,
+            } => {
+                let (start, end) = *time_range;
+                let mut chart = ChartBuilder::on(root)
+                    .margin(10)
+                    .set_all_label_area_size(30)
+                    .build_cartesian_2d(axes.x_range(), start..end)?;
+                chart
+                    .configure_mesh()
+                    .disable_mesh()
+                    .label_style(("sans-serif", 15).into_font().color(&GREEN))
+                    .x_label_formatter(&|x| axes.x_label(*x))
+                    .y_label_formatter(&|y| format!("{y:.1}s"))
+                    .axis_style(GREEN)
+                    .draw()?;
+
+                let (xs, ys) = chart.plotting_area().get_pixel_range();
+                let (width, height) = ((xs.end - xs.start) as usize, (ys.end - ys.start) as usize);
+                let rendering = Rendering {
+                    frequency_range: axes.frequency_range,
+                    log_frequency: axes.log_frequency,
+                    value_range: axes.value_range,
+                    colormap: *colormap,
+                };
+                // plotters のビットマップは RGB の順
+                let rgb = spectrogram
+                    .render(width, height, &rendering)
+                    .into_iter()
+                    .flat_map(|pixel| [(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8])
+                    .collect::<Vec<_>>();
+                if let Some(image) = BitMapElement::with_owned_buffer(
+                    (axes.x_range().start, end),
+                    (width as u32, height as u32),
+                    rgb,
+                ) {
+                    chart.draw_series(iter::once(image))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 拡張子が `png` なら PNG、`svg` なら SVG で `path` に書き出す
+    pub fn save(&self, path: &Path, size: (u32, u32)) -> Result<()> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => {
+                let root = BitMapBackend::new(path, size).into_drawing_area();
+                self.draw(&root)?;
+                root.present()?;
+            }
+            "svg" => {
+                let root = SVGBackend::new(path, size).into_drawing_area();
+                self.draw(&root)?;
+                root.present()?;
+            }
+            _ => bail!("Unsupported image format: {}", path.display()),
+        }
+        Ok(())
+    }
+}
+
+/// `out/frame.png` を `out/frame_000012.png` のように連番にする
+pub fn numbered_path(path: &Path, index: u64) -> std::path::PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem}_{index:06}.{}", extension.to_string_lossy()),
+        None => format!("{stem}_{index:06}"),
+    };
+    path.with_file_name(name)
+}
@@ -0,0 +1,381 @@
+//! `AudioSource` から取れたものをそのまま書き出す録音機
+
+use std::{fs::File, io::BufWriter, path::PathBuf, time::Duration};
+
+use anyhow::{ensure, Context as _, Result};
+
+use crate::{
+    decode::Decoder, format::WaveFormatEx, loudness::LoudnessMeter, packet::CaptureStats,
+    plot::numbered_path, source::AudioSource, writer::WavWriter,
+};
+
+/// 録音したバイト列の書き出し先
+pub trait Sink {
+    /// 録音開始時に呼ばれる。途中でフォーマットが変わると `finish` の後に新しいフォーマットでまた呼ばれる
+    fn start(&mut self, wave_format: &WaveFormatEx) -> Result<()>;
+
+    fn write(&mut self, bytes: &[u8]) -> Result<()>;
+
+    /// 書き出した `frame` フレーム目の手前で音が途切れた。既定では何もしない
+    fn mark_gap(&mut self, _frame: u64, _label: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// 録音終了時と、フォーマットが変わって始め直す前に呼ばれる
+    fn finish(&mut self) -> Result<()>;
+}
+
+/// WAV ファイルに書き出す。途切れた位置はキューとして残る。
+/// フォーマットが変わったら `out.wav` の続きを `out_000001.wav` のような連番のファイルに書く
+pub struct WavFileSink {
+    path: PathBuf,
+    writer: Option<WavWriter<BufWriter<File>>>,
+    /// 書き始めたファイルの数
+    files: u64,
+}
+
+impl WavFileSink {
+    pub fn new(path: impl Into<PathBuf>) -> WavFileSink {
+        WavFileSink {
+            path: path.into(),
+            writer: None,
+            files: 0,
+        }
+    }
+}
+
+impl Sink for WavFileSink {
+    fn start(&mut self, wave_format: &WaveFormatEx) -> Result<()> {
+        let path = match self.files {
+            0 => self.path.clone(),
+            index => numbered_path(&self.path, index),
+        };
+        self.writer = Some(WavWriter::create(&path, wave_format)?);
+        self.files += 1;
+        Ok(())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.writer
+            .as_mut()
+            .context("Sink is not started.")?
+            .write(bytes)
+    }
+
+    fn mark_gap(&mut self, frame: u64, label: &str) -> Result<()> {
+        self.writer
+            .as_mut()
+            .context("Sink is not started.")?
+            .add_cue(frame, label);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        match self.writer.take() {
+            Some(writer) => writer.finalize(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// メモリに溜めるだけ。フォーマットが変わっても `data` は続けて溜める
+#[derive(Default)]
+pub struct MemorySink {
+    /// 最後に `start` されたフォーマット
+    pub wave_format: Option<WaveFormatEx>,
+    pub data: Vec<u8>,
+    /// `mark_gap` されたフレーム位置と説明
+    pub gaps: Vec<(u64, String)>,
+    pub finished: bool,
+}
+
+impl Sink for MemorySink {
+    fn start(&mut self, wave_format: &WaveFormatEx) -> Result<()> {
+        self.wave_format = Some(wave_format.clone());
+        self.finished = false;
+        Ok(())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.data.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn mark_gap(&mut self, frame: u64, label: &str) -> Result<()> {
+        self.gaps.push((frame, label.to_string()));
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for &mut S {
+    fn start(&mut self, wave_format: &WaveFormatEx) -> Result<()> {
+        (**self).start(wave_format)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        (**self).write(bytes)
+    }
+
+    fn mark_gap(&mut self, frame: u64, label: &str) -> Result<()> {
+        (**self).mark_gap(frame, label)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        (**self).finish()
+    }
+}
+
+/// 録音する長さの上限
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    /// 録音したサンプル数から計算した長さ。一時停止中は進まない
+    Duration(Duration),
+    Frames(u64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecorderState {
+    Idle,
+    Recording,
+    Paused,
+    Stopped,
+}
+
+pub struct Recorder<'a> {
+    source: Box<dyn AudioSource + 'a>,
+    sink: Box<dyn Sink + 'a>,
+    /// 今書いているフォーマット。ソースのフォーマットは読んだパケットより先に変わるので別に持つ
+    wave_format: WaveFormatEx,
+    limit: Option<Limit>,
+    state: RecorderState,
+    frames: u64,
+    /// 今のフォーマットで書き始めた時の `frames`
+    segment_frames: u64,
+    /// 前のフォーマットまでで書いた長さ
+    segment_offset: Duration,
+    poll_interval: Duration,
+    measure_loudness: bool,
+    loudness: Option<(Decoder, LoudnessMeter)>,
+    capture_stats: CaptureStats,
+}
+
+impl<'a> Recorder<'a> {
+    pub fn new(source: impl AudioSource + 'a, sink: impl Sink + 'a) -> Result<Recorder<'a>> {
+        ensure!(source.wave_format().block_align > 0, "Invalid block align.");
+        Ok(Recorder {
+            wave_format: source.wave_format().clone(),
+            source: Box::new(source),
+            sink: Box::new(sink),
+            limit: None,
+            state: RecorderState::Idle,
+            frames: 0,
+            segment_frames: 0,
+            segment_offset: Duration::ZERO,
+            poll_interval: Duration::from_millis(10),
+            measure_loudness: false,
+            loudness: None,
+            capture_stats: CaptureStats::default(),
+        })
+    }
+
+    pub fn with_limit(mut self, limit: Limit) -> Recorder<'a> {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// `run` でバッファが空だった時に待つ最長の時間。ソースが知らせてくれればそれより早く読む
+    pub fn with_poll_interval(mut self, interval: Duration) -> Recorder<'a> {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// 書き出した音のラウドネスも測る
+    pub fn with_loudness(mut self) -> Recorder<'a> {
+        self.measure_loudness = true;
+        self
+    }
+
+    /// `with_loudness` を付けて `start` した後なら、書き出した分のラウドネス。
+    /// 途中でフォーマットが変わっていれば、変わった後の分だけ
+    pub fn loudness(&self) -> Option<&LoudnessMeter> {
+        self.loudness.as_ref().map(|(_, meter)| meter)
+    }
+
+    pub fn state(&self) -> RecorderState {
+        self.state
+    }
+
+    /// 今書いているフォーマット
+    pub fn wave_format(&self) -> &WaveFormatEx {
+        &self.wave_format
+    }
+
+    /// 書き出したフレーム数
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// ソースから読んだパケットの取りこぼしなどのまとめ。一時停止中に読み捨てた分も数える
+    pub fn capture_stats(&self) -> &CaptureStats {
+        &self.capture_stats
+    }
+
+    /// 書き出した音声の長さ。途中でフォーマットが変わっていればそれぞれの長さを足す
+    pub fn recorded(&self) -> Duration {
+        let samples_per_sec = self.wave_format.samples_per_sec.max(1) as f64;
+        let frames = self.frames - self.segment_frames;
+        self.segment_offset + Duration::from_secs_f64(frames as f64 / samples_per_sec)
+    }
+
+    pub fn start(&mut self) -> Result<()> {
+        ensure!(
+            self.state == RecorderState::Idle,
+            "Recorder is already started."
+        );
+        if self.measure_loudness {
+            self.loudness = Some(new_loudness(&self.wave_format)?);
+        }
+        self.sink.start(&self.wave_format)?;
+        self.state = RecorderState::Recording;
+        Ok(())
+    }
+
+    pub fn pause(&mut self) {
+        if self.state == RecorderState::Recording {
+            self.state = RecorderState::Paused;
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if self.state == RecorderState::Paused {
+            self.state = RecorderState::Recording;
+        }
+    }
+
+    /// 溜まっている分を書き出す。上限に達するかソースが終わったら止めて `false` を返す
+    pub fn poll(&mut self) -> Result<bool> {
+        match self.state {
+            RecorderState::Idle | RecorderState::Stopped => return Ok(false),
+            RecorderState::Recording | RecorderState::Paused => {}
+        }
+        while let Some(packet) = self.source.get_packet()? {
+            if let Some(wave_format) = packet.format {
+                self.change_format(wave_format)?;
+            }
+            let before = self.capture_stats;
+            self.capture_stats.record(&packet.info);
+            // 一時停止中も読み捨てておかないと再開時に古い音が出てくる
+            if self.state == RecorderState::Paused {
+                continue;
+            }
+            if let Some(label) = gap_label(&before, &self.capture_stats) {
+                self.sink
+                    .mark_gap(self.frames - self.segment_frames, &label)?;
+            }
+            let block_align = self.wave_format.block_align as u64;
+            let buffer = packet.data;
+            let frames = buffer.len() as u64 / block_align;
+            let frames = match self.remaining_frames() {
+                Some(remaining) => frames.min(remaining),
+                None => frames,
+            };
+            let buffer = &buffer[..(frames * block_align) as usize];
+            self.sink.write(buffer)?;
+            if let Some((decoder, meter)) = &mut self.loudness {
+                meter.process(&decoder.decode(buffer));
+            }
+            self.frames += frames;
+            if self.remaining_frames() == Some(0) {
+                break;
+            }
+        }
+        if self.remaining_frames() == Some(0) || self.source.is_finished() {
+            self.stop()?;
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    pub fn stop(&mut self) -> Result<()> {
+        if matches!(self.state, RecorderState::Recording | RecorderState::Paused) {
+            self.state = RecorderState::Stopped;
+            self.sink.finish()?;
+        }
+        Ok(())
+    }
+
+    /// 上限に達するかソースが終わるまで録音する
+    pub fn run(&mut self) -> Result<()> {
+        if self.state == RecorderState::Idle {
+            self.start()?;
+        }
+        while self.poll()? {
+            self.source.wait(self.poll_interval)?;
+        }
+        Ok(())
+    }
+
+    /// 今のファイルを閉じて、新しいフォーマットで始め直す。ラウドネスも測り直す
+    fn change_format(&mut self, wave_format: WaveFormatEx) -> Result<()> {
+        ensure!(wave_format.block_align > 0, "Invalid block align.");
+        log::info!("Audio format changed. Starting a new segment: {wave_format:?}");
+        self.segment_offset = self.recorded();
+        self.segment_frames = self.frames;
+        if self.loudness.is_some() {
+            self.loudness = Some(new_loudness(&wave_format)?);
+        }
+        self.sink.finish()?;
+        self.sink.start(&wave_format)?;
+        self.wave_format = wave_format;
+        Ok(())
+    }
+
+    fn remaining_frames(&self) -> Option<u64> {
+        match self.limit? {
+            Limit::Frames(frames) => Some(frames.saturating_sub(self.frames)),
+            // 前のフォーマットまでの分を引いて、今のフォーマットのフレーム数にする
+            Limit::Duration(duration) => {
+                let samples_per_sec = self.wave_format.samples_per_sec as f64;
+                let remaining = duration.saturating_sub(self.segment_offset);
+                let limit = (remaining.as_secs_f64() * samples_per_sec).round() as u64;
+                Some(limit.saturating_sub(self.frames - self.segment_frames))
+            }
+        }
+    }
+}
+
+fn new_loudness(wave_format: &WaveFormatEx) -> Result<(Decoder, LoudnessMeter)> {
+    let decoder = Decoder::new(wave_format)?;
+    let meter = LoudnessMeter::new(decoder.channels(), wave_format.samples_per_sec)
+        .with_channel_mask(wave_format.channel_mask());
+    Ok((decoder, meter))
+}
+
+/// `before` から `after` までに増えた途切れの説明。途切れていなければ `None`
+fn gap_label(before: &CaptureStats, after: &CaptureStats) -> Option<String> {
+    if after.reconnects > before.reconnects {
+        Some("reconnect".to_string())
+    } else if after.gaps > before.gaps {
+        Some(format!(
+            "gap ({} frames lost)",
+            after.lost_frames - before.lost_frames
+        ))
+    } else if after.overruns > before.overruns {
+        Some("overrun".to_string())
+    } else {
+        None
+    }
+}
+
+impl Drop for Recorder<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.stop() {
+            log::error!("Failed to stop recorder: {e:?}");
+        }
+    }
+}
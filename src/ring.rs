@@ -0,0 +1,104 @@
+//! 容量固定のリングバッファ
+
+/// 容量を超えた分は古い方から上書きして、捨てた数を数えておく
+#[derive(Clone, Debug)]
+pub struct RingBuffer<T> {
+    buffer: Vec<T>,
+    start: usize,
+    len: usize,
+    overrun_samples: u64,
+    overruns: u64,
+}
+
+impl<T: Copy + Default> RingBuffer<T> {
+    pub fn new(capacity: usize) -> RingBuffer<T> {
+        RingBuffer {
+            buffer: vec![T::default(); capacity.max(1)],
+            start: 0,
+            len: 0,
+            overrun_samples: 0,
+            overruns: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 溢れて捨てたサンプルの総数
+    pub fn overrun_samples(&self) -> u64 {
+        self.overrun_samples
+    }
+
+    /// 溢れた回数 (`extend` 1 回につき高々 1)
+    pub fn overruns(&self) -> u64 {
+        self.overruns
+    }
+
+    pub fn extend(&mut self, values: &[T]) {
+        let capacity = self.capacity();
+        let overflow = (self.len + values.len()).saturating_sub(capacity);
+        if overflow > 0 {
+            self.overrun_samples += overflow as u64;
+            self.overruns += 1;
+        }
+        // 入りきらない分は先頭から捨てる
+        let values = &values[values.len().saturating_sub(capacity)..];
+        for &value in values {
+            let end = (self.start + self.len) % capacity;
+            self.buffer[end] = value;
+            if self.len == capacity {
+                self.start = (self.start + 1) % capacity;
+            } else {
+                self.len += 1;
+            }
+        }
+    }
+
+    /// 古い方から `n` 個捨てる
+    pub fn discard(&mut self, n: usize) {
+        let n = n.min(self.len);
+        self.start = (self.start + n) % self.capacity();
+        self.len -= n;
+    }
+
+    pub fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+    }
+
+    /// 古い方から `out.len()` 個コピーする。足りなければ `false`
+    pub fn copy_to(&self, out: &mut [T]) -> bool {
+        if out.len() > self.len {
+            return false;
+        }
+        for (i, value) in out.iter_mut().enumerate() {
+            *value = self.buffer[(self.start + i) % self.capacity()];
+        }
+        true
+    }
+
+    /// 新しい方から `out.len()` 個を古い順にコピーする。足りなければ `false`
+    pub fn copy_latest_to(&self, out: &mut [T]) -> bool {
+        if out.len() > self.len {
+            return false;
+        }
+        let skip = self.len - out.len();
+        for (i, value) in out.iter_mut().enumerate() {
+            *value = self.buffer[(self.start + skip + i) % self.capacity()];
+        }
+        true
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        (0..self.len).map(|i| self.buffer[(self.start + i) % self.capacity()])
+    }
+}
@@ -0,0 +1,133 @@
+//! 時間領域の表示 (オシロスコープ、ゴニオメーター) 用の計算
+
+use std::time::Duration;
+
+/// 一画面に表示する時間の範囲
+pub const MIN_TIMEBASE: Duration = Duration::from_millis(1);
+pub const MAX_TIMEBASE: Duration = Duration::from_secs(1);
+
+/// 立ち上がりでトリガーするオシロスコープ
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Oscilloscope {
+    timebase: Duration,
+    trigger_level: f32,
+}
+
+impl Default for Oscilloscope {
+    fn default() -> Self {
+        Oscilloscope {
+            timebase: Duration::from_millis(20),
+            trigger_level: 0.0,
+        }
+    }
+}
+
+/// `Oscilloscope::capture` の結果
+#[derive(Clone, Debug, PartialEq)]
+pub struct Capture {
+    /// チャンネル毎に `timebase` 分のサンプル
+    pub channels: Vec<Vec<f32>>,
+    /// トリガーが掛かったか。掛からなければ最新の分をそのまま出す
+    pub triggered: bool,
+}
+
+impl Oscilloscope {
+    pub fn new(timebase: Duration, trigger_level: f32) -> Oscilloscope {
+        Oscilloscope::default()
+            .with_timebase(timebase)
+            .with_trigger_level(trigger_level)
+    }
+
+    pub fn with_timebase(mut self, timebase: Duration) -> Oscilloscope {
+        self.set_timebase(timebase);
+        self
+    }
+
+    pub fn timebase(&self) -> Duration {
+        self.timebase
+    }
+
+    /// `MIN_TIMEBASE..=MAX_TIMEBASE` に収める
+    pub fn set_timebase(&mut self, timebase: Duration) {
+        self.timebase = timebase.clamp(MIN_TIMEBASE, MAX_TIMEBASE);
+    }
+
+    pub fn with_trigger_level(mut self, level: f32) -> Oscilloscope {
+        self.trigger_level = level;
+        self
+    }
+
+    pub fn trigger_level(&self) -> f32 {
+        self.trigger_level
+    }
+
+    pub fn set_trigger_level(&mut self, level: f32) {
+        self.trigger_level = level;
+    }
+
+    /// `timebase` 分のフレーム数
+    pub fn frames(&self, samples_per_sec: u32) -> usize {
+        ((self.timebase.as_secs_f64() * samples_per_sec as f64).round() as usize).max(1)
+    }
+
+    /// 後ろに `frames` 個取れる中で一番新しい立ち上がりの位置
+    pub fn trigger(&self, samples: &[f32], frames: usize) -> Option<usize> {
+        let last = samples.len().checked_sub(frames)?;
+        (1..=last)
+            .rev()
+            .find(|&i| samples[i - 1] < self.trigger_level && samples[i] >= self.trigger_level)
+    }
+
+    /// 最初のチャンネルでトリガーして `timebase` 分を切り出す。
+    /// 表示を安定させるには `timebase` の 2 倍くらいの履歴を渡す
+    pub fn capture(&self, channels: &[Vec<f32>], samples_per_sec: u32) -> Capture {
+        let Some(first) = channels.first() else {
+            return Capture {
+                channels: vec![],
+                triggered: false,
+            };
+        };
+        let frames = self.frames(samples_per_sec).min(first.len());
+        let (start, triggered) = match self.trigger(first, frames) {
+            Some(start) => (start, true),
+            None => (first.len() - frames, false),
+        };
+        Capture {
+            channels: channels
+                .iter()
+                .map(|samples| samples[start..start + frames].to_vec())
+                .collect(),
+            triggered,
+        }
+    }
+}
+
+/// ゴニオメーターの点。同相 (モノラル) は縦、逆相は横に並ぶように 45 度回す。
+/// 左だけの音は左上、右だけの音は右上に出る
+pub fn goniometer(left: &[f32], right: &[f32]) -> Vec<(f32, f32)> {
+    left.iter()
+        .zip(right)
+        .map(|(l, r)| {
+            let side = (r - l) * std::f32::consts::FRAC_1_SQRT_2;
+            let mid = (l + r) * std::f32::consts::FRAC_1_SQRT_2;
+            (side, mid)
+        })
+        .collect()
+}
+
+/// 左右の相関係数。+1 が同相、0 が無相関、-1 が逆相。無音なら 0
+pub fn correlation(left: &[f32], right: &[f32]) -> f64 {
+    let (mut lr, mut ll, mut rr) = (0.0, 0.0, 0.0);
+    for (&l, &r) in left.iter().zip(right) {
+        let (l, r) = (l as f64, r as f64);
+        lr += l * r;
+        ll += l * l;
+        rr += r * r;
+    }
+    let norm = (ll * rr).sqrt();
+    if norm > 0.0 {
+        lr / norm
+    } else {
+        0.0
+    }
+}
@@ -0,0 +1,200 @@
+//! スペクトルの時間方向の平滑化、ピークホールド、長時間平均
+
+use std::time::Duration;
+
+/// 指数移動平均の時定数。上がる時は `attack`、下がる時は `release` で追従する。
+/// 0 ならその向きには平滑化しない
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Ballistics {
+    pub attack: Duration,
+    pub release: Duration,
+}
+
+impl Ballistics {
+    pub fn new(attack: Duration, release: Duration) -> Ballistics {
+        Ballistics { attack, release }
+    }
+
+    /// `dt` 秒後に `current` から `target` にどこまで近づくか
+    fn step(&self, current: f64, target: f64, dt: f64) -> f64 {
+        let tau = if target > current {
+            self.attack
+        } else {
+            self.release
+        }
+        .as_secs_f64();
+        if tau <= 0.0 {
+            return target;
+        }
+        let keep = (-dt / tau).exp();
+        keep * current + (1.0 - keep) * target
+    }
+}
+
+/// ピークを `hold` の間そのまま残して、その後は `fall_rate` dB/s で落とす
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PeakHold {
+    pub hold: Duration,
+    pub fall_rate: f64,
+}
+
+impl Default for PeakHold {
+    fn default() -> Self {
+        PeakHold {
+            hold: Duration::from_secs(1),
+            fall_rate: 20.0,
+        }
+    }
+}
+
+impl PeakHold {
+    pub fn new(hold: Duration, fall_rate: f64) -> PeakHold {
+        PeakHold { hold, fall_rate }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Peak {
+    power: f64,
+    /// ピークを更新してからの秒数
+    age: f64,
+}
+
+/// パワーのスペクトルを受け取って平滑化、ピーク、平均の系列を作る。
+/// 帯域の並びが変わったら全部作り直す
+#[derive(Clone, Debug, Default)]
+pub struct Smoother {
+    ballistics: Ballistics,
+    peak_hold: PeakHold,
+    frequencies: Vec<Vec<f64>>,
+    smoothed: Vec<Vec<f64>>,
+    peaks: Vec<Vec<Peak>>,
+    sums: Vec<Vec<f64>>,
+    count: u64,
+}
+
+impl Smoother {
+    pub fn new(ballistics: Ballistics, peak_hold: PeakHold) -> Smoother {
+        Smoother {
+            ballistics,
+            peak_hold,
+            ..Default::default()
+        }
+    }
+
+    pub fn ballistics(&self) -> Ballistics {
+        self.ballistics
+    }
+
+    pub fn set_ballistics(&mut self, ballistics: Ballistics) {
+        self.ballistics = ballistics;
+    }
+
+    pub fn peak_hold(&self) -> PeakHold {
+        self.peak_hold
+    }
+
+    pub fn set_peak_hold(&mut self, peak_hold: PeakHold) {
+        self.peak_hold = peak_hold;
+    }
+
+    /// 平均に使ったフレーム数
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// ピークと平均を捨てる
+    pub fn reset(&mut self) {
+        self.frequencies.clear();
+        self.smoothed.clear();
+        self.peaks.clear();
+        self.sums.clear();
+        self.count = 0;
+    }
+
+    /// `dt` 秒進めて新しいスペクトルを取り込む
+    pub fn update(&mut self, spectra: &[Vec<(f64, f64)>], dt: f64) {
+        let same_layout = self.frequencies.len() == spectra.len()
+            && self
+                .frequencies
+                .iter()
+                .zip(spectra)
+                .all(|(frequencies, spectrum)| {
+                    frequencies.len() == spectrum.len()
+                        && frequencies.iter().zip(spectrum).all(|(a, (b, _))| a == b)
+                });
+        if !same_layout {
+            self.reset();
+            self.frequencies = spectra
+                .iter()
+                .map(|spectrum| spectrum.iter().map(|&(freq, _)| freq).collect())
+                .collect();
+            self.smoothed = spectra
+                .iter()
+                .map(|spectrum| spectrum.iter().map(|&(_, power)| power).collect())
+                .collect();
+            self.peaks = spectra
+                .iter()
+                .map(|spectrum| {
+                    spectrum
+                        .iter()
+                        .map(|&(_, power)| Peak { power, age: 0.0 })
+                        .collect()
+                })
+                .collect();
+            self.sums = self.smoothed.clone();
+            self.count = 1;
+            return;
+        }
+
+        let hold = self.peak_hold.hold.as_secs_f64();
+        for (i, spectrum) in spectra.iter().enumerate() {
+            for (j, &(_, power)) in spectrum.iter().enumerate() {
+                let smoothed = &mut self.smoothed[i][j];
+                *smoothed = self.ballistics.step(*smoothed, power, dt);
+
+                let peak = &mut self.peaks[i][j];
+                peak.age += dt;
+                if peak.age > hold {
+                    // hold を過ぎた分だけ落とす
+                    let falling = (peak.age - hold).min(dt);
+                    peak.power *= 10f64.powf(-self.peak_hold.fall_rate * falling / 10.0);
+                }
+                if power >= peak.power {
+                    *peak = Peak { power, age: 0.0 };
+                }
+
+                self.sums[i][j] += power;
+            }
+        }
+        self.count += 1;
+    }
+
+    pub fn smoothed(&self) -> Vec<Vec<(f64, f64)>> {
+        self.series(|i, j| self.smoothed[i][j])
+    }
+
+    pub fn peaks(&self) -> Vec<Vec<(f64, f64)>> {
+        self.series(|i, j| self.peaks[i][j].power)
+    }
+
+    /// 最後に `reset` してからの平均
+    pub fn average(&self) -> Vec<Vec<(f64, f64)>> {
+        let count = self.count.max(1) as f64;
+        self.series(|i, j| self.sums[i][j] / count)
+    }
+
+    fn series(&self, value: impl Fn(usize, usize) -> f64) -> Vec<Vec<(f64, f64)>> {
+        self.frequencies
+            .iter()
+            .enumerate()
+            .map(|(i, frequencies)| {
+                frequencies
+                    .iter()
+                    .enumerate()
+                    .map(|(j, &freq)| (freq, value(i, j)))
+                    .collect()
+            })
+            .collect()
+    }
+}
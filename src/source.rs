@@ -0,0 +1,331 @@
+//! 解析に流し込む音声の取得元
+
+use std::{
+    f32::consts::TAU,
+    fs::File,
+    io::{BufReader, Cursor, Read, Seek, SeekFrom},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, ensure, Context as _, Result};
+
+use crate::{format::WaveFormatEx, packet::Packet};
+
+/// `Client::get_buffer` と同じく、溜まっているフレームを引き出す形の音声ソース
+pub trait AudioSource {
+    fn wave_format(&self) -> &WaveFormatEx;
+
+    /// 溜まっているフレームをまとめて返す。何も無ければ `None`
+    fn get_buffer(&mut self) -> Result<Option<Vec<u8>>>;
+
+    /// `get_buffer` と同じだが、位置や時刻などの情報も付ける。既定ではフレーム数しか分からない
+    fn get_packet(&mut self) -> Result<Option<Packet>> {
+        let block_align = self.wave_format().block_align;
+        Ok(self
+            .get_buffer()?
+            .map(|data| Packet::new(data, block_align)))
+    }
+
+    /// これ以上フレームが出てこないなら `true`。ライブ入力は終わらない
+    fn is_finished(&self) -> bool {
+        false
+    }
+
+    /// 次のフレームが来そうになるか `timeout` が過ぎるまで待つ。既定では `timeout` だけ眠る
+    fn wait(&mut self, timeout: Duration) -> Result<()> {
+        std::thread::sleep(timeout);
+        Ok(())
+    }
+}
+
+impl<S: AudioSource + ?Sized> AudioSource for Box<S> {
+    fn wave_format(&self) -> &WaveFormatEx {
+        (**self).wave_format()
+    }
+
+    fn get_buffer(&mut self) -> Result<Option<Vec<u8>>> {
+        (**self).get_buffer()
+    }
+
+    fn get_packet(&mut self) -> Result<Option<Packet>> {
+        (**self).get_packet()
+    }
+
+    fn is_finished(&self) -> bool {
+        (**self).is_finished()
+    }
+
+    fn wait(&mut self, timeout: Duration) -> Result<()> {
+        (**self).wait(timeout)
+    }
+}
+
+impl<S: AudioSource + ?Sized> AudioSource for &mut S {
+    fn wave_format(&self) -> &WaveFormatEx {
+        (**self).wave_format()
+    }
+
+    fn get_buffer(&mut self) -> Result<Option<Vec<u8>>> {
+        (**self).get_buffer()
+    }
+
+    fn get_packet(&mut self) -> Result<Option<Packet>> {
+        (**self).get_packet()
+    }
+
+    fn is_finished(&self) -> bool {
+        (**self).is_finished()
+    }
+
+    fn wait(&mut self, timeout: Duration) -> Result<()> {
+        (**self).wait(timeout)
+    }
+}
+
+/// メモリ上のバイト列をパケットに切って返すソース
+pub struct MemorySource {
+    wave_format: WaveFormatEx,
+    data: Vec<u8>,
+    position: usize,
+    frames_per_packet: usize,
+    realtime: bool,
+    started_at: Option<Instant>,
+}
+
+impl MemorySource {
+    pub fn new(wave_format: WaveFormatEx, data: Vec<u8>) -> Result<MemorySource> {
+        ensure!(wave_format.block_align > 0, "Invalid block align.");
+        // 10ms 分ずつ返すのが WASAPI の共有モードに近い
+        let frames_per_packet = (wave_format.samples_per_sec as usize / 100).max(1);
+        Ok(MemorySource {
+            wave_format,
+            data,
+            position: 0,
+            frames_per_packet,
+            realtime: false,
+            started_at: None,
+        })
+    }
+
+    /// インターリーブ済みの f32 サンプルから 32bit float のソースを作る
+    pub fn from_f32(channels: u16, samples_per_sec: u32, samples: &[f32]) -> Result<MemorySource> {
+        let data = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        MemorySource::new(WaveFormatEx::ieee_float(channels, samples_per_sec), data)
+    }
+
+    /// 全チャンネル同じ正弦波
+    pub fn sine(
+        frequency: f32,
+        amplitude: f32,
+        channels: u16,
+        samples_per_sec: u32,
+        duration: Duration,
+    ) -> Result<MemorySource> {
+        let frames = (duration.as_secs_f64() * samples_per_sec as f64) as usize;
+        let samples = (0..frames)
+            .flat_map(|n| {
+                let value = amplitude * (TAU * frequency * n as f32 / samples_per_sec as f32).sin();
+                std::iter::repeat_n(value, channels as usize)
+            })
+            .collect::<Vec<_>>();
+        MemorySource::from_f32(channels, samples_per_sec, &samples)
+    }
+
+    /// 1 回の `get_buffer` で返す最大フレーム数
+    pub fn with_frames_per_packet(mut self, frames: usize) -> MemorySource {
+        self.frames_per_packet = frames.max(1);
+        self
+    }
+
+    /// 実時間で進んだ分しか返さないようにする
+    pub fn with_realtime(mut self, realtime: bool) -> MemorySource {
+        self.realtime = realtime;
+        self
+    }
+
+    fn available_frames(&mut self) -> usize {
+        let block_align = self.wave_format.block_align as usize;
+        let remaining = (self.data.len() - self.position) / block_align;
+        if !self.realtime {
+            return remaining;
+        }
+        let started_at = *self.started_at.get_or_insert_with(Instant::now);
+        let due =
+            (started_at.elapsed().as_secs_f64() * self.wave_format.samples_per_sec as f64) as usize;
+        due.saturating_sub(self.position / block_align)
+            .min(remaining)
+    }
+}
+
+impl AudioSource for MemorySource {
+    fn wave_format(&self) -> &WaveFormatEx {
+        &self.wave_format
+    }
+
+    fn get_buffer(&mut self) -> Result<Option<Vec<u8>>> {
+        let frames = self.available_frames().min(self.frames_per_packet);
+        if frames == 0 {
+            return Ok(None);
+        }
+        let end = self.position + frames * self.wave_format.block_align as usize;
+        let buffer = self.data[self.position..end].to_vec();
+        self.position = end;
+        Ok(Some(buffer))
+    }
+
+    fn is_finished(&self) -> bool {
+        self.data.len() - self.position < self.wave_format.block_align as usize
+    }
+}
+
+/// WAV ファイルのソース。ヘッダのチャンクだけ先に読み、`data` チャンクはパケット毎に少しずつ読むので、
+/// 何時間もある RF64 でもメモリはパケット分しか使わない
+pub struct WavSource<R = BufReader<File>> {
+    reader: R,
+    wave_format: WaveFormatEx,
+    /// `data` チャンクのまだ読んでいないバイト数
+    remaining: u64,
+    /// 読んだバイト数
+    position: u64,
+    frames_per_packet: usize,
+    realtime: bool,
+    started_at: Option<Instant>,
+}
+
+impl WavSource {
+    pub fn open(path: impl AsRef<Path>) -> Result<WavSource> {
+        let file = File::open(path.as_ref()).context("Failed to read wav file.")?;
+        WavSource::new(BufReader::new(file))
+    }
+}
+
+impl WavSource<Cursor<Vec<u8>>> {
+    /// メモリ上の WAV ファイル
+    pub fn from_bytes(bytes: &[u8]) -> Result<WavSource<Cursor<Vec<u8>>>> {
+        WavSource::new(Cursor::new(bytes.to_vec()))
+    }
+}
+
+impl<R: Read + Seek> WavSource<R> {
+    /// `reader` のヘッダを読んで `data` チャンクの先頭まで進める
+    pub fn new(mut reader: R) -> Result<WavSource<R>> {
+        let len = reader
+            .seek(SeekFrom::End(0))
+            .context("Failed to read wav file.")?;
+        reader.seek(SeekFrom::Start(0))?;
+        let mut header = [0; 12];
+        ensure!(
+            reader.read_exact(&mut header).is_ok()
+                && (&header[0..4] == b"RIFF" || &header[0..4] == b"RF64")
+                && &header[8..12] == b"WAVE",
+            "Not a RIFF/WAVE file."
+        );
+        let mut wave_format = None;
+        // `data` チャンクの中身の位置と長さ
+        let mut data = None;
+        // RF64 の場合 data チャンクのサイズは ds64 に入っている
+        let mut ds64_data_size = None;
+        let mut offset = 12;
+        loop {
+            let mut chunk = [0; 8];
+            if reader.read_exact(&mut chunk).is_err() {
+                break;
+            }
+            let id = &chunk[0..4];
+            let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as u64;
+            let start = offset + 8;
+            let size = match (id, ds64_data_size) {
+                (b"data", Some(ds64_size)) if size == u32::MAX as u64 => ds64_size,
+                _ => size,
+            }
+            .min(len.saturating_sub(start));
+            match id {
+                b"ds64" if size >= 16 => {
+                    let mut sizes = [0; 16];
+                    reader.read_exact(&mut sizes)?;
+                    let mut data_size = [0; 8];
+                    data_size.copy_from_slice(&sizes[8..16]);
+                    ds64_data_size = Some(u64::from_le_bytes(data_size));
+                }
+                b"fmt " => {
+                    let mut body = vec![0; size as usize];
+                    reader.read_exact(&mut body)?;
+                    wave_format = Some(WaveFormatEx::from_bytes(&body)?);
+                }
+                b"data" => data = Some((start, size)),
+                _ => {}
+            }
+            // チャンクは偶数バイトに揃えられている
+            offset = start + size + (size & 1);
+            if offset >= len {
+                break;
+            }
+            reader.seek(SeekFrom::Start(offset))?;
+        }
+        let Some(wave_format) = wave_format else {
+            bail!("Missing fmt chunk.");
+        };
+        let (start, size) = data.context("Missing data chunk.")?;
+        ensure!(wave_format.block_align > 0, "Invalid block align.");
+        reader.seek(SeekFrom::Start(start))?;
+        // 10ms 分ずつ返すのが WASAPI の共有モードに近い
+        let frames_per_packet = (wave_format.samples_per_sec as usize / 100).max(1);
+        Ok(WavSource {
+            reader,
+            wave_format,
+            remaining: size,
+            position: 0,
+            frames_per_packet,
+            realtime: false,
+            started_at: None,
+        })
+    }
+
+    pub fn with_frames_per_packet(mut self, frames: usize) -> WavSource<R> {
+        self.frames_per_packet = frames.max(1);
+        self
+    }
+
+    pub fn with_realtime(mut self, realtime: bool) -> WavSource<R> {
+        self.realtime = realtime;
+        self
+    }
+
+    fn available_frames(&mut self) -> u64 {
+        let block_align = self.wave_format.block_align as u64;
+        let remaining = self.remaining / block_align;
+        if !self.realtime {
+            return remaining;
+        }
+        let started_at = *self.started_at.get_or_insert_with(Instant::now);
+        let due =
+            (started_at.elapsed().as_secs_f64() * self.wave_format.samples_per_sec as f64) as u64;
+        due.saturating_sub(self.position / block_align)
+            .min(remaining)
+    }
+}
+
+impl<R: Read + Seek> AudioSource for WavSource<R> {
+    fn wave_format(&self) -> &WaveFormatEx {
+        &self.wave_format
+    }
+
+    fn get_buffer(&mut self) -> Result<Option<Vec<u8>>> {
+        let frames = self.available_frames().min(self.frames_per_packet as u64);
+        if frames == 0 {
+            return Ok(None);
+        }
+        let mut buffer = vec![0; frames as usize * self.wave_format.block_align as usize];
+        self.reader
+            .read_exact(&mut buffer)
+            .context("Failed to read wav file.")?;
+        self.remaining -= buffer.len() as u64;
+        self.position += buffer.len() as u64;
+        Ok(Some(buffer))
+    }
+
+    fn is_finished(&self) -> bool {
+        self.remaining < self.wave_format.block_align as u64
+    }
+}
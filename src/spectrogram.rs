@@ -0,0 +1,110 @@
+//! スペクトルの履歴を画像にする
+
+use std::collections::VecDeque;
+
+use crate::colormap::Colormap;
+
+/// 描き方
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rendering {
+    /// 横軸の周波数の範囲
+    pub frequency_range: (f64, f64),
+    /// 横軸を対数にする
+    pub log_frequency: bool,
+    /// `colormap` の両端に対応する値
+    pub value_range: (f64, f64),
+    pub colormap: Colormap,
+}
+
+/// 新しい方から `capacity` 個までのスペクトル
+#[derive(Clone, Debug)]
+pub struct Spectrogram {
+    capacity: usize,
+    rows: VecDeque<Vec<(f64, f64)>>,
+}
+
+impl Spectrogram {
+    pub fn new(capacity: usize) -> Spectrogram {
+        Spectrogram {
+            capacity: capacity.max(1),
+            rows: VecDeque::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.rows.clear();
+    }
+
+    pub fn push(&mut self, spectrum: Vec<(f64, f64)>) {
+        if self.rows.len() == self.capacity {
+            self.rows.pop_back();
+        }
+        self.rows.push_front(spectrum);
+    }
+
+    /// 新しいものから順に
+    pub fn rows(&self) -> impl Iterator<Item = &[(f64, f64)]> {
+        self.rows.iter().map(Vec::as_slice)
+    }
+
+    /// `width` x `height` の `0x00RRGGBB` の画像にする。一番上が最新で、
+    /// 縦は `capacity` 行分を引き伸ばす。まだ無い行は黒
+    pub fn render(&self, width: usize, height: usize, rendering: &Rendering) -> Vec<u32> {
+        let (min, max) = rendering.frequency_range;
+        let to_axis = |freq: f64| {
+            if rendering.log_frequency {
+                freq.max(f64::MIN_POSITIVE).log10()
+            } else {
+                freq
+            }
+        };
+        let (axis_min, axis_max) = (to_axis(min), to_axis(max));
+        let columns = (0..width)
+            .map(|x| axis_min + (axis_max - axis_min) * (x as f64 + 0.5) / width as f64)
+            .collect::<Vec<_>>();
+        let (low, high) = rendering.value_range;
+
+        let mut image = vec![0; width * height];
+        for (y, line) in image.chunks_exact_mut(width.max(1)).enumerate() {
+            let Some(row) = self.rows.get(y * self.capacity / height) else {
+                continue;
+            };
+            if row.is_empty() {
+                continue;
+            }
+            let centers = row
+                .iter()
+                .map(|&(freq, _)| to_axis(freq))
+                .collect::<Vec<_>>();
+            for (pixel, &axis) in line.iter_mut().zip(&columns) {
+                let value = row[nearest(&centers, axis)].1;
+                *pixel = rendering.colormap.color((value - low) / (high - low));
+            }
+        }
+        image
+    }
+}
+
+/// 昇順の `centers` で `axis` に一番近いものの位置
+fn nearest(centers: &[f64], axis: f64) -> usize {
+    let index = centers.partition_point(|&center| center < axis);
+    if index == 0 {
+        0
+    } else if index == centers.len() || axis - centers[index - 1] < centers[index] - axis {
+        index - 1
+    } else {
+        index
+    }
+}
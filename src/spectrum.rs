@@ -0,0 +1,215 @@
+//! FFT の結果を表示用の帯域にまとめる
+
+use std::str::FromStr;
+
+use anyhow::{bail, Context as _, Error};
+
+use crate::fft::Fft;
+
+/// 1 つの帯域。`low..high` に入るパワーを合計して `center` の値とする
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Band {
+    pub center: f64,
+    pub low: f64,
+    pub high: f64,
+}
+
+/// スペクトルのまとめ方
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Binning {
+    /// 等間隔に `bins` 個
+    Linear { bins: usize },
+    /// 対数で等間隔に `bins` 個
+    Log { bins: usize },
+    /// ISO 266 の 1/1 オクターブバンド
+    Octave,
+    /// ISO 266 の 1/3 オクターブバンド
+    #[default]
+    ThirdOctave,
+}
+
+impl Binning {
+    /// `min..max` に収まる帯域を低い方から並べる
+    pub fn bands(&self, min: f64, max: f64) -> Vec<Band> {
+        match *self {
+            Binning::Linear { bins } => {
+                let width = (max - min) / bins.max(1) as f64;
+                (0..bins)
+                    .map(|i| {
+                        let low = min + width * i as f64;
+                        Band {
+                            center: low + width / 2.0,
+                            low,
+                            high: low + width,
+                        }
+                    })
+                    .collect()
+            }
+            Binning::Log { bins } => {
+                let ratio = (max / min).powf(1.0 / bins.max(1) as f64);
+                (0..bins)
+                    .map(|i| {
+                        let low = min * ratio.powi(i as i32);
+                        Band {
+                            center: low * ratio.sqrt(),
+                            low,
+                            high: low * ratio,
+                        }
+                    })
+                    .collect()
+            }
+            Binning::Octave => fractional_octave_bands(1, min, max),
+            Binning::ThirdOctave => fractional_octave_bands(3, min, max),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Binning::Linear { bins } => format!("linear:{bins}"),
+            Binning::Log { bins } => format!("log:{bins}"),
+            Binning::Octave => "octave".to_string(),
+            Binning::ThirdOctave => "third-octave".to_string(),
+        }
+    }
+
+    /// 帯域の間隔が対数的か。表示で横軸を対数にするかどうかに使う
+    pub fn is_logarithmic(&self) -> bool {
+        !matches!(self, Binning::Linear { .. })
+    }
+}
+
+/// `linear`, `linear:128`, `log:64`, `octave`, `third-octave` のような書き方
+impl FromStr for Binning {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, bins) = match s.split_once(':') {
+            Some((kind, bins)) => {
+                let bins = bins
+                    .parse::<usize>()
+                    .with_context(|| format!("Invalid number of bins: {bins}"))?;
+                (kind, Some(bins))
+            }
+            None => (s, None),
+        };
+        let binning = match (kind.to_ascii_lowercase().as_str(), bins) {
+            ("linear", bins) => Binning::Linear {
+                bins: bins.unwrap_or(64),
+            },
+            ("log", bins) => Binning::Log {
+                bins: bins.unwrap_or(64),
+            },
+            ("octave", None) => Binning::Octave,
+            ("third-octave", None) => Binning::ThirdOctave,
+            _ => bail!("Unknown binning: {s}"),
+        };
+        if let Binning::Linear { bins: 0 } | Binning::Log { bins: 0 } = binning {
+            bail!("Number of bins must be positive.");
+        }
+        Ok(binning)
+    }
+}
+
+/// 1kHz を基準に 2 の `1 / fraction` 乗ずつ並べた帯域 (IEC 61260 の base-2)。
+/// 公称値の 20Hz (19.7Hz) や 20kHz (20.2kHz) も含めたいので範囲は 2% 緩く見る
+fn fractional_octave_bands(fraction: i32, min: f64, max: f64) -> Vec<Band> {
+    let step = 2f64.powf(1.0 / fraction as f64);
+    let half = step.sqrt();
+    let tolerance = fraction as f64 * 1.02f64.log2();
+    let first = (fraction as f64 * (min / 1_000.0).log2() - tolerance).ceil() as i32;
+    let last = (fraction as f64 * (max / 1_000.0).log2() + tolerance).floor() as i32;
+    (first..=last)
+        .map(|i| {
+            let center = 1_000.0 * step.powi(i);
+            Band {
+                center,
+                low: center / half,
+                high: center * half,
+            }
+        })
+        .collect()
+}
+
+/// `power[k]` を `k * bin_width` Hz のパワーとして帯域毎に合計する。
+/// FFT のビンは `±bin_width / 2` の幅を持つとして、帯域と重なった割合だけ足す
+pub fn bin_power(power: &[f64], bin_width: f64, bands: &[Band]) -> Vec<(f64, f64)> {
+    bands
+        .iter()
+        .map(|band| {
+            let first = ((band.low / bin_width) - 0.5).floor().max(0.0) as usize;
+            let last = (((band.high / bin_width) + 0.5).ceil() as usize).min(power.len());
+            let sum = (first..last)
+                .map(|k| {
+                    let low = (k as f64 - 0.5) * bin_width;
+                    let high = (k as f64 + 0.5) * bin_width;
+                    let overlap = high.min(band.high) - low.max(band.low);
+                    power[k] * (overlap / bin_width).max(0.0)
+                })
+                .sum::<f64>();
+            (band.center, sum)
+        })
+        .collect()
+}
+
+/// 窓を掛けて FFT した 0Hz からナイキスト周波数までのビン毎のパワー。
+/// 振幅 A の正弦波が周りのビンの合計で A² になるように、窓のコヒーレントゲインと
+/// 等価雑音帯域幅で補正してある (フルスケールの正弦波が 0dBFS)
+pub fn power_spectrum(fft: &Fft, samples: &[f32], window: &[f32]) -> Vec<f64> {
+    let windowed = samples
+        .iter()
+        .zip(window)
+        .map(|(sample, w)| sample * w)
+        .collect::<Vec<_>>();
+    let magnitudes = fft.magnitudes(&windowed);
+
+    let size = samples.len() as f64;
+    let coherent_gain = window.iter().map(|&w| w as f64).sum::<f64>() / size;
+    let enbw =
+        window.iter().map(|&w| (w as f64).powi(2)).sum::<f64>() / (size * coherent_gain.powi(2));
+    let last = magnitudes.len() - 1;
+    magnitudes
+        .iter()
+        .enumerate()
+        .map(|(k, magnitude)| {
+            // 片側スペクトルなので DC とナイキスト以外は負の周波数の分を足す
+            let one_sided = if k == 0 || k == last { 1.0 } else { 2.0 };
+            let amplitude = one_sided * magnitude / (size * coherent_gain);
+            amplitude.powi(2) / enbw
+        })
+        .collect()
+}
+
+/// 表示用の値への変換
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scale {
+    /// `power_spectrum` のパワーそのまま
+    Power,
+    /// dBFS から `reference` を引いた値。`floor` より下は `floor` に揃える
+    Decibel { reference: f64, floor: f64 },
+}
+
+impl Default for Scale {
+    fn default() -> Self {
+        Scale::Decibel {
+            reference: 0.0,
+            floor: -120.0,
+        }
+    }
+}
+
+impl Scale {
+    pub fn apply(&self, power: f64) -> f64 {
+        match *self {
+            Scale::Power => power,
+            Scale::Decibel { reference, floor } => (10.0 * power.log10() - reference).max(floor),
+        }
+    }
+
+    /// 表示する縦軸の範囲。dB ならフルスケール (0 dBFS) の `-reference` までにする
+    pub fn range(&self) -> (f64, f64) {
+        match *self {
+            Scale::Power => (0.0, 1.0),
+            Scale::Decibel { reference, floor } => (floor, -reference),
+        }
+    }
+}
@@ -1,236 +1,460 @@
-use std::{collections::VecDeque, mem::ManuallyDrop, ops::Deref, time::Duration};
+use std::time::Duration;
 
-use anyhow::{ensure, Context as _, Result};
-use spectrum_analyzer::{
-    samples_fft_to_spectrum, scaling::divide_by_N, windows::hann_window, FrequencyLimit,
-};
-use windows::Win32::{
-    Devices::FunctionDiscovery::PKEY_Device_FriendlyName,
-    Media::Audio::{
-        eConsole, eRender, IAudioCaptureClient, IAudioClient, IMMDevice, IMMDeviceEnumerator,
-        MMDeviceEnumerator, AUDCLNT_SHAREMODE_SHARED, AUDCLNT_STREAMFLAGS_LOOPBACK, WAVEFORMATEX,
-    },
-    System::Com::{
-        CoCreateInstance, CoInitializeEx, CoUninitialize, CLSCTX_ALL, COINIT_MULTITHREADED,
-        STGM_READ,
-    },
-};
+use anyhow::Result;
 
-pub fn get_device() -> Result<IMMDevice> {
-    unsafe {
-        let enumerator: IMMDeviceEnumerator =
-            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)
-                .context("Failed to create device enumerator.")?;
+use crate::{
+    channel::ChannelMix,
+    decode::Decoder,
+    fft::Fft,
+    loudness::LoudnessMeter,
+    packet::CaptureStats,
+    ring::RingBuffer,
+    smoothing::{Ballistics, PeakHold, Smoother},
+    source::AudioSource,
+    spectrogram::Spectrogram,
+    spectrum::{bin_power, power_spectrum, Binning, Scale},
+    window::Window,
+};
 
-        let device = enumerator
-            .GetDefaultAudioEndpoint(eRender, eConsole)
-            .context("Failed to get default audio endpoint.")?;
-        Ok(device)
-    }
-}
+const DEFAULT_FFT_SIZE: usize = 2048;
+/// 既定で保持しておくサンプル数
+const HISTORY: usize = DEFAULT_FFT_SIZE * 16;
+/// 波形の表示用に残しておく秒数
+const WAVEFORM_SECONDS: usize = 2;
+/// 既定で保持しておくスペクトログラムの行数
+const SPECTROGRAM_ROWS: usize = 256;
 
-pub fn get_device_name(device: &IMMDevice) -> Result<String> {
-    unsafe {
-        let store = device.OpenPropertyStore(STGM_READ)?;
-        let value = store.GetValue(&PKEY_Device_FriendlyName)?;
-        Ok(value.to_string())
-    }
+/// サンプル履歴の状況
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HistoryStats {
+    /// 解析した STFT フレームの数
+    pub analyzed_frames: u64,
+    /// 履歴が溢れた回数
+    pub overruns: u64,
+    /// 溢れて解析されずに捨てたサンプル数
+    pub overrun_samples: u64,
+    /// 解析待ちのサンプル数
+    pub buffered: usize,
 }
 
-const SIZE: usize = 2048;
-
 pub struct App {
     name: String,
-    client: Client,
-    samples: VecDeque<f32>,
-    data: Vec<(f64, f64)>,
+    source: Box<dyn AudioSource>,
+    history: usize,
+    channels: Vec<RingBuffer<f32>>,
+    /// 解析とは別に残しておく最新のサンプル
+    waveform: Vec<RingBuffer<f32>>,
+    mix: ChannelMix,
+    binning: Binning,
+    scale: Scale,
+    frequency_range: (f64, f64),
+    hop: Option<usize>,
+    fft: Fft,
+    window: Window,
+    coefficients: Vec<f32>,
+    frames: Vec<Vec<f32>>,
+    analyzed_frames: u64,
+    spectra: Vec<Vec<(f64, f64)>>,
+    smoother: Smoother,
+    spectrogram: Spectrogram,
+    loudness: Option<LoudnessMeter>,
+    capture_stats: CaptureStats,
 }
 
 impl App {
-    pub fn new(name: String, client: Client) -> App {
+    pub fn new(name: String, source: impl AudioSource + 'static) -> App {
         App {
             name,
-            client,
-            data: Default::default(),
-            samples: VecDeque::with_capacity(SIZE),
+            source: Box::new(source),
+            history: HISTORY,
+            channels: vec![],
+            waveform: vec![],
+            mix: ChannelMix::default(),
+            binning: Binning::default(),
+            scale: Scale::default(),
+            frequency_range: (20.0, 20_000.0),
+            hop: None,
+            fft: Fft::new(DEFAULT_FFT_SIZE).unwrap(),
+            window: Window::default(),
+            coefficients: Window::default().coefficients(DEFAULT_FFT_SIZE),
+            frames: vec![],
+            analyzed_frames: 0,
+            spectra: vec![],
+            smoother: Smoother::default(),
+            spectrogram: Spectrogram::new(SPECTROGRAM_ROWS),
+            loudness: None,
+            capture_stats: CaptureStats::default(),
         }
     }
 
-    pub fn on_tick(&mut self) {
-        let format = self.client.wave_format();
-        while let Some(buffer) = self.client.get_buffer().expect("Failed to get buffer.") {
-            self.samples.extend(
-                buffer
-                    .chunks(format.block_align as usize)
-                    .map(|frame| i16::from_ne_bytes([frame[0], frame[1]]) as f32),
-            );
-        }
-        if self.samples.len() < SIZE {
-            return;
+    /// 解析待ちとして保持するチャンネル毎のサンプル数。UI が止まってもこれ以上は溜めない。
+    /// FFT サイズの 2 倍より小さくはならない
+    pub fn with_history(mut self, samples: usize) -> App {
+        self.history = samples;
+        self.channels.clear();
+        self
+    }
+
+    /// STFT のフレームを進める幅。FFT サイズより小さければ重なる。既定は FFT サイズの半分
+    pub fn with_hop(mut self, hop: usize) -> App {
+        self.hop = Some(hop.max(1));
+        self
+    }
+
+    pub fn hop(&self) -> usize {
+        self.hop.unwrap_or(self.fft.size() / 2).min(self.fft.size())
+    }
+
+    /// STFT のフレームの間隔 (秒)
+    pub fn frame_interval(&self) -> f64 {
+        self.hop() as f64 / self.source.wave_format().samples_per_sec as f64
+    }
+
+    /// スペクトログラムとして残しておくスペクトルの数
+    pub fn with_spectrogram_rows(mut self, rows: usize) -> App {
+        self.spectrogram = Spectrogram::new(rows);
+        self
+    }
+
+    pub fn with_fft_size(mut self, size: usize) -> Result<App> {
+        self.set_fft_size(size)?;
+        Ok(self)
+    }
+
+    pub fn fft_size(&self) -> usize {
+        self.fft.size()
+    }
+
+    /// 256 から 65536 までの 2 の冪。溜まっていたサンプルは捨てる
+    pub fn set_fft_size(&mut self, size: usize) -> Result<()> {
+        if size == self.fft.size() {
+            return Ok(());
         }
-        let skips = self.samples.len().saturating_sub(SIZE);
-        let samples = self.samples.drain(skips..skips + SIZE).collect::<Vec<_>>();
-
-        let samples = hann_window(&samples);
-        let res = samples_fft_to_spectrum(
-            &samples,
-            format.samples_per_sec,
-            FrequencyLimit::Range(60f32, 15_000f32),
-            Some(&divide_by_N),
-        )
-        .unwrap();
-        self.data = (1..70)
-            .into_iter()
-            .map(|freq| freq as f32 * 200.0)
-            .map(|freq| (freq as f64, res.freq_val_exact(freq).val().powi(2) as f64))
-            .collect();
-        // self.data = res
-        //     .data()
-        //     .into_iter()
-        //     .map(|(freq, data)| (freq.val() as f64, (data.val() as f64)))
-        //     .collect::<Vec<_>>();
+        self.fft = Fft::new(size)?;
+        self.coefficients = self.window.coefficients(size);
+        self.channels.clear();
+        Ok(())
     }
 
-    pub fn data(&self) -> &[(f64, f64)] {
-        &self.data
+    pub fn with_window(mut self, window: Window) -> App {
+        self.set_window(window);
+        self
     }
-}
 
-pub struct Client {
-    device: IMMDevice,
-    audio_client: IAudioClient,
-    capture_client: IAudioCaptureClient,
-    wave_format: WaveFormatEx,
-}
+    pub fn window(&self) -> Window {
+        self.window
+    }
 
-impl Client {
-    pub fn new(device: IMMDevice) -> Result<Client> {
-        unsafe {
-            let audio_client: IAudioClient = device
-                .Activate(CLSCTX_ALL, None)
-                .context("Failed to activate audio client.")?;
-
-            let wave_format = audio_client
-                .GetMixFormat()
-                .context("Failed to get mix format.")?;
-
-            let buffered_duration = Duration::from_secs(10);
-
-            audio_client
-                .Initialize(
-                    AUDCLNT_SHAREMODE_SHARED,
-                    AUDCLNT_STREAMFLAGS_LOOPBACK,
-                    buffered_duration.as_micros() as i64,
-                    0,
-                    wave_format,
-                    None,
-                )
-                .context("Failed to initialize audio client.")?;
-            let wave_format: WaveFormatEx = (*wave_format).into();
-
-            let capture_client: IAudioCaptureClient = audio_client
-                .GetService()
-                .context("Failed to get capture client.")?;
-
-            audio_client
-                .Start()
-                .context("Failed to start audio client.")?;
-            Ok(Client {
-                device,
-                audio_client,
-                capture_client,
-                wave_format,
-            })
+    pub fn set_window(&mut self, window: Window) {
+        self.window = window;
+        self.coefficients = window.coefficients(self.fft.size());
+    }
+
+    pub fn samples_per_sec(&self) -> u32 {
+        self.source.wave_format().samples_per_sec
+    }
+
+    /// FFT のビン 1 つ分の周波数
+    pub fn frequency_resolution(&self) -> f64 {
+        self.source.wave_format().samples_per_sec as f64 / self.fft.size() as f64
+    }
+
+    pub fn with_mix(mut self, mix: ChannelMix) -> App {
+        self.mix = mix;
+        self
+    }
+
+    pub fn mix(&self) -> ChannelMix {
+        self.mix
+    }
+
+    pub fn set_mix(&mut self, mix: ChannelMix) {
+        self.mix = mix;
+    }
+
+    pub fn with_binning(mut self, binning: Binning) -> App {
+        self.binning = binning;
+        self
+    }
+
+    pub fn binning(&self) -> Binning {
+        self.binning
+    }
+
+    pub fn set_binning(&mut self, binning: Binning) {
+        self.binning = binning;
+    }
+
+    pub fn with_scale(mut self, scale: Scale) -> App {
+        self.scale = scale;
+        self
+    }
+
+    pub fn scale(&self) -> Scale {
+        self.scale
+    }
+
+    pub fn set_scale(&mut self, scale: Scale) {
+        self.scale = scale;
+    }
+
+    /// `smoothed` の追従の速さ
+    pub fn with_ballistics(mut self, ballistics: Ballistics) -> App {
+        self.smoother.set_ballistics(ballistics);
+        self
+    }
+
+    pub fn ballistics(&self) -> Ballistics {
+        self.smoother.ballistics()
+    }
+
+    pub fn set_ballistics(&mut self, ballistics: Ballistics) {
+        self.smoother.set_ballistics(ballistics);
+    }
+
+    pub fn with_peak_hold(mut self, peak_hold: PeakHold) -> App {
+        self.smoother.set_peak_hold(peak_hold);
+        self
+    }
+
+    pub fn peak_hold(&self) -> PeakHold {
+        self.smoother.peak_hold()
+    }
+
+    pub fn set_peak_hold(&mut self, peak_hold: PeakHold) {
+        self.smoother.set_peak_hold(peak_hold);
+    }
+
+    /// ピークと長時間平均を捨ててやり直す
+    pub fn reset_smoothing(&mut self) {
+        self.smoother.reset();
+    }
+
+    /// 帯域を作る範囲。上はナイキスト周波数で頭打ちになる
+    pub fn with_frequency_range(mut self, min: f64, max: f64) -> App {
+        self.frequency_range = (min.max(f64::MIN_POSITIVE), max);
+        self
+    }
+
+    /// 溜まっているパケットを全部読んで解析する。デバイスが無くなった時などはエラーを返す
+    pub fn on_tick(&mut self) -> Result<()> {
+        let mut decoder = self.prepare()?;
+        while let Some(packet) = self.source.get_packet()? {
+            if packet.format.is_some() {
+                decoder = self.reset_format()?;
+            }
+            self.capture_stats.record(&packet.info);
+            self.push(&decoder, &packet.data);
         }
+        self.analyze_frames(&mut |_| Ok(()))
     }
 
-    pub fn wave_format(&self) -> &WaveFormatEx {
-        &self.wave_format
+    /// ソースが終わるまで 1 パケットずつ読んで、STFT のフレームを解析する度に `on_frame` を呼ぶ。
+    /// ファイルのようにすぐに読み切れるソース向け
+    pub fn analyze_all(&mut self, mut on_frame: impl FnMut(&App) -> Result<()>) -> Result<()> {
+        let mut decoder = self.prepare()?;
+        loop {
+            match self.source.get_packet()? {
+                Some(packet) => {
+                    if packet.format.is_some() {
+                        decoder = self.reset_format()?;
+                    }
+                    self.capture_stats.record(&packet.info);
+                    self.push(&decoder, &packet.data);
+                    self.analyze_frames(&mut on_frame)?;
+                }
+                None if self.source.is_finished() => return Ok(()),
+                None => self.source.wait(Duration::from_millis(10))?,
+            }
+        }
     }
 
-    pub fn get_buffer(&self) -> Result<Option<Vec<u8>>> {
-        unsafe {
-            let frames = self
-                .audio_client
-                .GetCurrentPadding()
-                .context("Failed to get current padding.")?;
-            if frames == 0 {
-                return Ok(None);
+    /// チャンネル数が変わっていたらバッファを作り直す
+    fn prepare(&mut self) -> Result<Decoder> {
+        let decoder = Decoder::new(self.source.wave_format())?;
+        let samples_per_sec = self.source.wave_format().samples_per_sec;
+        if self.channels.len() != decoder.channels() {
+            let capacity = self.history.max(self.fft.size() * 2);
+            self.channels = vec![RingBuffer::new(capacity); decoder.channels()];
+            self.frames = vec![vec![0.0; self.fft.size()]; decoder.channels()];
+            let capacity = samples_per_sec as usize * WAVEFORM_SECONDS;
+            self.waveform = vec![RingBuffer::new(capacity); decoder.channels()];
+        }
+        // FFT サイズを変えても測り直さないように別に作る
+        let channel_mask = self.source.wave_format().channel_mask();
+        let new_meter = || {
+            LoudnessMeter::new(decoder.channels(), samples_per_sec).with_channel_mask(channel_mask)
+        };
+        let loudness = self.loudness.get_or_insert_with(new_meter);
+        if loudness.channels() != decoder.channels() || loudness.channel_mask() != channel_mask {
+            *loudness = new_meter();
+        }
+        Ok(decoder)
+    }
+
+    /// ソースのフォーマットが変わったので、前のフォーマットで溜めたサンプルとラウドネスを捨てて作り直す
+    fn reset_format(&mut self) -> Result<Decoder> {
+        self.channels.clear();
+        self.loudness = None;
+        self.prepare()
+    }
+
+    fn push(&mut self, decoder: &Decoder, buffer: &[u8]) {
+        let decoded = decoder.decode(buffer);
+        if let Some(loudness) = &mut self.loudness {
+            loudness.process(&decoded);
+        }
+        for ((ring, waveform), samples) in self
+            .channels
+            .iter_mut()
+            .zip(&mut self.waveform)
+            .zip(decoded)
+        {
+            ring.extend(&samples);
+            waveform.extend(&samples);
+        }
+    }
+
+    fn analyze_frames(&mut self, on_frame: &mut dyn FnMut(&App) -> Result<()>) -> Result<()> {
+        let samples_per_sec = self.source.wave_format().samples_per_sec;
+        loop {
+            let filled = self
+                .channels
+                .iter()
+                .zip(&mut self.frames)
+                .all(|(ring, frame)| ring.copy_to(frame));
+            if !filled {
+                return Ok(());
+            }
+            self.analyze(samples_per_sec);
+            on_frame(self)?;
+            let hop = self.hop();
+            for ring in &mut self.channels {
+                ring.discard(hop);
             }
+        }
+    }
 
-            let mut buffer_ptr: *mut u8 = &mut 0;
-            let mut stored_frames = 0;
-            let mut flags = 0;
-            self.capture_client
-                .GetBuffer(&mut buffer_ptr, &mut stored_frames, &mut flags, None, None)
-                .context("Failed to get buffer.")?;
-
-            let buffer_length = stored_frames * (self.wave_format.block_align as u32);
-            // drop が走っちゃって死ぬので
-            let buffer = ManuallyDrop::new(Vec::from_raw_parts(
-                buffer_ptr,
-                buffer_length as usize,
-                buffer_length as usize,
-            ))
-            .deref()
-            .clone();
-
-            self.capture_client
-                .ReleaseBuffer(stored_frames)
-                .context("Failed to release buffer.")?;
-
-            Ok(Some(buffer))
+    fn analyze(&mut self, samples_per_sec: u32) {
+        let (min, max) = self.frequency_range;
+        let bands = self
+            .binning
+            .bands(min, max.min(samples_per_sec as f64 / 2.0));
+        let bin_width = samples_per_sec as f64 / self.fft.size() as f64;
+        let spectra = self
+            .mix
+            .series(&self.frames)
+            .iter()
+            .map(|samples| {
+                let power = power_spectrum(&self.fft, samples, &self.coefficients);
+                bin_power(&power, bin_width, &bands)
+            })
+            .collect();
+        let power = self.mix.combine(spectra);
+        self.smoother
+            .update(&power, self.hop() as f64 / samples_per_sec as f64);
+        self.spectra = self.scaled(power);
+        if let Some(spectrum) = self.spectra.first() {
+            self.spectrogram.push(spectrum.clone());
         }
+        self.analyzed_frames += 1;
     }
-}
 
-pub struct Com;
+    fn scaled(&self, mut spectra: Vec<Vec<(f64, f64)>>) -> Vec<Vec<(f64, f64)>> {
+        for spectrum in &mut spectra {
+            for (_, value) in spectrum.iter_mut() {
+                *value = self.scale.apply(*value);
+            }
+        }
+        spectra
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 最初の系列のスペクトル
+    pub fn data(&self) -> &[(f64, f64)] {
+        self.spectra.first().map(Vec::as_slice).unwrap_or_default()
+    }
 
-impl Com {
-    pub fn initialize() -> Result<Com> {
-        unsafe {
-            CoInitializeEx(None, COINIT_MULTITHREADED)
-                .ok()
-                .context("Failed to initialize COM.")?;
+    /// 最後に解析した STFT のフレーム (窓を掛ける前のチャンネル毎のサンプル)
+    pub fn frame(&self) -> &[Vec<f32>] {
+        &self.frames
+    }
+
+    /// 最後に解析した STFT のフレームの中心の時刻 (秒)。最初に読んだサンプルが 0
+    pub fn frame_time(&self) -> f64 {
+        let start = self.analyzed_frames.saturating_sub(1) as f64 * self.hop() as f64;
+        (start + self.fft.size() as f64 / 2.0) / self.samples_per_sec() as f64
+    }
+
+    /// `ChannelMix::All` ならチャンネル毎、それ以外は 1 つだけのスペクトル
+    pub fn spectra(&self) -> &[Vec<(f64, f64)>] {
+        &self.spectra
+    }
+
+    /// `spectra` を `ballistics` で平滑化したもの
+    pub fn smoothed(&self) -> Vec<Vec<(f64, f64)>> {
+        self.scaled(self.smoother.smoothed())
+    }
+
+    /// `spectra` のピークホールド
+    pub fn peaks(&self) -> Vec<Vec<(f64, f64)>> {
+        self.scaled(self.smoother.peaks())
+    }
+
+    /// 最後に `reset_smoothing` してからの `spectra` のパワーの平均
+    pub fn average(&self) -> Vec<Vec<(f64, f64)>> {
+        self.scaled(self.smoother.average())
+    }
+
+    /// チャンネル毎の最新 `frames` 個のサンプル。溜まっていなければその分だけ
+    pub fn waveform(&self, frames: usize) -> Vec<Vec<f32>> {
+        self.waveform
+            .iter()
+            .map(|ring| {
+                let mut samples = vec![0.0; frames.min(ring.len())];
+                ring.copy_latest_to(&mut samples);
+                samples
+            })
+            .collect()
+    }
+
+    /// 今まで取り込んだ音のラウドネス。まだ何も取り込んでいなければ `None`
+    pub fn loudness(&self) -> Option<&LoudnessMeter> {
+        self.loudness.as_ref()
+    }
+
+    /// ラウドネスの測定をやり直す
+    pub fn reset_loudness(&mut self) {
+        if let Some(loudness) = &mut self.loudness {
+            loudness.reset();
         }
-        Ok(Com)
     }
-}
 
-impl Drop for Com {
-    fn drop(&mut self) {
-        unsafe { CoUninitialize() }
+    /// 最初の系列のスペクトルの履歴
+    pub fn spectrogram(&self) -> &Spectrogram {
+        &self.spectrogram
     }
-}
 
-#[derive(Debug)]
-pub struct WaveFormatEx {
-    pub format_tag: u16,
-    pub channels: u16,
-    pub samples_per_sec: u32,
-    pub avg_bytes_per_sec: u32,
-    pub block_align: u16,
-    pub bits_per_sample: u16,
-    pub size: u16,
-}
+    /// ソースから読んだパケットの取りこぼしなどのまとめ
+    pub fn capture_stats(&self) -> &CaptureStats {
+        &self.capture_stats
+    }
 
-impl From<WAVEFORMATEX> for WaveFormatEx {
-    fn from(value: WAVEFORMATEX) -> Self {
-        let format_tag = value.wFormatTag;
-        let channels = value.nChannels;
-        let samples_per_sec = value.nSamplesPerSec;
-        let avg_bytes_per_sec = value.nAvgBytesPerSec;
-        let block_align = value.nBlockAlign;
-        let bits_per_sample = value.wBitsPerSample;
-        let size = value.cbSize;
-        Self {
-            format_tag,
-            channels,
-            samples_per_sec,
-            avg_bytes_per_sec,
-            block_align,
-            bits_per_sample,
-            size,
+    pub fn stats(&self) -> HistoryStats {
+        let Some(ring) = self.channels.first() else {
+            return HistoryStats {
+                analyzed_frames: self.analyzed_frames,
+                ..Default::default()
+            };
+        };
+        HistoryStats {
+            analyzed_frames: self.analyzed_frames,
+            overruns: ring.overruns(),
+            overrun_samples: ring.overrun_samples(),
+            buffered: ring.len(),
         }
     }
 }
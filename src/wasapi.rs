@@ -0,0 +1,725 @@
+//! WASAPI で取り込む。Windows でしか使えない
+
+use std::{
+    borrow::Cow,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::{Context as _, Error, Result};
+use windows::{
+    core::{implement, Interface, HSTRING, PCWSTR},
+    Win32::{
+        Devices::FunctionDiscovery::PKEY_Device_FriendlyName,
+        Foundation::{CloseHandle, HANDLE},
+        Media::Audio::{
+            eCapture, eCommunications, eConsole, eMultimedia, eRender, EDataFlow, ERole,
+            IAudioCaptureClient, IAudioClient, IMMDevice, IMMDeviceEnumerator, IMMEndpoint,
+            IMMNotificationClient, IMMNotificationClient_Impl, MMDeviceEnumerator,
+            _AUDCLNT_BUFFERFLAGS, AUDCLNT_BUFFERFLAGS_DATA_DISCONTINUITY,
+            AUDCLNT_BUFFERFLAGS_SILENT, AUDCLNT_BUFFERFLAGS_TIMESTAMP_ERROR,
+            AUDCLNT_E_DEVICE_INVALIDATED, AUDCLNT_SHAREMODE_SHARED,
+            AUDCLNT_STREAMFLAGS_AUTOCONVERTPCM, AUDCLNT_STREAMFLAGS_EVENTCALLBACK,
+            AUDCLNT_STREAMFLAGS_LOOPBACK, AUDCLNT_STREAMFLAGS_SRC_DEFAULT_QUALITY, DEVICE_STATE,
+            DEVICE_STATE_ACTIVE, DEVICE_STATE_DISABLED, DEVICE_STATE_NOTPRESENT,
+            DEVICE_STATE_UNPLUGGED, WAVEFORMATEX,
+        },
+        System::Com::{
+            CoCreateInstance, CoInitializeEx, CoTaskMemFree, CoUninitialize, CLSCTX_ALL,
+            COINIT_MULTITHREADED, STGM_READ,
+        },
+        System::Threading::{CreateEventW, WaitForSingleObject},
+        UI::Shell::PropertiesSystem::PROPERTYKEY,
+    },
+};
+
+use crate::{
+    device::{
+        default_data_flow, open_device, CaptureMode, DataFlow, DeviceEnumerator, DeviceInfo,
+        DeviceState, Role,
+    },
+    follow::Connector,
+    format::{SampleFormat, WaveFormatEx},
+    packet::{Packet, PacketInfo},
+    source::AudioSource,
+};
+
+pub fn get_device() -> Result<IMMDevice> {
+    unsafe {
+        let enumerator: IMMDeviceEnumerator =
+            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)
+                .context("Failed to create device enumerator.")?;
+
+        let device = enumerator
+            .GetDefaultAudioEndpoint(eRender, eConsole)
+            .context("Failed to get default audio endpoint.")?;
+        Ok(device)
+    }
+}
+
+pub fn get_device_name(device: &IMMDevice) -> Result<String> {
+    unsafe {
+        let store = device.OpenPropertyStore(STGM_READ)?;
+        let value = store.GetValue(&PKEY_Device_FriendlyName)?;
+        Ok(value.to_string())
+    }
+}
+
+pub fn get_device_id(device: &IMMDevice) -> Result<String> {
+    unsafe {
+        let id = device.GetId()?;
+        let result = id.to_string();
+        CoTaskMemFree(Some(id.0 as _));
+        Ok(result?)
+    }
+}
+
+/// WASAPI のデバイスの一覧
+pub struct WasapiEnumerator {
+    enumerator: IMMDeviceEnumerator,
+}
+
+impl WasapiEnumerator {
+    pub fn new() -> Result<WasapiEnumerator> {
+        unsafe {
+            let enumerator = CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)
+                .context("Failed to create device enumerator.")?;
+            Ok(WasapiEnumerator { enumerator })
+        }
+    }
+}
+
+impl DeviceEnumerator for WasapiEnumerator {
+    type Device = IMMDevice;
+
+    /// 有効なものから状態毎に、再生、録音の順で並べる
+    fn devices(&self) -> Result<Vec<DeviceInfo>> {
+        unsafe {
+            let mut defaults = vec![];
+            for data_flow in DataFlow::VARIANTS {
+                for role in Role::VARIANTS {
+                    // 既定のデバイスが無いこともある
+                    if let Ok(device) = self
+                        .enumerator
+                        .GetDefaultAudioEndpoint(e_data_flow(data_flow), e_role(role))
+                    {
+                        defaults.push((get_device_id(&device)?, role));
+                    }
+                }
+            }
+
+            let mut devices = vec![];
+            for state in DeviceState::VARIANTS {
+                for data_flow in DataFlow::VARIANTS {
+                    let collection = self
+                        .enumerator
+                        .EnumAudioEndpoints(e_data_flow(data_flow), device_state(state))
+                        .context("Failed to enumerate audio endpoints.")?;
+                    for i in 0..collection.GetCount()? {
+                        let device = collection.Item(i)?;
+                        let id = get_device_id(&device)?;
+                        let default_roles = defaults
+                            .iter()
+                            .filter(|(default, _)| *default == id)
+                            .map(|&(_, role)| role)
+                            .collect();
+                        devices.push(DeviceInfo {
+                            id,
+                            // 外されたデバイスは名前を読めないことがある
+                            name: get_device_name(&device).unwrap_or_default(),
+                            data_flow,
+                            state,
+                            default_roles,
+                        });
+                    }
+                }
+            }
+            Ok(devices)
+        }
+    }
+
+    fn open(&self, id: &str) -> Result<IMMDevice> {
+        unsafe {
+            self.enumerator
+                .GetDevice(&HSTRING::from(id))
+                .with_context(|| format!("Failed to open audio endpoint: {id}"))
+        }
+    }
+}
+
+fn e_data_flow(data_flow: DataFlow) -> EDataFlow {
+    match data_flow {
+        DataFlow::Render => eRender,
+        DataFlow::Capture => eCapture,
+    }
+}
+
+fn e_role(role: Role) -> ERole {
+    match role {
+        Role::Console => eConsole,
+        Role::Multimedia => eMultimedia,
+        Role::Communications => eCommunications,
+    }
+}
+
+fn device_state(state: DeviceState) -> DEVICE_STATE {
+    match state {
+        DeviceState::Active => DEVICE_STATE_ACTIVE,
+        DeviceState::Disabled => DEVICE_STATE_DISABLED,
+        DeviceState::NotPresent => DEVICE_STATE_NOTPRESENT,
+        DeviceState::Unplugged => DEVICE_STATE_UNPLUGGED,
+    }
+}
+
+/// drop すると閉じるイベントのハンドル
+struct OwnedEvent(HANDLE);
+
+impl Drop for OwnedEvent {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CloseHandle(self.0);
+        }
+    }
+}
+
+pub struct Client {
+    device: IMMDevice,
+    mode: CaptureMode,
+    audio_client: IAudioClient,
+    capture_client: IAudioCaptureClient,
+    wave_format: WaveFormatEx,
+    /// パケットが溜まると WASAPI が立てるイベント
+    event: OwnedEvent,
+    /// 作ったスレッドで COM を使い終わるまで残しておく。他のフィールドより後に drop する
+    _com: Com,
+}
+
+impl Client {
+    /// 再生デバイスならループバック、録音デバイスなら直接取る
+    pub fn new(device: IMMDevice) -> Result<Client> {
+        let data_flow = unsafe {
+            device
+                .cast::<IMMEndpoint>()?
+                .GetDataFlow()
+                .context("Failed to get data flow.")?
+        };
+        let mode = CaptureMode::for_data_flow(if data_flow == eCapture {
+            DataFlow::Capture
+        } else {
+            DataFlow::Render
+        });
+        Client::with_mode(device, mode)
+    }
+
+    /// `mode` で取る。ループバックは再生デバイス、直接は録音デバイスでしか使えない
+    pub fn with_mode(device: IMMDevice, mode: CaptureMode) -> Result<Client> {
+        Client::with_format(device, mode, None)
+    }
+
+    /// `format` があれば、ミックスフォーマットと違っても Windows に変換させてそれで取る。
+    /// 開き直したデバイスで前と同じフォーマットにするのに使う
+    pub fn with_format(
+        device: IMMDevice,
+        mode: CaptureMode,
+        format: Option<&WaveFormatEx>,
+    ) -> Result<Client> {
+        let com = Com::initialize()?;
+        let mut stream_flags = match mode {
+            CaptureMode::Loopback => AUDCLNT_STREAMFLAGS_LOOPBACK,
+            CaptureMode::Direct => 0,
+        } | AUDCLNT_STREAMFLAGS_EVENTCALLBACK;
+        unsafe {
+            let audio_client: IAudioClient = device
+                .Activate(CLSCTX_ALL, None)
+                .context("Failed to activate audio client.")?;
+
+            let mix_format = audio_client
+                .GetMixFormat()
+                .context("Failed to get mix format.")?;
+            let mix = WaveFormatEx::from_ptr(mix_format);
+            CoTaskMemFree(Some(mix_format as _));
+            let mix = mix?;
+            let wave_format = match format {
+                Some(format) if *format != mix => {
+                    stream_flags |= AUDCLNT_STREAMFLAGS_AUTOCONVERTPCM
+                        | AUDCLNT_STREAMFLAGS_SRC_DEFAULT_QUALITY;
+                    format.clone()
+                }
+                _ => mix,
+            };
+            // WAVEFORMATEX は詰めて並んでいるのでバイト列のまま渡せる
+            let format_bytes = wave_format.to_bytes();
+
+            let buffered_duration = Duration::from_secs(10);
+
+            audio_client
+                .Initialize(
+                    AUDCLNT_SHAREMODE_SHARED,
+                    stream_flags,
+                    buffered_duration.as_micros() as i64,
+                    0,
+                    format_bytes.as_ptr() as *const WAVEFORMATEX,
+                    None,
+                )
+                .with_context(|| {
+                    format!(
+                        "Failed to initialize audio client for {} capture of a {} endpoint.",
+                        mode.name(),
+                        mode.data_flow().name()
+                    )
+                })?;
+
+            let capture_client: IAudioCaptureClient = audio_client
+                .GetService()
+                .context("Failed to get capture client.")?;
+
+            let event = OwnedEvent(
+                CreateEventW(None, false, false, None).context("Failed to create event.")?,
+            );
+            // 古い Windows のループバックではイベントが立たないが、`wait` が時間切れになるだけ
+            audio_client
+                .SetEventHandle(event.0)
+                .context("Failed to set event handle.")?;
+
+            audio_client
+                .Start()
+                .context("Failed to start audio client.")?;
+            Ok(Client {
+                device,
+                mode,
+                audio_client,
+                capture_client,
+                wave_format,
+                event,
+                _com: com,
+            })
+        }
+    }
+
+    pub fn device(&self) -> &IMMDevice {
+        &self.device
+    }
+
+    pub fn mode(&self) -> CaptureMode {
+        self.mode
+    }
+
+    pub fn wave_format(&self) -> &WaveFormatEx {
+        &self.wave_format
+    }
+
+    /// 次のパケットを 1 つ借りる。返したパケットを drop するまで次は読めない
+    pub fn next_packet(&mut self) -> Result<Option<CapturePacket<'_>>> {
+        unsafe {
+            let next = self
+                .capture_client
+                .GetNextPacketSize()
+                .context("Failed to get next packet size.")?;
+            if next == 0 {
+                return Ok(None);
+            }
+
+            let mut data = std::ptr::null_mut();
+            let mut frames = 0;
+            let mut flags = 0;
+            let mut device_position = 0;
+            let mut qpc_position = 0;
+            self.capture_client
+                .GetBuffer(
+                    &mut data,
+                    &mut frames,
+                    &mut flags,
+                    Some(&mut device_position),
+                    Some(&mut qpc_position),
+                )
+                .context("Failed to get buffer.")?;
+
+            let length = frames as usize * self.wave_format.block_align as usize;
+            let data = if data.is_null() || length == 0 {
+                &[]
+            } else {
+                std::slice::from_raw_parts(data as *const u8, length)
+            };
+            // 8bit PCM は符号無しなので無音は 0x80
+            let silence = match self.wave_format.sample_format() {
+                Ok(SampleFormat::U8) => 0x80,
+                _ => 0,
+            };
+            Ok(Some(CapturePacket {
+                capture_client: &self.capture_client,
+                data,
+                silence,
+                frames,
+                flags,
+                device_position,
+                qpc_position,
+            }))
+        }
+    }
+
+    /// 次のパケットを 1 つ複製して返す。ループバックでも直接でも同じ
+    pub fn get_buffer(&mut self) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .next_packet()?
+            .map(|packet| packet.bytes().into_owned()))
+    }
+
+    /// `get_buffer` と同じだが、位置や時刻、フラグも付ける
+    pub fn get_packet(&mut self) -> Result<Option<Packet>> {
+        Ok(self.next_packet()?.map(|packet| Packet {
+            data: packet.bytes().into_owned(),
+            info: packet.info(),
+            format: None,
+        }))
+    }
+}
+
+/// `GetBuffer` で借りた WASAPI のバッファ。drop すると `ReleaseBuffer` で返す
+pub struct CapturePacket<'a> {
+    capture_client: &'a IAudioCaptureClient,
+    data: &'a [u8],
+    /// 無音のパケットを埋める値
+    silence: u8,
+    frames: u32,
+    flags: u32,
+    device_position: u64,
+    qpc_position: u64,
+}
+
+impl CapturePacket<'_> {
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// `AUDCLNT_BUFFERFLAGS_*` の組み合わせ
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// 無音として扱うパケット。バッファの中身は読まない
+    pub fn is_silent(&self) -> bool {
+        self.flags & AUDCLNT_BUFFERFLAGS_SILENT.0 as u32 != 0
+    }
+
+    /// ストリームの先頭からのフレーム位置
+    pub fn device_position(&self) -> u64 {
+        self.device_position
+    }
+
+    /// 最初のフレームを取った時刻 (100ns 単位)
+    pub fn qpc_position(&self) -> u64 {
+        self.qpc_position
+    }
+
+    pub fn info(&self) -> PacketInfo {
+        let has = |flag: _AUDCLNT_BUFFERFLAGS| self.flags & flag.0 as u32 != 0;
+        PacketInfo {
+            frames: self.frames as u64,
+            device_position: Some(self.device_position),
+            qpc_position: Some(self.qpc_position),
+            discontinuity: has(AUDCLNT_BUFFERFLAGS_DATA_DISCONTINUITY),
+            silent: has(AUDCLNT_BUFFERFLAGS_SILENT),
+            timestamp_error: has(AUDCLNT_BUFFERFLAGS_TIMESTAMP_ERROR),
+            ..PacketInfo::default()
+        }
+    }
+
+    /// 中身。無音のパケットは無音の値 (8bit PCM なら 0x80、それ以外は 0) で埋めたものを返す
+    pub fn bytes(&self) -> Cow<'_, [u8]> {
+        if self.is_silent() {
+            Cow::Owned(vec![self.silence; self.data.len()])
+        } else {
+            Cow::Borrowed(self.data)
+        }
+    }
+}
+
+impl Drop for CapturePacket<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            if let Err(e) = self.capture_client.ReleaseBuffer(self.frames) {
+                log::error!("Failed to release buffer: {e:?}");
+            }
+        }
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        unsafe {
+            // イベントはこの後フィールドと一緒に閉じる
+            let _ = self.audio_client.Stop();
+        }
+    }
+}
+
+impl AudioSource for Client {
+    fn wave_format(&self) -> &WaveFormatEx {
+        Client::wave_format(self)
+    }
+
+    fn get_buffer(&mut self) -> Result<Option<Vec<u8>>> {
+        Client::get_buffer(self)
+    }
+
+    fn get_packet(&mut self) -> Result<Option<Packet>> {
+        Client::get_packet(self)
+    }
+
+    /// 次のパケットが来るまでイベントで待つ
+    fn wait(&mut self, timeout: Duration) -> Result<()> {
+        unsafe {
+            WaitForSingleObject(
+                self.event.0,
+                timeout.as_millis().min(u32::MAX as u128) as u32,
+            );
+        }
+        Ok(())
+    }
+}
+
+/// `DeviceNotifier` が通知を受けて立てる印
+struct Notifications {
+    /// 追いかける既定のデバイスの向き
+    follow: Option<EDataFlow>,
+    /// 開いているデバイスの ID
+    id: Mutex<Option<String>>,
+    changed: AtomicBool,
+}
+
+impl Notifications {
+    fn is_current(&self, id: PCWSTR) -> bool {
+        if id.is_null() {
+            return false;
+        }
+        let Ok(id) = (unsafe { id.to_string() }) else {
+            return false;
+        };
+        self.id
+            .lock()
+            .is_ok_and(|current| current.as_deref() == Some(id.as_str()))
+    }
+}
+
+/// `IMMNotificationClient` を実装した COM オブジェクト。通知を受けたら `notifications` に印を立てる
+#[implement(IMMNotificationClient)]
+struct NotificationClient {
+    notifications: Arc<Notifications>,
+}
+
+impl IMMNotificationClient_Impl for NotificationClient {
+    /// 開いているデバイスが無効になった
+    fn OnDeviceStateChanged(&self, id: &PCWSTR, state: DEVICE_STATE) -> windows::core::Result<()> {
+        if state != DEVICE_STATE_ACTIVE && self.notifications.is_current(*id) {
+            self.notifications.changed.store(true, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    fn OnDeviceAdded(&self, _id: &PCWSTR) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn OnDeviceRemoved(&self, _id: &PCWSTR) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    /// 追いかけている向きの既定のデバイスが別のものになった。
+    /// 既定が無くなった時は、読む方で無効になったのが分かる
+    fn OnDefaultDeviceChanged(
+        &self,
+        flow: EDataFlow,
+        role: ERole,
+        id: &PCWSTR,
+    ) -> windows::core::Result<()> {
+        if self.notifications.follow == Some(flow)
+            && role == eConsole
+            && !id.is_null()
+            && !self.notifications.is_current(*id)
+        {
+            self.notifications.changed.store(true, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    fn OnPropertyValueChanged(
+        &self,
+        _id: &PCWSTR,
+        _key: &PROPERTYKEY,
+    ) -> windows::core::Result<()> {
+        Ok(())
+    }
+}
+
+/// デバイスの変化の通知を受け取る。drop すると登録を外す
+struct DeviceNotifier {
+    enumerator: IMMDeviceEnumerator,
+    client: IMMNotificationClient,
+    notifications: Arc<Notifications>,
+}
+
+impl DeviceNotifier {
+    /// `follow` の向きの既定のデバイスが変わるか、開いているデバイスが無効になると印を立てる
+    fn register(
+        enumerator: &IMMDeviceEnumerator,
+        follow: Option<DataFlow>,
+    ) -> Result<DeviceNotifier> {
+        let notifications = Arc::new(Notifications {
+            follow: follow.map(e_data_flow),
+            id: Mutex::new(None),
+            changed: AtomicBool::new(false),
+        });
+        let client: IMMNotificationClient = NotificationClient {
+            notifications: notifications.clone(),
+        }
+        .into();
+        unsafe {
+            enumerator
+                .RegisterEndpointNotificationCallback(&client)
+                .context("Failed to register endpoint notification callback.")?;
+            Ok(DeviceNotifier {
+                enumerator: enumerator.clone(),
+                client,
+                notifications,
+            })
+        }
+    }
+
+    /// 開いたデバイス。それまでの印は捨てる
+    fn set_current(&self, id: &str) {
+        if let Ok(mut current) = self.notifications.id.lock() {
+            *current = Some(id.to_string());
+        }
+        self.notifications.changed.store(false, Ordering::Relaxed);
+    }
+
+    fn take_changed(&self) -> bool {
+        self.notifications.changed.swap(false, Ordering::Relaxed)
+    }
+}
+
+impl Drop for DeviceNotifier {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = self
+                .enumerator
+                .UnregisterEndpointNotificationCallback(&self.client);
+        }
+    }
+}
+
+/// `FollowSource` で WASAPI のデバイスを開き直す。`query` が既定のデバイスなら、
+/// 既定が変わったと通知される度にそちらへ移る
+pub struct WasapiConnector {
+    query: Option<String>,
+    /// 追いかける既定のデバイスの向き
+    follow: Option<DataFlow>,
+    notifier: DeviceNotifier,
+    enumerator: WasapiEnumerator,
+    /// 開いているデバイスの ID
+    id: Option<String>,
+    /// 作ったスレッドで COM を使い終わるまで残しておく。他のフィールドより後に drop する
+    _com: Com,
+}
+
+impl WasapiConnector {
+    /// `query` は `find_device` と同じ
+    pub fn new(query: Option<&str>) -> Result<WasapiConnector> {
+        let com = Com::initialize()?;
+        let follow = default_data_flow(query)?;
+        let enumerator = WasapiEnumerator::new()?;
+        Ok(WasapiConnector {
+            query: query.map(str::to_string),
+            follow,
+            notifier: DeviceNotifier::register(&enumerator.enumerator, follow)?,
+            enumerator,
+            id: None,
+            _com: com,
+        })
+    }
+}
+
+impl Connector for WasapiConnector {
+    type Source = Client;
+
+    fn connect(&mut self, format: Option<&WaveFormatEx>) -> Result<Client> {
+        // 既定を追わないなら、番号や名前が変わっても同じデバイスを開き直す
+        let query = match (&self.follow, &self.id) {
+            (None, Some(id)) => Some(id.as_str()),
+            _ => self.query.as_deref(),
+        };
+        let (info, device) = open_device(&self.enumerator, query)?;
+        let client =
+            Client::with_format(device, CaptureMode::for_data_flow(info.data_flow), format)?;
+        self.notifier.set_current(&info.id);
+        self.id = Some(info.id);
+        Ok(client)
+    }
+
+    /// `IMMNotificationClient` に通知された変化があれば `true`
+    fn should_reconnect(&mut self) -> bool {
+        self.notifier.take_changed()
+    }
+
+    fn is_device_lost(&self, error: &Error) -> bool {
+        error.chain().any(|cause| {
+            cause
+                .downcast_ref::<windows::core::Error>()
+                .is_some_and(|e| e.code() == AUDCLNT_E_DEVICE_INVALIDATED)
+        })
+    }
+}
+
+pub struct Com;
+
+impl Com {
+    pub fn initialize() -> Result<Com> {
+        unsafe {
+            CoInitializeEx(None, COINIT_MULTITHREADED)
+                .ok()
+                .context("Failed to initialize COM.")?;
+        }
+        Ok(Com)
+    }
+}
+
+impl Drop for Com {
+    fn drop(&mut self) {
+        unsafe { CoUninitialize() }
+    }
+}
+
+impl From<WAVEFORMATEX> for WaveFormatEx {
+    fn from(value: WAVEFORMATEX) -> Self {
+        let format_tag = value.wFormatTag;
+        let channels = value.nChannels;
+        let samples_per_sec = value.nSamplesPerSec;
+        let avg_bytes_per_sec = value.nAvgBytesPerSec;
+        let block_align = value.nBlockAlign;
+        let bits_per_sample = value.wBitsPerSample;
+        let size = value.cbSize;
+        Self {
+            format_tag,
+            channels,
+            samples_per_sec,
+            avg_bytes_per_sec,
+            block_align,
+            bits_per_sample,
+            size,
+            extensible: None,
+        }
+    }
+}
+
+impl WaveFormatEx {
+    /// # Safety
+    /// `ptr` は `cbSize` 分の拡張を含めて読める `WAVEFORMATEX` を指していること
+    pub unsafe fn from_ptr(ptr: *const WAVEFORMATEX) -> Result<WaveFormatEx> {
+        let size = std::ptr::read_unaligned(ptr).cbSize as usize;
+        let bytes = std::slice::from_raw_parts(ptr as *const u8, 18 + size);
+        WaveFormatEx::from_bytes(bytes)
+    }
+}
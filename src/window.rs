@@ -0,0 +1,90 @@
+//! FFT の前に掛ける窓関数
+
+use std::{f64::consts::TAU, str::FromStr};
+
+use anyhow::{bail, Error};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Window {
+    Rectangular,
+    #[default]
+    Hann,
+    Hamming,
+    Blackman,
+    /// 4 項の Blackman-Harris
+    BlackmanHarris,
+    /// 振幅の読みが正確な SRS 形式の flat-top
+    FlatTop,
+}
+
+impl Window {
+    pub const VARIANTS: [Window; 6] = [
+        Window::Rectangular,
+        Window::Hann,
+        Window::Hamming,
+        Window::Blackman,
+        Window::BlackmanHarris,
+        Window::FlatTop,
+    ];
+
+    /// 余弦の和で表した係数 a0 - a1 cos + a2 cos - ...
+    fn cosine_terms(&self) -> &'static [f64] {
+        match self {
+            Window::Rectangular => &[1.0],
+            Window::Hann => &[0.5, 0.5],
+            Window::Hamming => &[0.54, 0.46],
+            Window::Blackman => &[0.42, 0.5, 0.08],
+            Window::BlackmanHarris => &[0.35875, 0.48829, 0.14128, 0.01168],
+            Window::FlatTop => &[1.0, 1.93, 1.29, 0.388, 0.028],
+        }
+    }
+
+    /// スペクトル解析向けの周期的な窓
+    pub fn coefficients(&self, size: usize) -> Vec<f32> {
+        let terms = self.cosine_terms();
+        (0..size)
+            .map(|n| {
+                let x = TAU * n as f64 / size as f64;
+                terms
+                    .iter()
+                    .enumerate()
+                    .map(|(k, a)| {
+                        let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                        sign * a * (k as f64 * x).cos()
+                    })
+                    .sum::<f64>() as f32
+            })
+            .collect()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Window::Rectangular => "rectangular",
+            Window::Hann => "hann",
+            Window::Hamming => "hamming",
+            Window::Blackman => "blackman",
+            Window::BlackmanHarris => "blackman-harris",
+            Window::FlatTop => "flat-top",
+        }
+    }
+
+    /// キー操作で順番に切り替える用
+    pub fn next(&self) -> Window {
+        let index = Window::VARIANTS.iter().position(|w| w == self).unwrap();
+        Window::VARIANTS[(index + 1) % Window::VARIANTS.len()]
+    }
+}
+
+impl FromStr for Window {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Window::VARIANTS
+            .into_iter()
+            .find(|window| window.name().eq_ignore_ascii_case(s))
+        {
+            Some(window) => Ok(window),
+            None => bail!("Unknown window: {s}"),
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! `WaveFormatEx` のまま少しずつ書き込める WAV ライター
+
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use anyhow::{Context as _, Result};
+
+use crate::format::{WaveFormatEx, WAVE_FORMAT_PCM};
+
+/// RIFF の 32bit サイズで表せる上限。超えたら RF64 に切り替える
+const RIFF_LIMIT: u64 = u32::MAX as u64;
+/// `ds64` チャンクの中身 (riffSize, dataSize, sampleCount, tableLength)
+const DS64_SIZE: u32 = 28;
+
+/// ヘッダを先に書いておき、`finalize` でサイズを埋める。
+/// 先頭に `JUNK` を置いておき、4GiB を超えたらそこを `ds64` にして RF64 にする (EBU Tech 3306)。
+/// `add_cue` した位置は `finalize` で `data` の後ろに `cue ` と `LIST adtl` チャンクで書く
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    wave_format: WaveFormatEx,
+    fact_offset: Option<u64>,
+    data_offset: u64,
+    data_bytes: u64,
+    cues: Vec<(u64, String)>,
+    /// `data` の後ろに書いたチャンクのバイト数
+    trailer_bytes: u64,
+    finalized: bool,
+}
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>, wave_format: &WaveFormatEx) -> Result<Self> {
+        let file = File::create(path.as_ref()).context("Failed to create output file.")?;
+        WavWriter::new(BufWriter::new(file), wave_format)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W, wave_format: &WaveFormatEx) -> Result<Self> {
+        let wave_format = if wave_format.requires_extensible() {
+            wave_format.to_extensible()
+        } else {
+            wave_format.clone()
+        };
+        let mut fmt = wave_format.to_bytes();
+        if wave_format.format_tag == WAVE_FORMAT_PCM && wave_format.size == 0 {
+            // 素の PCM は cbSize を持たない 16 バイトで書くのが普通
+            fmt.truncate(16);
+        }
+
+        writer.write_all(b"RIFF\0\0\0\0WAVE")?;
+        writer.write_all(b"JUNK")?;
+        writer.write_all(&DS64_SIZE.to_le_bytes())?;
+        writer.write_all(&[0; DS64_SIZE as usize])?;
+        write_chunk(&mut writer, b"fmt ", &fmt)?;
+        let fact_offset = if wave_format.effective_format_tag() != Some(WAVE_FORMAT_PCM) {
+            // PCM 以外は fact チャンクが必須
+            let offset = writer.stream_position()?;
+            write_chunk(&mut writer, b"fact", &[0; 4])?;
+            Some(offset)
+        } else {
+            None
+        };
+        writer.write_all(b"data\0\0\0\0")?;
+        let data_offset = writer.stream_position()?;
+
+        Ok(WavWriter {
+            writer,
+            wave_format,
+            fact_offset,
+            data_offset,
+            data_bytes: 0,
+            cues: vec![],
+            trailer_bytes: 0,
+            finalized: false,
+        })
+    }
+
+    /// ヘッダに書かれるフォーマット。必要に応じて拡張形式になっている
+    pub fn wave_format(&self) -> &WaveFormatEx {
+        &self.wave_format
+    }
+
+    pub fn data_bytes(&self) -> u64 {
+        self.data_bytes
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.data_bytes / self.wave_format.block_align.max(1) as u64
+    }
+
+    /// キャプチャしたままのバイト列を追記する
+    pub fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.writer
+            .write_all(bytes)
+            .context("Failed to write samples.")?;
+        self.data_bytes += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// `frame` の位置に `label` の付いたキューを置く。`cue ` の位置は 32bit なので、それを超える位置は書かれない
+    pub fn add_cue(&mut self, frame: u64, label: impl Into<String>) {
+        self.cues.push((frame, label.into()));
+    }
+
+    /// ここまでのサイズでヘッダを更新する。途中で落ちても読めるファイルにしたい時用
+    pub fn flush(&mut self) -> Result<()> {
+        self.update_header()?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub fn finalize(mut self) -> Result<()> {
+        self.finish()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.finalized {
+            return Ok(());
+        }
+        self.finalized = true;
+        if self.data_bytes % 2 == 1 {
+            self.writer.write_all(&[0])?;
+        }
+        self.write_cues()?;
+        self.update_header()?;
+        self.writer.flush().context("Failed to flush wav file.")?;
+        Ok(())
+    }
+
+    fn update_header(&mut self) -> Result<()> {
+        let end = self.writer.stream_position()?;
+        let padded_end = self.data_offset + self.data_bytes + self.data_bytes % 2;
+        let riff_size = padded_end + self.trailer_bytes - 8;
+        let frames = self.frames();
+
+        if riff_size > RIFF_LIMIT || self.data_bytes > RIFF_LIMIT {
+            self.writer.seek(SeekFrom::Start(0))?;
+            self.writer.write_all(b"RF64")?;
+            self.writer.write_all(&u32::MAX.to_le_bytes())?;
+            self.writer.seek(SeekFrom::Start(12))?;
+            self.writer.write_all(b"ds64")?;
+            self.writer.write_all(&DS64_SIZE.to_le_bytes())?;
+            self.writer.write_all(&riff_size.to_le_bytes())?;
+            self.writer.write_all(&self.data_bytes.to_le_bytes())?;
+            self.writer.write_all(&frames.to_le_bytes())?;
+            self.writer.write_all(&0u32.to_le_bytes())?;
+            self.patch_u32(self.data_offset - 4, u32::MAX)?;
+            if let Some(offset) = self.fact_offset {
+                self.patch_u32(offset + 8, u32::MAX)?;
+            }
+        } else {
+            self.patch_u32(4, riff_size as u32)?;
+            self.patch_u32(self.data_offset - 4, self.data_bytes as u32)?;
+            if let Some(offset) = self.fact_offset {
+                self.patch_u32(offset + 8, frames.min(RIFF_LIMIT) as u32)?;
+            }
+        }
+
+        self.writer.seek(SeekFrom::Start(end))?;
+        Ok(())
+    }
+
+    fn write_cues(&mut self) -> Result<()> {
+        let cues = self
+            .cues
+            .iter()
+            .filter_map(|(frame, label)| Some((u32::try_from(*frame).ok()?, label)))
+            .collect::<Vec<_>>();
+        if cues.is_empty() {
+            return Ok(());
+        }
+        let mut cue = (cues.len() as u32).to_le_bytes().to_vec();
+        let mut adtl = b"adtl".to_vec();
+        for (id, (frame, label)) in (1u32..).zip(cues) {
+            // dwName, dwPosition, fccChunk, dwChunkStart, dwBlockStart, dwSampleOffset
+            cue.extend(id.to_le_bytes());
+            cue.extend(frame.to_le_bytes());
+            cue.extend(b"data");
+            cue.extend([0; 8]);
+            cue.extend(frame.to_le_bytes());
+            let mut labl = id.to_le_bytes().to_vec();
+            labl.extend(label.as_bytes());
+            labl.push(0);
+            write_chunk(&mut adtl, b"labl", &labl)?;
+        }
+        let start = self.writer.stream_position()?;
+        write_chunk(&mut self.writer, b"cue ", &cue)?;
+        write_chunk(&mut self.writer, b"LIST", &adtl)?;
+        self.trailer_bytes = self.writer.stream_position()? - start;
+        Ok(())
+    }
+
+    fn patch_u32(&mut self, offset: u64, value: u32) -> Result<()> {
+        self.writer.seek(SeekFrom::Start(offset))?;
+        self.writer.write_all(&value.to_le_bytes())?;
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> Drop for WavWriter<W> {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            log::error!("Failed to finalize wav file: {e:?}");
+        }
+    }
+}
+
+fn write_chunk(writer: &mut impl Write, id: &[u8; 4], body: &[u8]) -> Result<()> {
+    writer.write_all(id)?;
+    writer.write_all(&(body.len() as u32).to_le_bytes())?;
+    writer.write_all(body)?;
+    if body.len() % 2 == 1 {
+        writer.write_all(&[0])?;
+    }
+    Ok(())
+}
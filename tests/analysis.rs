@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use windows_cap_audio::{
+    analysis::{analyze, ReportFormat, ReportWriter},
+    source::{AudioSource, MemorySource, WavSource},
+    spectrum::{Binning, Scale},
+    util::App,
+    writer::WavWriter,
+};
+
+fn tone_app(seconds: u64) -> App {
+    let amplitude = 10f32.powf(-23.0 / 20.0);
+    let source =
+        MemorySource::sine(1_000.0, amplitude, 2, 48_000, Duration::from_secs(seconds)).unwrap();
+    App::new("tone".to_string(), source)
+        .with_binning(Binning::Octave)
+        .with_scale(Scale::default())
+        .with_hop(4_800)
+        .with_fft_size(8_192)
+        .unwrap()
+}
+
+#[test]
+fn reports_every_frame_and_summary() {
+    let mut app = tone_app(4);
+    let mut reports = vec![];
+    let summary = analyze(&mut app, |report| {
+        reports.push(report);
+        Ok(())
+    })
+    .unwrap();
+
+    // (192000 - 8192) / 4800 + 1
+    assert_eq!(reports.len(), 39);
+    assert_eq!(summary.frames, 39);
+    assert!((reports[0].time - 4_096.0 / 48_000.0).abs() < 1e-9);
+    assert!((reports[1].time - reports[0].time - 0.1).abs() < 1e-9);
+    assert_eq!(summary.duration, reports.last().unwrap().time);
+
+    let last = reports.last().unwrap();
+    assert!((last.peak + 23.0).abs() < 0.1);
+    assert!((last.rms + 26.0).abs() < 0.1);
+    assert!((last.momentary.unwrap() + 23.0).abs() < 0.1);
+    assert!((last.short_term.unwrap() + 23.0).abs() < 0.1);
+    assert_eq!(reports[0].short_term, None);
+    assert!((summary.sample_peak + 23.0).abs() < 0.1);
+    assert!((summary.loudness.integrated.unwrap() + 23.0).abs() < 0.1);
+
+    let peak = summary
+        .average_spectrum
+        .iter()
+        .copied()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap();
+    assert_eq!(peak.0, 1_000.0);
+    assert!((peak.1 + 23.0).abs() < 0.2);
+}
+
+#[test]
+fn stops_on_frame_error() {
+    let mut app = tone_app(1);
+    let mut calls = 0;
+    let result = analyze(&mut app, |_| {
+        calls += 1;
+        anyhow::ensure!(calls < 3, "enough");
+        Ok(())
+    });
+    assert!(result.is_err());
+    assert_eq!(calls, 3);
+}
+
+#[test]
+fn writes_json_lines() {
+    let mut app = tone_app(1);
+    let mut writer = ReportWriter::new(vec![], ReportFormat::Json);
+    let summary = analyze(&mut app, |report| writer.frame(&report)).unwrap();
+    writer.summary(&summary).unwrap();
+    let text = String::from_utf8(writer.into_inner()).unwrap();
+    let lines = text.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len() as u64, summary.frames + 1);
+    assert!(lines[0].starts_with(r#"{"type":"frame","time":0.08533333333333333,"#));
+    // 3 秒無いので short-term は null
+    assert!(lines[0].contains(r#""short_term":null"#));
+    assert!(lines[0].contains(r#""spectrum":[[31.25,"#));
+    let last = lines.last().unwrap();
+    assert!(last.starts_with(r#"{"type":"summary","frames":"#));
+    assert!(last.contains(r#""max_short_term":null"#));
+    assert!(last.ends_with("]]}"));
+}
+
+#[test]
+fn writes_csv_with_band_header() {
+    let mut app = tone_app(1);
+    let mut writer = ReportWriter::new(vec![], ReportFormat::Csv);
+    let summary = analyze(&mut app, |report| writer.frame(&report)).unwrap();
+    writer.summary(&summary).unwrap();
+    let text = String::from_utf8(writer.into_inner()).unwrap();
+    let (comments, lines): (Vec<_>, Vec<_>) = text.lines().partition(|line| line.starts_with('#'));
+    assert_eq!(lines.len() as u64, summary.frames + 1);
+    assert!(lines[0].starts_with("time,peak,rms,momentary,short_term,31.2Hz,62.5Hz,"));
+    let columns = lines[0].split(',').count();
+    assert!(lines[1..]
+        .iter()
+        .all(|line| line.split(',').count() == columns));
+    // momentary はあるが short-term は空
+    let momentary = lines[5].split(',').nth(3).unwrap().parse::<f64>().unwrap();
+    assert!((momentary + 23.0).abs() < 0.1);
+    assert_eq!(lines[5].split(',').nth(4).unwrap(), "");
+
+    // まとめは表の後ろにコメント行で付く
+    assert!(text
+        .lines()
+        .last()
+        .unwrap()
+        .starts_with("# average_spectrum,"));
+    assert_eq!(comments[0], format!("# frames,{}", summary.frames));
+    let integrated = comments
+        .iter()
+        .find_map(|line| line.strip_prefix("# integrated,"))
+        .unwrap()
+        .parse::<f64>()
+        .unwrap();
+    assert!((integrated + 23.0).abs() < 0.1);
+    assert!(comments.contains(&"# max_short_term,"));
+}
+
+#[test]
+fn parses_report_format() {
+    assert_eq!("CSV".parse::<ReportFormat>().unwrap(), ReportFormat::Csv);
+    assert!("xml".parse::<ReportFormat>().is_err());
+}
+
+#[test]
+fn analyzes_wav_file() {
+    let path = std::env::temp_dir().join("windows-cap-audio-analysis.wav");
+    let mut source = MemorySource::sine(440.0, 0.5, 1, 44_100, Duration::from_millis(500)).unwrap();
+    let mut writer = WavWriter::create(&path, source.wave_format()).unwrap();
+    while let Some(buffer) = source.get_buffer().unwrap() {
+        writer.write(&buffer).unwrap();
+    }
+    writer.finalize().unwrap();
+
+    let mut app = App::new("file".to_string(), WavSource::open(&path).unwrap());
+    let summary = analyze(&mut app, |_| Ok(())).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    // (22050 - 2048) / 1024 + 1
+    assert_eq!(summary.frames, 20);
+    assert!((summary.sample_peak + 6.02).abs() < 0.1);
+}
@@ -0,0 +1,256 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use windows_cap_audio::{
+    capture::{pump, CaptureThread, Overflow, PacketQueue},
+    format::WaveFormatEx,
+    packet::{CaptureStats, Packet},
+    recorder::{MemorySink, Recorder},
+    source::{AudioSource, MemorySource},
+};
+
+fn ramp(frames: usize) -> MemorySource {
+    let samples = (0..frames).map(|n| n as f32).collect::<Vec<_>>();
+    MemorySource::from_f32(1, 1_000, &samples)
+        .unwrap()
+        .with_frames_per_packet(10)
+}
+
+/// 止められるまで同じパケットを返し続けるソース
+struct Endless(WaveFormatEx);
+
+impl AudioSource for Endless {
+    fn wave_format(&self) -> &WaveFormatEx {
+        &self.0
+    }
+
+    fn get_buffer(&mut self) -> Result<Option<Vec<u8>>> {
+        Ok(Some(vec![0; 4]))
+    }
+}
+
+/// `fail_after` パケット返したら失敗するソース
+struct Failing {
+    wave_format: WaveFormatEx,
+    fail_after: usize,
+}
+
+impl AudioSource for Failing {
+    fn wave_format(&self) -> &WaveFormatEx {
+        &self.wave_format
+    }
+
+    fn get_buffer(&mut self) -> Result<Option<Vec<u8>>> {
+        if self.fail_after == 0 {
+            bail!("device lost");
+        }
+        self.fail_after -= 1;
+        Ok(Some(vec![0; 4]))
+    }
+}
+
+#[test]
+fn drop_oldest_keeps_latest_packets() {
+    let queue = PacketQueue::new(2, Overflow::DropOldest);
+    for n in 0..5u8 {
+        assert!(queue.push(vec![n; 3]));
+    }
+    let third = queue.try_pop().unwrap();
+    assert_eq!(third.data, vec![3; 3]);
+    // 捨てた 3 つは残った最初のパケットの手前の途切れになる
+    assert_eq!(third.info.dropped_packets, 3);
+    let fourth = queue.try_pop().unwrap();
+    assert_eq!(fourth.data, vec![4; 3]);
+    assert_eq!(fourth.info.dropped_packets, 0);
+    assert_eq!(queue.try_pop(), None);
+    let stats = queue.stats();
+    assert_eq!(stats.pushed, 5);
+    assert_eq!(stats.popped, 2);
+    assert_eq!(stats.dropped_packets, 3);
+    assert_eq!(stats.dropped_bytes, 9);
+    assert_eq!(stats.high_water, 2);
+}
+
+#[test]
+fn drop_newest_keeps_earliest_packets() {
+    let queue = PacketQueue::new(2, Overflow::DropNewest);
+    for n in 0..5u8 {
+        assert!(queue.push(vec![n]));
+    }
+    assert_eq!(queue.try_pop().map(|packet| packet.data), Some(vec![0]));
+    assert_eq!(queue.try_pop().map(|packet| packet.data), Some(vec![1]));
+    assert_eq!(queue.stats().dropped_packets, 3);
+    assert_eq!(queue.stats().pushed, 2);
+
+    // 捨てた分は次に入れたパケットに付く
+    assert!(queue.push(Packet::new(vec![0; 8], 4)));
+    let next = queue.try_pop().unwrap();
+    assert_eq!(next.info.dropped_packets, 3);
+    let mut stats = CaptureStats::default();
+    stats.record(&next.info);
+    assert_eq!(stats.gaps, 1);
+}
+
+#[test]
+fn dropped_format_change_is_kept() {
+    let changed = || Packet {
+        format: Some(WaveFormatEx::ieee_float(2, 2_000)),
+        ..Packet::from(vec![9])
+    };
+    for overflow in [Overflow::DropOldest, Overflow::DropNewest] {
+        let queue = PacketQueue::new(1, overflow);
+        match overflow {
+            Overflow::DropOldest => {
+                assert!(queue.push(changed()));
+                assert!(queue.push(vec![1]));
+            }
+            _ => {
+                assert!(queue.push(vec![0]));
+                assert!(queue.push(changed()));
+                assert_eq!(queue.try_pop().unwrap().format, None);
+                assert!(queue.push(vec![1]));
+            }
+        }
+        // 変わったことは捨てずに次のパケットで知らせる
+        let next = queue.try_pop().unwrap();
+        assert_eq!(next.data, vec![1], "{overflow:?}");
+        assert_eq!(next.format, Some(WaveFormatEx::ieee_float(2, 2_000)));
+    }
+}
+
+#[test]
+fn block_waits_for_consumer() {
+    let queue = PacketQueue::new(1, Overflow::Block);
+    assert!(queue.push(vec![0]));
+    let producer = {
+        let queue = queue.clone();
+        thread::spawn(move || queue.push(vec![1]))
+    };
+    // 取り出すまで入らない
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(queue.stats().queued, 1);
+    assert_eq!(queue.try_pop().map(|packet| packet.data), Some(vec![0]));
+    assert!(producer.join().unwrap());
+    assert_eq!(queue.try_pop().map(|packet| packet.data), Some(vec![1]));
+    let stats = queue.stats();
+    assert_eq!(stats.blocked, 1);
+    assert_eq!(stats.dropped_packets, 0);
+}
+
+#[test]
+fn close_releases_blocked_producer() {
+    let queue = PacketQueue::new(1, Overflow::Block);
+    assert!(queue.push(vec![0]));
+    let producer = {
+        let queue = queue.clone();
+        thread::spawn(move || queue.push(vec![1]))
+    };
+    thread::sleep(Duration::from_millis(20));
+    queue.close();
+    assert!(!producer.join().unwrap());
+    // 閉じても残りは取り出せる
+    assert!(queue.wait(Duration::from_secs(1)));
+    assert_eq!(queue.try_pop().map(|packet| packet.data), Some(vec![0]));
+    assert!(!queue.wait(Duration::from_secs(1)));
+}
+
+#[test]
+fn wait_times_out_when_empty() {
+    let queue = PacketQueue::new(4, Overflow::Block);
+    let started = Instant::now();
+    assert!(!queue.wait(Duration::from_millis(30)));
+    assert!(started.elapsed() >= Duration::from_millis(30));
+    queue.push(vec![0]);
+    assert!(queue.wait(Duration::from_secs(1)));
+}
+
+#[test]
+fn pump_stops_when_source_ends() {
+    let queue = PacketQueue::new(100, Overflow::Block);
+    pump(&mut ramp(95), &queue).unwrap();
+    assert_eq!(queue.stats().pushed, 10);
+}
+
+#[test]
+fn thread_delivers_every_packet() {
+    let mut capture =
+        CaptureThread::spawn(|| Ok(ramp(95)), PacketQueue::new(2, Overflow::Block)).unwrap();
+    assert_eq!(capture.wave_format().samples_per_sec, 1_000);
+    let mut data = vec![];
+    while !capture.is_finished() {
+        capture.wait(Duration::from_millis(100)).unwrap();
+        while let Some(packet) = capture.get_buffer().unwrap() {
+            data.extend(packet);
+        }
+    }
+    let samples = data
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(samples, (0..95).map(|n| n as f32).collect::<Vec<_>>());
+    assert_eq!(capture.stats().dropped_packets, 0);
+    capture.stop().unwrap();
+}
+
+#[test]
+fn recorder_reads_from_thread() {
+    let source = || Ok(ramp(1_000).with_realtime(true).with_frames_per_packet(50));
+    let capture = CaptureThread::spawn(source, PacketQueue::new(8, Overflow::Block)).unwrap();
+    let mut sink = MemorySink::default();
+    let mut recorder = Recorder::new(capture, &mut sink).unwrap();
+    recorder.run().unwrap();
+    assert_eq!(recorder.frames(), 1_000);
+}
+
+#[test]
+fn stop_ends_live_source() {
+    let wave_format = WaveFormatEx::ieee_float(1, 1_000);
+    let mut capture = CaptureThread::spawn(
+        move || Ok(Endless(wave_format)),
+        PacketQueue::new(4, Overflow::DropOldest),
+    )
+    .unwrap();
+    thread::sleep(Duration::from_millis(20));
+    capture.stop().unwrap();
+    let stats = capture.stats();
+    assert_eq!(stats.queued, 4);
+    assert!(stats.dropped_packets > 0);
+    assert!(!capture.is_finished());
+    while capture.get_buffer().unwrap().is_some() {}
+    assert!(capture.is_finished());
+}
+
+#[test]
+fn errors_come_back_to_reader() {
+    let opening = CaptureThread::spawn(
+        || -> Result<MemorySource> { bail!("no device") },
+        PacketQueue::new(4, Overflow::Block),
+    );
+    assert_eq!(opening.err().unwrap().to_string(), "no device");
+
+    let wave_format = WaveFormatEx::ieee_float(1, 1_000);
+    let mut capture = CaptureThread::spawn(
+        move || {
+            Ok(Failing {
+                wave_format,
+                fail_after: 3,
+            })
+        },
+        PacketQueue::new(8, Overflow::Block),
+    )
+    .unwrap();
+    let mut packets = 0;
+    let error = loop {
+        capture.wait(Duration::from_millis(100)).unwrap();
+        match capture.get_buffer() {
+            Ok(Some(_)) => packets += 1,
+            Ok(None) => {}
+            Err(e) => break e,
+        }
+    };
+    assert_eq!(packets, 3);
+    assert_eq!(error.to_string(), "device lost");
+}
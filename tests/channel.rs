@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+use windows_cap_audio::{channel::ChannelMix, source::MemorySource, spectrum::Scale, util::App};
+
+/// 左に 1kHz、右に 5kHz を入れたステレオ
+fn stereo() -> MemorySource {
+    let rate = 48_000;
+    let samples = (0..rate / 10)
+        .flat_map(|n| {
+            let t = n as f32 / rate as f32;
+            let left = 0.5 * (std::f32::consts::TAU * 1_000.0 * t).sin();
+            let right = 0.5 * (std::f32::consts::TAU * 5_000.0 * t).sin();
+            [left, right]
+        })
+        .collect::<Vec<_>>();
+    MemorySource::from_f32(2, rate, &samples).unwrap()
+}
+
+/// 一番大きい帯域の中心周波数が `expected` と 1/3 オクターブ以内か
+fn assert_peak(spectrum: &[(f64, f64)], expected: f64) {
+    let peak = peak(spectrum);
+    assert!(
+        (peak / expected).log2().abs() < 1.0 / 3.0,
+        "{peak} != {expected}"
+    );
+}
+
+fn peak(spectrum: &[(f64, f64)]) -> f64 {
+    spectrum
+        .iter()
+        .copied()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap()
+        .0
+}
+
+#[test]
+fn series_mixes_channels() {
+    let channels = vec![vec![1.0, 0.5], vec![0.0, 0.5]];
+    assert_eq!(ChannelMix::Left.series(&channels), vec![vec![1.0, 0.5]]);
+    assert_eq!(ChannelMix::Right.series(&channels), vec![vec![0.0, 0.5]]);
+    assert_eq!(ChannelMix::Mid.series(&channels), vec![vec![0.5, 0.5]]);
+    assert_eq!(ChannelMix::Side.series(&channels), vec![vec![0.5, 0.0]]);
+    assert_eq!(ChannelMix::All.series(&channels), channels);
+}
+
+#[test]
+fn mono_input_side_is_silent() {
+    let channels = vec![vec![1.0, 0.5]];
+    assert_eq!(ChannelMix::Right.series(&channels), channels);
+    assert_eq!(ChannelMix::Side.series(&channels), vec![vec![0.0, 0.0]]);
+}
+
+#[test]
+fn max_combines_per_bin() {
+    let spectra = vec![vec![(1.0, 1.0), (2.0, 0.0)], vec![(1.0, 0.5), (2.0, 3.0)]];
+    assert_eq!(
+        ChannelMix::Max.combine(spectra),
+        vec![vec![(1.0, 1.0), (2.0, 3.0)]]
+    );
+}
+
+#[test]
+fn parses_names() {
+    assert_eq!("Side".parse::<ChannelMix>().unwrap(), ChannelMix::Side);
+    assert!("center".parse::<ChannelMix>().is_err());
+}
+
+#[test]
+fn app_analyzes_each_channel() {
+    let mut app = App::new("stereo".to_string(), stereo()).with_mix(ChannelMix::All);
+    app.on_tick().unwrap();
+    let spectra = app.spectra();
+    assert_eq!(spectra.len(), 2);
+    assert_peak(&spectra[0], 1_000.0);
+    assert_peak(&spectra[1], 5_000.0);
+
+    let mut app = App::new("stereo".to_string(), stereo()).with_mix(ChannelMix::Right);
+    app.on_tick().unwrap();
+    assert_eq!(app.spectra().len(), 1);
+    assert_peak(app.data(), 5_000.0);
+}
+
+#[test]
+fn app_keeps_channels_in_sync() {
+    let source = MemorySource::sine(440.0, 0.5, 2, 48_000, Duration::from_millis(200)).unwrap();
+    let mut app = App::new("sine".to_string(), source)
+        .with_mix(ChannelMix::Side)
+        .with_scale(Scale::Power);
+    app.on_tick().unwrap();
+    assert!(app.stats().analyzed_frames > 0);
+    assert!(app.data().iter().all(|(_, value)| *value == 0.0));
+}
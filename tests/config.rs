@@ -0,0 +1,153 @@
+use std::path::PathBuf;
+
+use clap::{
+    builder::RangedU64ValueParser, Args, CommandFactory, FromArgMatches, Parser, Subcommand,
+};
+use windows_cap_audio::config::{positive_f64, Config};
+
+#[derive(Parser, Debug)]
+struct Cli {
+    #[clap(long, global = true)]
+    config: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+enum Command {
+    Visualize(VisualizeArgs),
+    Record(RecordArgs),
+}
+
+#[derive(Args, Debug, PartialEq)]
+struct VisualizeArgs {
+    #[clap(long, default_value_t = 800, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    width: usize,
+    #[clap(long, default_value_t = 30.0, value_parser = positive_f64)]
+    frame_rate: f64,
+    #[clap(long, default_value_t = 2048)]
+    fft_size: usize,
+    #[clap(long)]
+    log_frequency: bool,
+}
+
+#[derive(Args, Debug, PartialEq)]
+struct RecordArgs {
+    #[clap(short, long)]
+    output: PathBuf,
+    #[clap(long, default_value_t = 2048)]
+    fft_size: usize,
+    #[clap(long, default_value_t = -120.0, allow_negative_numbers = true)]
+    floor: f64,
+}
+
+fn parse(config: &str, args: &[&str]) -> anyhow::Result<Command> {
+    let command = config.parse::<Config>()?.apply(Cli::command())?;
+    let matches = command.try_get_matches_from(args)?;
+    Ok(Cli::from_arg_matches(&matches)?.command)
+}
+
+#[test]
+fn config_replaces_defaults() {
+    let config = r#"
+        fft_size = 4096
+
+        [visualize]
+        width = 1200
+        log-frequency = true
+
+        [record]
+        output = "out.wav"
+        floor = -90.5
+    "#;
+    assert_eq!(
+        parse(config, &["app", "visualize"]).unwrap(),
+        Command::Visualize(VisualizeArgs {
+            width: 1200,
+            frame_rate: 30.0,
+            fft_size: 4096,
+            log_frequency: true,
+        })
+    );
+    assert_eq!(
+        parse(config, &["app", "record"]).unwrap(),
+        Command::Record(RecordArgs {
+            output: "out.wav".into(),
+            fft_size: 4096,
+            floor: -90.5,
+        })
+    );
+}
+
+#[test]
+fn command_line_overrides_config() {
+    let config = "fft_size = 4096\n[record]\nfft_size = 8192\noutput = \"out.wav\"\n";
+    assert_eq!(
+        parse(
+            config,
+            &["app", "record", "-o", "cli.wav", "--fft-size", "512"]
+        )
+        .unwrap(),
+        Command::Record(RecordArgs {
+            output: "cli.wav".into(),
+            fft_size: 512,
+            floor: -120.0,
+        })
+    );
+    // 表の中の値がトップレベルより優先される
+    let Command::Record(args) = parse(config, &["app", "record"]).unwrap() else {
+        panic!("not a record command");
+    };
+    assert_eq!(args.fft_size, 8192);
+}
+
+#[test]
+fn rejects_unknown_keys() {
+    let unknown = [
+        "bogus = 1",
+        "[visualize]\nfloor = -90",
+        "[play]\nwidth = 1",
+        "width = [1, 2]",
+    ];
+    for config in unknown {
+        let config = config.parse::<Config>().unwrap();
+        assert!(config.apply(Cli::command()).is_err(), "{config:?}");
+    }
+    assert!("width = ".parse::<Config>().is_err());
+}
+
+#[test]
+fn rejects_invalid_values_from_config() {
+    let invalid = [
+        "[visualize]\nwidth = 0",
+        "frame_rate = 0",
+        "frame_rate = -1.5",
+        "frame_rate = inf",
+        "frame_rate = nan",
+    ];
+    for config in invalid {
+        assert!(parse(config, &["app", "visualize"]).is_err(), "{config}");
+    }
+    let Command::Visualize(args) = parse("frame_rate = 60", &["app", "visualize"]).unwrap() else {
+        panic!("not a visualize command");
+    };
+    assert_eq!(args.frame_rate, 60.0);
+}
+
+#[test]
+fn loads_config_file() {
+    let path = std::env::temp_dir().join("windows-cap-audio-config.toml");
+    std::fs::write(&path, "[visualize]\nwidth = 640\n").unwrap();
+    let config = Config::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let matches = config
+        .apply(Cli::command())
+        .unwrap()
+        .get_matches_from(["app", "visualize"]);
+    let Command::Visualize(args) = Cli::from_arg_matches(&matches).unwrap().command else {
+        panic!("not a visualize command");
+    };
+    assert_eq!(args.width, 640);
+    assert!(Config::load(&path).is_err());
+}
@@ -0,0 +1,140 @@
+use windows_cap_audio::{
+    decode::Decoder,
+    format::{
+        Guid, SampleFormat, WaveFormatEx, WaveFormatExtensible, WAVE_FORMAT_EXTENSIBLE,
+        WAVE_FORMAT_IEEE_FLOAT, WAVE_FORMAT_PCM,
+    },
+};
+
+fn format(format_tag: u16, channels: u16, bits_per_sample: u16) -> WaveFormatEx {
+    let block_align = channels * bits_per_sample / 8;
+    WaveFormatEx {
+        format_tag,
+        channels,
+        samples_per_sec: 48_000,
+        avg_bytes_per_sec: 48_000 * block_align as u32,
+        block_align,
+        bits_per_sample,
+        size: 0,
+        extensible: None,
+    }
+}
+
+fn extensible(sub_format: Guid, channels: u16, bits: u16, valid_bits: u16) -> WaveFormatEx {
+    WaveFormatEx {
+        size: 22,
+        extensible: Some(WaveFormatExtensible {
+            valid_bits_per_sample: valid_bits,
+            channel_mask: 0b11,
+            sub_format,
+        }),
+        ..format(WAVE_FORMAT_EXTENSIBLE, channels, bits)
+    }
+}
+
+#[test]
+fn decodes_pcm8() {
+    let decoder = Decoder::new(&format(WAVE_FORMAT_PCM, 1, 8)).unwrap();
+    assert_eq!(decoder.sample_format(), SampleFormat::U8);
+    assert_eq!(decoder.decode(&[0, 128, 192]), vec![vec![-1.0, 0.0, 0.5]]);
+}
+
+#[test]
+fn decodes_pcm16() {
+    let decoder = Decoder::new(&format(WAVE_FORMAT_PCM, 1, 16)).unwrap();
+    let bytes = [i16::MIN, 0, 16_384]
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect::<Vec<_>>();
+    assert_eq!(decoder.decode(&bytes), vec![vec![-1.0, 0.0, 0.5]]);
+}
+
+#[test]
+fn decodes_pcm24() {
+    let decoder = Decoder::new(&format(WAVE_FORMAT_PCM, 1, 24)).unwrap();
+    let bytes = [0x00, 0x00, 0x80, 0x00, 0x00, 0x40, 0xff, 0xff, 0xff];
+    let expected = vec![-1.0, 0.5, -1.0 / 8_388_608.0];
+    assert_eq!(decoder.decode(&bytes), vec![expected]);
+}
+
+#[test]
+fn decodes_pcm32() {
+    let decoder = Decoder::new(&format(WAVE_FORMAT_PCM, 1, 32)).unwrap();
+    let bytes = [i32::MIN, 0, 1 << 30]
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect::<Vec<_>>();
+    assert_eq!(decoder.decode(&bytes), vec![vec![-1.0, 0.0, 0.5]]);
+}
+
+#[test]
+fn decodes_float32() {
+    let decoder = Decoder::new(&format(WAVE_FORMAT_IEEE_FLOAT, 1, 32)).unwrap();
+    let bytes = [-1.0f32, 0.25, 1.0]
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect::<Vec<_>>();
+    assert_eq!(decoder.decode(&bytes), vec![vec![-1.0, 0.25, 1.0]]);
+}
+
+#[test]
+fn decodes_float64() {
+    let decoder = Decoder::new(&format(WAVE_FORMAT_IEEE_FLOAT, 1, 64)).unwrap();
+    let bytes = [-1.0f64, 0.25, 1.0]
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect::<Vec<_>>();
+    assert_eq!(decoder.decode(&bytes), vec![vec![-1.0, 0.25, 1.0]]);
+}
+
+#[test]
+fn deinterleaves_channels() {
+    let decoder = Decoder::new(&format(WAVE_FORMAT_PCM, 2, 16)).unwrap();
+    let bytes = [16_384i16, -16_384, 0, i16::MIN]
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        decoder.decode(&bytes),
+        vec![vec![0.5, 0.0], vec![-0.5, -1.0]]
+    );
+}
+
+#[test]
+fn ignores_trailing_partial_frame() {
+    let decoder = Decoder::new(&format(WAVE_FORMAT_PCM, 2, 16)).unwrap();
+    assert_eq!(
+        decoder.decode(&[0, 0, 0, 0, 0, 0]),
+        vec![vec![0.0], vec![0.0]]
+    );
+}
+
+#[test]
+fn decodes_extensible_float() {
+    let wave_format = extensible(Guid::KSDATAFORMAT_SUBTYPE_IEEE_FLOAT, 2, 32, 32);
+    let decoder = Decoder::new(&wave_format).unwrap();
+    assert_eq!(decoder.sample_format(), SampleFormat::F32);
+    let bytes = [0.5f32, -0.5]
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect::<Vec<_>>();
+    assert_eq!(decoder.decode(&bytes), vec![vec![0.5], vec![-0.5]]);
+}
+
+#[test]
+fn decodes_extensible_pcm24_in_32bit_container() {
+    let wave_format = extensible(Guid::KSDATAFORMAT_SUBTYPE_PCM, 1, 32, 24);
+    let decoder = Decoder::new(&wave_format).unwrap();
+    assert_eq!(decoder.sample_format(), SampleFormat::I32);
+    // 24bit の値が上位に左詰めされている
+    let bytes = (0x40_0000i32 << 8).to_le_bytes();
+    assert_eq!(decoder.decode(&bytes), vec![vec![0.5]]);
+}
+
+#[test]
+fn rejects_unknown_formats() {
+    assert!(Decoder::new(&format(WAVE_FORMAT_PCM, 1, 12)).is_err());
+    assert!(Decoder::new(&format(WAVE_FORMAT_IEEE_FLOAT, 1, 16)).is_err());
+    assert!(Decoder::new(&format(0x0055, 2, 16)).is_err());
+    assert!(Decoder::new(&extensible(Guid(0x1234), 2, 16, 16)).is_err());
+}
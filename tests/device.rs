@@ -0,0 +1,214 @@
+use std::cell::RefCell;
+
+use anyhow::Result;
+use windows_cap_audio::device::{
+    default_data_flow, default_device, open_device, select_device, write_devices, CaptureMode,
+    DataFlow, DeviceEnumerator, DeviceInfo, DeviceState, ListFormat, Role,
+};
+
+fn device(id: &str, name: &str, data_flow: DataFlow, state: DeviceState) -> DeviceInfo {
+    DeviceInfo {
+        id: id.to_string(),
+        name: name.to_string(),
+        data_flow,
+        state,
+        default_roles: vec![],
+    }
+}
+
+fn devices() -> Vec<DeviceInfo> {
+    let mut speakers = device(
+        "{render-1}",
+        "Speakers (Realtek)",
+        DataFlow::Render,
+        DeviceState::Active,
+    );
+    speakers.default_roles = vec![Role::Console, Role::Multimedia];
+    let mut mic = device(
+        "{capture-1}",
+        "Microphone (USB)",
+        DataFlow::Capture,
+        DeviceState::Active,
+    );
+    mic.default_roles = Role::VARIANTS.to_vec();
+    vec![
+        speakers,
+        device(
+            "{render-2}",
+            "Headphones (USB)",
+            DataFlow::Render,
+            DeviceState::Active,
+        ),
+        mic,
+        device(
+            "{render-3}",
+            "Speakers",
+            DataFlow::Render,
+            DeviceState::Active,
+        ),
+        device(
+            "{render-4}",
+            "HDMI Output",
+            DataFlow::Render,
+            DeviceState::Unplugged,
+        ),
+        device(
+            "{render-5}",
+            "Headphones (USB)",
+            DataFlow::Render,
+            DeviceState::NotPresent,
+        ),
+    ]
+}
+
+/// 開いたデバイスの ID を返すだけの偽物
+struct FakeEnumerator {
+    devices: Vec<DeviceInfo>,
+    opened: RefCell<Vec<String>>,
+}
+
+impl DeviceEnumerator for FakeEnumerator {
+    type Device = String;
+
+    fn devices(&self) -> Result<Vec<DeviceInfo>> {
+        Ok(self.devices.clone())
+    }
+
+    fn open(&self, id: &str) -> Result<String> {
+        self.opened.borrow_mut().push(id.to_string());
+        Ok(id.to_string())
+    }
+}
+
+#[test]
+fn selects_by_id_index_and_name() {
+    let devices = devices();
+    let id = |query: &str| select_device(&devices, query).map(|device| device.id.clone());
+    assert_eq!(id("{capture-1}").unwrap(), "{capture-1}");
+    assert_eq!(id("1").unwrap(), "{render-2}");
+    assert!(id("6").is_err());
+    assert_eq!(id("realtek").unwrap(), "{render-1}");
+    assert_eq!(id("MICROPHONE").unwrap(), "{capture-1}");
+    assert!(id("bluetooth").is_err());
+}
+
+#[test]
+fn narrows_ambiguous_names() {
+    let devices = devices();
+    let id = |query: &str| select_device(&devices, query).map(|device| device.id.clone());
+    // 有効なものに絞る
+    assert_eq!(id("headphones").unwrap(), "{render-2}");
+    // 名前が完全に一致するものに絞る
+    assert_eq!(id("speakers").unwrap(), "{render-3}");
+    // USB は 2 つとも有効なので決められない
+    let error = id("usb").unwrap_err().to_string();
+    assert!(error.contains("Ambiguous"), "{error}");
+}
+
+#[test]
+fn finds_default_devices() {
+    let devices = devices();
+    assert_eq!(
+        default_device(&devices, DataFlow::Render).unwrap().id,
+        "{render-1}"
+    );
+    assert_eq!(
+        default_device(&devices, DataFlow::Capture).unwrap().id,
+        "{capture-1}"
+    );
+    assert!(default_device(&devices[1..2], DataFlow::Render).is_err());
+}
+
+#[test]
+fn opens_selected_device() {
+    let enumerator = FakeEnumerator {
+        devices: devices(),
+        opened: RefCell::new(vec![]),
+    };
+    let (info, device) = open_device(&enumerator, None).unwrap();
+    assert_eq!(info.name, "Speakers (Realtek)");
+    assert_eq!(device, "{render-1}");
+    let (_, device) = open_device(&enumerator, Some("default")).unwrap();
+    assert_eq!(device, "{render-1}");
+    let (info, device) = open_device(&enumerator, Some("2")).unwrap();
+    assert_eq!(info.data_flow, DataFlow::Capture);
+    assert_eq!(device, "{capture-1}");
+
+    let (info, device) = open_device(&enumerator, Some("default:capture")).unwrap();
+    assert_eq!(info.name, "Microphone (USB)");
+    assert_eq!(device, "{capture-1}");
+    assert!(open_device(&enumerator, Some("default:speakers")).is_err());
+
+    // 外されたデバイスは開かない
+    let error = open_device(&enumerator, Some("hdmi"))
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("unplugged"), "{error}");
+    assert_eq!(enumerator.opened.borrow().len(), 4);
+}
+
+#[test]
+fn capture_mode_follows_data_flow() {
+    for mode in CaptureMode::VARIANTS {
+        assert_eq!(CaptureMode::for_data_flow(mode.data_flow()), mode);
+    }
+    let devices = devices();
+    let modes = devices
+        .iter()
+        .take(3)
+        .map(|device| CaptureMode::for_data_flow(device.data_flow))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        modes,
+        [
+            CaptureMode::Loopback,
+            CaptureMode::Loopback,
+            CaptureMode::Direct
+        ]
+    );
+}
+
+#[test]
+fn default_queries_name_data_flow() {
+    assert_eq!(default_data_flow(None).unwrap(), Some(DataFlow::Render));
+    assert_eq!(
+        default_data_flow(Some("Default")).unwrap(),
+        Some(DataFlow::Render)
+    );
+    assert_eq!(
+        default_data_flow(Some("default:capture")).unwrap(),
+        Some(DataFlow::Capture)
+    );
+    assert_eq!(default_data_flow(Some("Speakers")).unwrap(), None);
+    assert_eq!(default_data_flow(Some("{render-1}")).unwrap(), None);
+    assert!(default_data_flow(Some("default:sideways")).is_err());
+}
+
+#[test]
+fn writes_table_and_json() {
+    let mut devices = devices();
+    devices.truncate(3);
+    devices[1].name = "Say \"hi\"\\".to_string();
+
+    let mut table = vec![];
+    write_devices(&mut table, &devices, ListFormat::Table).unwrap();
+    let table = String::from_utf8(table).unwrap();
+    let lines = table.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].contains("NAME"));
+    assert!(lines[1].contains("console,multimedia"));
+    assert!(lines[3].starts_with("  2  capture"));
+
+    let mut json = vec![];
+    write_devices(&mut json, &devices, ListFormat::Json).unwrap();
+    let json = String::from_utf8(json).unwrap();
+    assert!(json.starts_with("[\n  {\"index\":0,\"id\":\"{render-1}\""));
+    assert!(json.contains(r#""name":"Say \"hi\"\\""#));
+    assert!(json.contains(r#""data_flow":"capture","state":"active","default_roles":["console","multimedia","communications"]"#));
+    assert!(json.trim_end().ends_with("}\n]"));
+
+    let mut empty = vec![];
+    write_devices(&mut empty, &[], ListFormat::Json).unwrap();
+    assert_eq!(empty, b"[]\n");
+    assert_eq!("JSON".parse::<ListFormat>().unwrap(), ListFormat::Json);
+}
@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use windows_cap_audio::{
+    fft::Fft,
+    source::MemorySource,
+    spectrum::{Binning, Scale},
+    util::App,
+    window::Window,
+};
+
+#[test]
+fn rejects_invalid_sizes() {
+    assert!(Fft::new(128).is_err());
+    assert!(Fft::new(1000).is_err());
+    assert!(Fft::new(131_072).is_err());
+    assert!(Fft::new(65_536).is_ok());
+}
+
+#[test]
+fn finds_bin_of_pure_tone() {
+    let fft = Fft::new(256).unwrap();
+    // ちょうど 8 ビン目の余弦波
+    let samples = (0..256)
+        .map(|n| (std::f32::consts::TAU * 8.0 * n as f32 / 256.0).cos())
+        .collect::<Vec<_>>();
+    let magnitudes = fft.magnitudes(&samples);
+    assert_eq!(magnitudes.len(), 129);
+    assert!((magnitudes[8] - 128.0).abs() < 1e-3);
+    assert!(magnitudes
+        .iter()
+        .enumerate()
+        .all(|(k, m)| k == 8 || *m < 1e-3));
+}
+
+#[test]
+fn windows_are_normalized() {
+    for window in Window::VARIANTS {
+        let coefficients = window.coefficients(1024);
+        assert_eq!(coefficients.len(), 1024);
+        let peak = coefficients.iter().copied().fold(f32::MIN, f32::max);
+        // flat-top は SRS の係数のままなので 1 を超える
+        if window != Window::FlatTop {
+            assert!((peak - 1.0).abs() < 1e-3, "{window:?}: {peak}");
+        }
+        assert_eq!(window.name().parse::<Window>().unwrap(), window);
+    }
+    assert_eq!(Window::FlatTop.next(), Window::Rectangular);
+}
+
+#[test]
+fn every_window_reads_0_dbfs_for_full_scale_sine() {
+    for window in Window::VARIANTS {
+        // 256 だと flat-top のメインローブがオクターブバンドからはみ出す
+        for size in [4096, 65_536] {
+            let source =
+                MemorySource::sine(1_000.0, 1.0, 1, 48_000, Duration::from_secs(2)).unwrap();
+            let mut app = App::new("sine".to_string(), source)
+                .with_binning(Binning::Octave)
+                .with_scale(Scale::default())
+                .with_window(window)
+                .with_fft_size(size)
+                .unwrap();
+            app.on_tick().unwrap();
+            let peak = app.data().iter().map(|(_, v)| *v).fold(f64::MIN, f64::max);
+            // 矩形窓は漏れが帯域の外まで広がるので少し甘く見る
+            let tolerance = if window == Window::Rectangular {
+                0.5
+            } else {
+                0.2
+            };
+            assert!(peak.abs() < tolerance, "{window:?} {size}: {peak}");
+        }
+    }
+}
+
+#[test]
+fn resolution_follows_fft_size() {
+    let source = MemorySource::sine(1_000.0, 1.0, 1, 48_000, Duration::from_millis(10)).unwrap();
+    let mut app = App::new("sine".to_string(), source);
+    assert_eq!(app.frequency_resolution(), 48_000.0 / 2048.0);
+    app.set_fft_size(8192).unwrap();
+    assert_eq!(app.fft_size(), 8192);
+    assert_eq!(app.hop(), 4096);
+    assert!(app.set_fft_size(100).is_err());
+    assert_eq!(app.fft_size(), 8192);
+}
@@ -0,0 +1,315 @@
+use std::{
+    cell::Cell,
+    collections::VecDeque,
+    rc::Rc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Error, Result};
+use windows_cap_audio::{
+    follow::{Connector, FollowSource, FollowState},
+    format::WaveFormatEx,
+    packet::{CaptureStats, Packet},
+    recorder::{Limit, MemorySink, Recorder},
+    source::AudioSource,
+};
+
+/// `Mock` が返すもの
+enum Step {
+    Frames(u64),
+    Lost,
+    Fail,
+}
+
+/// 決められた順にパケットやエラーを返すソース
+struct Mock {
+    wave_format: WaveFormatEx,
+    steps: VecDeque<Step>,
+}
+
+impl AudioSource for Mock {
+    fn wave_format(&self) -> &WaveFormatEx {
+        &self.wave_format
+    }
+
+    fn get_buffer(&mut self) -> Result<Option<Vec<u8>>> {
+        Ok(self.get_packet()?.map(|packet| packet.data))
+    }
+
+    fn get_packet(&mut self) -> Result<Option<Packet>> {
+        match self.steps.pop_front() {
+            Some(Step::Frames(frames)) => Ok(Some(Packet::new(vec![1; frames as usize * 4], 4))),
+            Some(Step::Lost) => bail!("lost"),
+            Some(Step::Fail) => bail!("broken"),
+            None => Ok(None),
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+/// 開く度に `script` の先頭を返す。`None` なら開けない
+struct MockConnector {
+    script: VecDeque<Option<Mock>>,
+    switch: Rc<Cell<bool>>,
+    connects: u32,
+}
+
+impl MockConnector {
+    fn new(script: Vec<Option<Vec<Step>>>) -> MockConnector {
+        let script = script
+            .into_iter()
+            .map(|steps| {
+                steps.map(|steps| Mock {
+                    wave_format: WaveFormatEx::ieee_float(1, 1_000),
+                    steps: steps.into(),
+                })
+            })
+            .collect();
+        MockConnector {
+            script,
+            switch: Rc::new(Cell::new(false)),
+            connects: 0,
+        }
+    }
+}
+
+impl Connector for MockConnector {
+    type Source = Mock;
+
+    /// フォーマットはいつも同じなので `_format` には合わせなくてよい
+    fn connect(&mut self, _format: Option<&WaveFormatEx>) -> Result<Mock> {
+        self.connects += 1;
+        self.script
+            .pop_front()
+            .flatten()
+            .ok_or_else(|| anyhow!("no device"))
+    }
+
+    fn should_reconnect(&mut self) -> bool {
+        self.switch.replace(false)
+    }
+
+    fn is_device_lost(&self, error: &Error) -> bool {
+        error.to_string() == "lost"
+    }
+}
+
+/// `source` が終わるまでのパケット。何も無い時は少し待つ
+fn drain<C: Connector>(source: &mut FollowSource<C>) -> Result<Vec<Packet>> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut packets = vec![];
+    while !source.is_finished() && Instant::now() < deadline {
+        match source.get_packet()? {
+            Some(packet) => packets.push(packet),
+            None => source.wait(Duration::from_millis(10))?,
+        }
+    }
+    Ok(packets)
+}
+
+#[test]
+fn reconnects_after_device_lost() {
+    let connector = MockConnector::new(vec![
+        Some(vec![Step::Frames(10), Step::Lost]),
+        Some(vec![Step::Frames(20)]),
+    ]);
+    let mut source = FollowSource::new(connector)
+        .unwrap()
+        .with_fill_silence(false);
+    let packets = drain(&mut source).unwrap();
+    let frames = packets.iter().map(|p| p.info.frames).collect::<Vec<_>>();
+    assert_eq!(frames, [10, 20]);
+    assert!(!packets[0].info.reconnected);
+    assert!(packets[1].info.reconnected);
+    assert_eq!(source.reconnects(), 1);
+    assert_eq!(source.state(), FollowState::Connected);
+
+    let mut stats = CaptureStats::default();
+    packets.iter().for_each(|p| stats.record(&p.info));
+    assert_eq!(stats.reconnects, 1);
+    assert!(stats.has_glitches());
+}
+
+#[test]
+fn retries_until_device_returns() {
+    let connector = MockConnector::new(vec![
+        Some(vec![Step::Lost]),
+        None,
+        None,
+        Some(vec![Step::Frames(5)]),
+    ]);
+    let mut source = FollowSource::new(connector)
+        .unwrap()
+        .with_retry_interval(Duration::from_millis(1))
+        .with_fill_silence(false);
+    assert!(source.get_packet().unwrap().is_none());
+    assert_eq!(source.state(), FollowState::Reconnecting { attempts: 0 });
+    assert!(source.get_packet().unwrap().is_none());
+    assert_eq!(source.state(), FollowState::Reconnecting { attempts: 1 });
+
+    let packets = drain(&mut source).unwrap();
+    assert_eq!(packets.len(), 1);
+    assert!(packets[0].info.reconnected);
+    assert_eq!(source.connector().connects, 4);
+}
+
+#[test]
+fn waits_between_attempts() {
+    let connector = MockConnector::new(vec![Some(vec![Step::Lost]), None, None]);
+    let mut source = FollowSource::new(connector)
+        .unwrap()
+        .with_retry_interval(Duration::from_secs(60));
+    assert!(source.get_packet().unwrap().is_none());
+    assert!(source.get_packet().unwrap().is_none());
+    assert!(source.get_packet().unwrap().is_none());
+    assert_eq!(source.state(), FollowState::Reconnecting { attempts: 1 });
+    assert_eq!(source.connector().connects, 2);
+}
+
+#[test]
+fn switches_when_asked() {
+    let connector = MockConnector::new(vec![
+        Some(vec![Step::Frames(10), Step::Frames(10)]),
+        Some(vec![Step::Frames(30)]),
+    ]);
+    let switch = connector.switch.clone();
+    let mut source = FollowSource::new(connector)
+        .unwrap()
+        .with_fill_silence(false);
+    assert_eq!(source.get_packet().unwrap().unwrap().info.frames, 10);
+    switch.set(true);
+    let packet = source.get_packet().unwrap().unwrap();
+    assert_eq!(packet.info.frames, 30);
+    assert!(packet.info.reconnected);
+    assert!(source.is_finished());
+}
+
+#[test]
+fn fills_outage_with_silence() {
+    let connector = MockConnector::new(vec![Some(vec![Step::Lost]), Some(vec![Step::Frames(10)])]);
+    let mut source = FollowSource::new(connector).unwrap();
+    assert!(source.get_packet().unwrap().is_none());
+    thread::sleep(Duration::from_millis(50));
+    let packets = drain(&mut source).unwrap();
+
+    let (silence, rest) = packets.split_at(packets.len() - 1);
+    assert!(!silence.is_empty());
+    assert!(silence[0].info.reconnected);
+    assert!(silence.iter().all(|p| p.info.silent));
+    assert!(silence.iter().all(|p| p.data.iter().all(|&b| b == 0)));
+    assert!(silence.iter().map(|p| p.info.frames).sum::<u64>() >= 50);
+    assert_eq!(rest[0].info.frames, 10);
+    assert!(!rest[0].info.reconnected);
+}
+
+#[test]
+fn fills_u8_outage_with_midpoint() {
+    let mut connector =
+        MockConnector::new(vec![Some(vec![Step::Lost]), Some(vec![Step::Frames(10)])]);
+    for mock in connector.script.iter_mut().flatten() {
+        mock.wave_format = WaveFormatEx::pcm(1, 1_000, 8);
+    }
+    let mut source = FollowSource::new(connector).unwrap();
+    assert!(source.get_packet().unwrap().is_none());
+    thread::sleep(Duration::from_millis(20));
+    let silence = drain(&mut source).unwrap().swap_remove(0);
+    assert!(silence.info.silent);
+    assert!(silence.data.iter().all(|&b| b == 0x80));
+}
+
+#[test]
+fn other_errors_propagate() {
+    let connector = MockConnector::new(vec![Some(vec![Step::Fail]), Some(vec![Step::Frames(10)])]);
+    let mut source = FollowSource::new(connector).unwrap();
+    assert!(source.get_packet().is_err());
+    assert_eq!(source.connector().connects, 1);
+}
+
+#[test]
+fn first_connect_error_is_returned() {
+    let connector = MockConnector::new(vec![None]);
+    assert!(FollowSource::new(connector).is_err());
+}
+
+#[test]
+fn changed_format_is_passed_on() {
+    let mut connector = MockConnector::new(vec![
+        Some(vec![Step::Frames(10), Step::Lost]),
+        Some(vec![Step::Frames(20), Step::Frames(20)]),
+    ]);
+    connector.script[1].as_mut().unwrap().wave_format = WaveFormatEx::ieee_float(1, 2_000);
+    let mut source = FollowSource::new(connector)
+        .unwrap()
+        .with_fill_silence(false);
+    let packets = drain(&mut source).unwrap();
+    let frames = packets.iter().map(|p| p.info.frames).collect::<Vec<_>>();
+    assert_eq!(frames, [10, 20, 20]);
+    assert_eq!(packets[0].format, None);
+    assert_eq!(packets[1].format, Some(WaveFormatEx::ieee_float(1, 2_000)));
+    assert_eq!(packets[2].format, None);
+    assert_eq!(source.wave_format().samples_per_sec, 2_000);
+    assert_eq!(source.state(), FollowState::Connected);
+}
+
+#[test]
+fn silence_uses_changed_format() {
+    let mut connector =
+        MockConnector::new(vec![Some(vec![Step::Lost]), Some(vec![Step::Frames(10)])]);
+    connector.script[1].as_mut().unwrap().wave_format = WaveFormatEx::ieee_float(2, 2_000);
+    let mut source = FollowSource::new(connector).unwrap();
+    assert!(source.get_packet().unwrap().is_none());
+    thread::sleep(Duration::from_millis(20));
+    let silence = drain(&mut source).unwrap().swap_remove(0);
+    assert!(silence.info.silent);
+    assert_eq!(silence.format, Some(WaveFormatEx::ieee_float(2, 2_000)));
+    assert_eq!(silence.data.len() as u64, silence.info.frames * 8);
+}
+
+#[test]
+fn recorder_starts_new_segment_on_format_change() {
+    let mut connector = MockConnector::new(vec![
+        Some(vec![Step::Frames(100), Step::Lost]),
+        Some(vec![Step::Frames(100), Step::Frames(100)]),
+    ]);
+    connector.script[1].as_mut().unwrap().wave_format = WaveFormatEx::ieee_float(1, 2_000);
+    let source = FollowSource::new(connector)
+        .unwrap()
+        .with_fill_silence(false);
+    let mut sink = MemorySink::default();
+    let mut recorder = Recorder::new(source, &mut sink)
+        .unwrap()
+        .with_limit(Limit::Duration(Duration::from_millis(150)))
+        .with_loudness();
+    recorder.run().unwrap();
+    // 1 kHz で 0.1 秒、2 kHz で残りの 0.05 秒
+    assert_eq!(recorder.frames(), 200);
+    assert_eq!(recorder.recorded(), Duration::from_millis(150));
+    assert_eq!(recorder.wave_format().samples_per_sec, 2_000);
+    assert_eq!(recorder.capture_stats().reconnects, 1);
+    drop(recorder);
+    assert_eq!(sink.wave_format, Some(WaveFormatEx::ieee_float(1, 2_000)));
+    assert_eq!(sink.gaps, [(0, "reconnect".to_string())]);
+    assert!(sink.finished);
+}
+
+#[test]
+fn recorder_keeps_running() {
+    let connector = MockConnector::new(vec![
+        Some(vec![Step::Frames(100), Step::Lost]),
+        Some(vec![Step::Frames(100)]),
+    ]);
+    let source = FollowSource::new(connector)
+        .unwrap()
+        .with_fill_silence(false);
+    let mut sink = MemorySink::default();
+    let mut recorder = Recorder::new(source, &mut sink)
+        .unwrap()
+        .with_limit(Limit::Frames(200));
+    recorder.run().unwrap();
+    assert_eq!(recorder.capture_stats().reconnects, 1);
+    assert_eq!(recorder.capture_stats().frames, 200);
+}
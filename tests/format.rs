@@ -0,0 +1,99 @@
+use windows_cap_audio::format::{
+    Guid, SampleFormat, WaveFormatEx, WaveFormatExtensible, WAVE_FORMAT_EXTENSIBLE,
+    WAVE_FORMAT_IEEE_FLOAT, WAVE_FORMAT_PCM,
+};
+
+/// 共有モードでよく返ってくる 48kHz/2ch/32bit float の `WAVEFORMATEXTENSIBLE`
+const MIX_FORMAT: [u8; 40] = [
+    0xfe, 0xff, 0x02, 0x00, 0x80, 0xbb, 0x00, 0x00, 0x00, 0xdc, 0x05, 0x00, 0x08, 0x00, 0x20, 0x00,
+    0x16, 0x00, 0x20, 0x00, 0x03, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00,
+    0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+];
+
+#[test]
+fn parses_extensible_blob() {
+    let format = WaveFormatEx::from_bytes(&MIX_FORMAT).unwrap();
+    assert_eq!(format.format_tag, WAVE_FORMAT_EXTENSIBLE);
+    assert_eq!(format.channels, 2);
+    assert_eq!(format.samples_per_sec, 48_000);
+    assert_eq!(format.avg_bytes_per_sec, 384_000);
+    assert_eq!(format.block_align, 8);
+    assert_eq!(format.bits_per_sample, 32);
+    assert_eq!(format.size, 22);
+    assert_eq!(
+        format.extensible,
+        Some(WaveFormatExtensible {
+            valid_bits_per_sample: 32,
+            channel_mask: 0x3,
+            sub_format: Guid::KSDATAFORMAT_SUBTYPE_IEEE_FLOAT,
+        })
+    );
+    assert_eq!(format.effective_format_tag(), Some(WAVE_FORMAT_IEEE_FLOAT));
+    assert_eq!(format.sample_format().unwrap(), SampleFormat::F32);
+}
+
+#[test]
+fn round_trips_extensible_blob() {
+    let format = WaveFormatEx::from_bytes(&MIX_FORMAT).unwrap();
+    assert_eq!(format.to_bytes(), MIX_FORMAT);
+}
+
+#[test]
+fn extensible_keeps_its_block_with_small_cb_size() {
+    let mut format = WaveFormatEx::from_bytes(&MIX_FORMAT).unwrap();
+    format.size = 0;
+    let bytes = format.to_bytes();
+    assert_eq!(bytes, MIX_FORMAT);
+    let parsed = WaveFormatEx::from_bytes(&bytes).unwrap();
+    assert_eq!(parsed.size, WaveFormatExtensible::SIZE);
+    assert_eq!(parsed.extensible, format.extensible);
+}
+
+fn pcm16() -> WaveFormatEx {
+    WaveFormatEx {
+        format_tag: WAVE_FORMAT_PCM,
+        channels: 1,
+        samples_per_sec: 44_100,
+        avg_bytes_per_sec: 88_200,
+        block_align: 2,
+        bits_per_sample: 16,
+        size: 0,
+        extensible: None,
+    }
+}
+
+#[test]
+fn round_trips_plain_pcm() {
+    let format = pcm16();
+    assert_eq!(
+        WaveFormatEx::from_bytes(&format.to_bytes()).unwrap(),
+        format
+    );
+}
+
+#[test]
+fn parses_pcmwaveformat_without_cb_size() {
+    let format = WaveFormatEx::from_bytes(&pcm16().to_bytes()[..16]).unwrap();
+    assert_eq!(format, pcm16());
+    // 拡張形式で cbSize が無いのはおかしい
+    assert!(WaveFormatEx::from_bytes(&MIX_FORMAT[..16]).is_err());
+}
+
+#[test]
+fn rejects_truncated_blobs() {
+    assert!(WaveFormatEx::from_bytes(&MIX_FORMAT[..12]).is_err());
+    assert!(WaveFormatEx::from_bytes(&MIX_FORMAT[..30]).is_err());
+
+    let mut short_extension = MIX_FORMAT[..18].to_vec();
+    short_extension[16] = 0;
+    assert!(WaveFormatEx::from_bytes(&short_extension).is_err());
+}
+
+#[test]
+fn guid_layout_matches_windows() {
+    let guid = Guid::KSDATAFORMAT_SUBTYPE_PCM;
+    assert_eq!(guid.to_string(), "{00000001-0000-0010-8000-00AA00389B71}");
+    assert_eq!(Guid::from_bytes(guid.to_bytes()), guid);
+    assert_eq!(guid.format_tag(), Some(WAVE_FORMAT_PCM));
+    assert_eq!(Guid(0x1234).format_tag(), None);
+}
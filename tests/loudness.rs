@@ -0,0 +1,185 @@
+use std::time::Duration;
+
+use windows_cap_audio::{
+    decode::Decoder,
+    format::{
+        SPEAKER_FRONT_CENTER, SPEAKER_FRONT_LEFT, SPEAKER_FRONT_RIGHT, SPEAKER_LOW_FREQUENCY,
+        SPEAKER_SIDE_LEFT, SPEAKER_SIDE_RIGHT,
+    },
+    loudness::LoudnessMeter,
+    source::{AudioSource, MemorySource},
+    util::App,
+};
+
+/// 48kHz ステレオで両チャンネル同じ正弦波
+fn sine(freq: f64, dbfs: f64, seconds: f64) -> Vec<Vec<f32>> {
+    let amplitude = 10f64.powf(dbfs / 20.0);
+    let samples = (0..(48_000.0 * seconds) as usize)
+        .map(|n| (amplitude * (std::f64::consts::TAU * freq * n as f64 / 48_000.0).sin()) as f32)
+        .collect::<Vec<_>>();
+    vec![samples.clone(), samples]
+}
+
+fn assert_near(actual: Option<f64>, expected: f64, tolerance: f64) {
+    let actual = actual.unwrap();
+    assert!(
+        (actual - expected).abs() <= tolerance,
+        "{actual} is not {expected}±{tolerance}"
+    );
+}
+
+#[test]
+fn reference_tone_reads_minus_23_lufs() {
+    // EBU Tech 3341: 1kHz, -23dBFS のステレオは -23 LUFS
+    let mut meter = LoudnessMeter::new(2, 48_000);
+    assert_eq!(meter.momentary(), None);
+    // short-term が出る 3 秒だけ入れる
+    meter.process(&sine(1_000.0, -23.0, 3.0));
+    assert_near(meter.momentary(), -23.0, 0.1);
+    assert_near(meter.short_term(), -23.0, 0.1);
+    assert_near(meter.integrated(), -23.0, 0.1);
+    assert_near(meter.loudness_range(), 0.0, 0.1);
+    assert_near(meter.true_peak(), -23.0, 0.1);
+}
+
+#[test]
+fn works_at_other_rates() {
+    let mut meter = LoudnessMeter::new(1, 44_100);
+    let samples = (0..44_100 / 2)
+        .map(|n| (0.1 * (std::f64::consts::TAU * 1_000.0 * n as f64 / 44_100.0).sin()) as f32)
+        .collect::<Vec<_>>();
+    meter.process(&[samples]);
+    // モノラルは片チャンネル分なので 3dB 低い
+    assert_near(meter.integrated(), -23.0, 0.1);
+}
+
+#[test]
+fn gates_silence_and_quiet_parts() {
+    // 境目を跨ぐブロックも数えられるので、それが 0.1 LU に収まるくらいの長さは要る
+    let mut meter = LoudnessMeter::new(2, 48_000);
+    meter.process(&sine(1_000.0, -23.0, 8.0));
+    meter.process(&[vec![0.0; 96_000], vec![0.0; 96_000]]);
+    assert_near(meter.integrated(), -23.0, 0.1);
+    assert_eq!(meter.momentary(), Some(f64::NEG_INFINITY));
+
+    let mut meter = LoudnessMeter::new(2, 48_000);
+    meter.process(&sine(1_000.0, -23.0, 8.0));
+    meter.process(&sine(1_000.0, -43.0, 1.0));
+    assert_near(meter.integrated(), -23.0, 0.1);
+    assert_near(meter.max_momentary(), -23.0, 0.1);
+}
+
+#[test]
+fn measures_loudness_range() {
+    // EBU Tech 3342 のケース 1 (-20 と -30 を 20 秒ずつ) を 4 秒ずつに縮めたもの
+    let mut meter = LoudnessMeter::new(2, 48_000);
+    meter.process(&sine(1_000.0, -20.0, 4.0));
+    meter.process(&sine(1_000.0, -30.0, 4.0));
+    assert_near(meter.loudness_range(), 10.0, 1.0);
+}
+
+/// 7.1ch で `channel` だけに -23dBFS の正弦波を入れた時の integrated
+fn integrated_on_7_1_channel(channel: usize, channel_mask: Option<u32>) -> Option<f64> {
+    let tone = sine(1_000.0, -23.0, 0.5).swap_remove(0);
+    let mut channels = vec![vec![0.0; tone.len()]; 8];
+    channels[channel] = tone;
+    let mut meter = LoudnessMeter::new(8, 48_000);
+    if let Some(channel_mask) = channel_mask {
+        meter = meter.with_channel_mask(channel_mask);
+    }
+    meter.process(&channels);
+    meter.integrated()
+}
+
+#[test]
+fn weights_7_1_channels_by_speaker() {
+    // 1ch だけなら -23 から 3dB 下がる
+    assert_near(integrated_on_7_1_channel(0, None), -26.0, 0.1);
+    // LFE は数えない
+    assert_eq!(integrated_on_7_1_channel(3, None), None);
+    // バックとサイドは 1.41 倍 (+1.5dB)
+    assert_near(integrated_on_7_1_channel(4, None), -24.5, 0.1);
+    assert_near(integrated_on_7_1_channel(6, None), -24.5, 0.1);
+    assert_near(integrated_on_7_1_channel(7, None), -24.5, 0.1);
+
+    // 5.1 (サイド) のマスクなら 5, 6 チャンネル目がサイド
+    let mask = SPEAKER_FRONT_LEFT
+        | SPEAKER_FRONT_RIGHT
+        | SPEAKER_FRONT_CENTER
+        | SPEAKER_LOW_FREQUENCY
+        | SPEAKER_SIDE_LEFT
+        | SPEAKER_SIDE_RIGHT;
+    assert_eq!(integrated_on_7_1_channel(3, Some(mask)), None);
+    assert_near(integrated_on_7_1_channel(4, Some(mask)), -24.5, 0.1);
+    // マスクに無い 7, 8 チャンネル目は 1 倍
+    assert_near(integrated_on_7_1_channel(6, Some(mask)), -26.0, 0.1);
+}
+
+#[test]
+fn finds_inter_sample_peaks() {
+    // fs/4 で位相を 45 度ずらすとサンプル値は ±0.707 にしかならない
+    let samples = (0..4_800)
+        .map(|n| {
+            (std::f64::consts::FRAC_PI_2 * n as f64 + std::f64::consts::FRAC_PI_4).sin() as f32
+        })
+        .collect::<Vec<_>>();
+    let mut meter = LoudnessMeter::new(1, 48_000);
+    meter.process(&[samples]);
+    assert_near(meter.true_peak(), 0.0, 0.5);
+}
+
+#[test]
+fn summary_lists_every_value() {
+    let mut meter = LoudnessMeter::new(2, 48_000);
+    meter.process(&sine(1_000.0, -23.0, 3.0));
+    let summary = meter.summary();
+    assert_near(summary.integrated, -23.0, 0.1);
+    assert_near(summary.max_short_term, -23.0, 0.1);
+    assert!(summary.to_string().starts_with("integrated -23.0 LUFS"));
+
+    meter.reset();
+    assert_eq!(meter.summary().integrated, None);
+    assert_eq!(meter.summary().to_string().matches('-').count(), 6);
+}
+
+#[test]
+fn reads_decoded_stream() {
+    let mut source = MemorySource::sine(
+        1_000.0,
+        10f32.powf(-23.0 / 20.0),
+        2,
+        48_000,
+        Duration::from_secs(1),
+    )
+    .unwrap();
+    let decoder = Decoder::new(source.wave_format()).unwrap();
+    let mut meter = LoudnessMeter::new(decoder.channels(), 48_000);
+    while let Some(buffer) = source.get_buffer().unwrap() {
+        meter.process(&decoder.decode(&buffer));
+    }
+    assert_near(meter.integrated(), -23.0, 0.1);
+}
+
+#[test]
+fn app_meters_everything_it_reads() {
+    let source = MemorySource::sine(
+        1_000.0,
+        10f32.powf(-23.0 / 20.0),
+        2,
+        48_000,
+        Duration::from_secs(1),
+    )
+    .unwrap();
+    let mut app = App::new("sine".to_string(), source);
+    assert!(app.loudness().is_none());
+    app.on_tick().unwrap();
+    assert_near(app.loudness().unwrap().momentary(), -23.0, 0.1);
+
+    // FFT サイズを変えても測り直さない
+    app.set_fft_size(4096).unwrap();
+    app.on_tick().unwrap();
+    assert_near(app.loudness().unwrap().integrated(), -23.0, 0.1);
+
+    app.reset_loudness();
+    assert_eq!(app.loudness().unwrap().integrated(), None);
+}
@@ -0,0 +1,183 @@
+use anyhow::Result;
+use windows_cap_audio::{
+    capture::{CaptureThread, Overflow, PacketQueue},
+    format::WaveFormatEx,
+    packet::{CaptureStats, Packet, PacketInfo},
+    recorder::{MemorySink, Recorder},
+    source::{AudioSource, MemorySource},
+    util::App,
+};
+
+/// `frames` フレームずつのパケットを返すソース。`skip` が `(n, lost)` なら n 番目の前で位置を `lost` 飛ばす
+struct Positioned {
+    wave_format: WaveFormatEx,
+    packets: Vec<PacketInfo>,
+}
+
+impl Positioned {
+    fn new(count: u64, frames: u64, skip: Option<(u64, u64)>) -> Positioned {
+        let mut position = 0;
+        let mut packets = (0..count)
+            .map(|n| {
+                position += skip
+                    .filter(|&(before, _)| before == n)
+                    .map_or(0, |(_, lost)| lost);
+                let info = PacketInfo {
+                    frames,
+                    device_position: Some(position),
+                    qpc_position: Some(position * 10_000),
+                    discontinuity: n == 0,
+                    ..PacketInfo::default()
+                };
+                position += frames;
+                info
+            })
+            .collect::<Vec<_>>();
+        // 後ろから取り出す
+        packets.reverse();
+        Positioned {
+            wave_format: WaveFormatEx::ieee_float(1, 1_000),
+            packets,
+        }
+    }
+}
+
+impl AudioSource for Positioned {
+    fn wave_format(&self) -> &WaveFormatEx {
+        &self.wave_format
+    }
+
+    fn get_buffer(&mut self) -> Result<Option<Vec<u8>>> {
+        Ok(self.get_packet()?.map(|packet| packet.data))
+    }
+
+    fn get_packet(&mut self) -> Result<Option<Packet>> {
+        Ok(self.packets.pop().map(|info| Packet {
+            data: vec![0; info.frames as usize * 4],
+            info,
+            format: None,
+        }))
+    }
+
+    fn is_finished(&self) -> bool {
+        self.packets.is_empty()
+    }
+}
+
+fn info(frames: u64, position: u64) -> PacketInfo {
+    PacketInfo {
+        frames,
+        device_position: Some(position),
+        ..PacketInfo::default()
+    }
+}
+
+#[test]
+fn stats_count_position_gaps() {
+    let mut stats = CaptureStats::default();
+    stats.record(&info(10, 0));
+    stats.record(&info(10, 10));
+    stats.record(&info(10, 25));
+    stats.record(&info(10, 35));
+    assert_eq!(stats.packets, 4);
+    assert_eq!(stats.frames, 40);
+    assert_eq!(stats.gaps, 1);
+    assert_eq!(stats.lost_frames, 5);
+    assert!(stats.has_glitches());
+}
+
+#[test]
+fn stats_ignore_discontinuity_on_first_packet() {
+    let mut stats = CaptureStats::default();
+    let first = PacketInfo {
+        discontinuity: true,
+        ..info(10, 0)
+    };
+    stats.record(&first);
+    assert_eq!(stats.overruns, 0);
+    assert!(!stats.has_glitches());
+
+    let glitch = PacketInfo {
+        discontinuity: true,
+        silent: true,
+        timestamp_error: true,
+        ..info(10, 10)
+    };
+    stats.record(&glitch);
+    assert_eq!(stats.overruns, 1);
+    assert_eq!(stats.gaps, 0);
+    assert_eq!(stats.silent_packets, 1);
+    assert_eq!(stats.timestamp_errors, 1);
+}
+
+#[test]
+fn stats_count_dropped_packets_once() {
+    let mut stats = CaptureStats::default();
+    stats.record(&info(10, 0));
+    // 位置でも分かる途切れは 1 回に数える
+    let dropped = PacketInfo {
+        dropped_packets: 2,
+        dropped_frames: 20,
+        ..info(10, 30)
+    };
+    stats.record(&dropped);
+    assert_eq!(stats.gaps, 1);
+    assert_eq!(stats.lost_frames, 20);
+
+    // 位置が無くても捨てた分は途切れになる
+    let mut stats = CaptureStats::default();
+    let mut unpositioned = Packet::new(vec![0; 40], 4).info;
+    unpositioned.add_dropped(&Packet::new(vec![0; 40], 4).info);
+    stats.record(&unpositioned);
+    assert_eq!(stats.gaps, 1);
+    assert_eq!(stats.lost_frames, 10);
+    assert!(stats.has_glitches());
+}
+
+#[test]
+fn stats_without_positions_only_count() {
+    let mut stats = CaptureStats::default();
+    stats.record(&Packet::new(vec![0; 40], 4).info);
+    stats.record(&Packet::new(vec![0; 20], 4).info);
+    assert_eq!(stats.packets, 2);
+    assert_eq!(stats.frames, 15);
+    assert_eq!(stats.gaps, 0);
+}
+
+#[test]
+fn default_packets_count_frames() {
+    let mut source = MemorySource::from_f32(2, 1_000, &[0.0; 20])
+        .unwrap()
+        .with_frames_per_packet(4);
+    let packet = source.get_packet().unwrap().unwrap();
+    assert_eq!(packet.info.frames, 4);
+    assert_eq!(packet.info.device_position, None);
+}
+
+#[test]
+fn info_survives_capture_thread() {
+    let capture = CaptureThread::spawn(
+        || Ok(Positioned::new(10, 10, Some((5, 30)))),
+        PacketQueue::new(4, Overflow::Block),
+    )
+    .unwrap();
+    let mut sink = MemorySink::default();
+    let mut recorder = Recorder::new(capture, &mut sink).unwrap();
+    recorder.run().unwrap();
+    let stats = recorder.capture_stats();
+    assert_eq!(stats.packets, 10);
+    assert_eq!(stats.frames, 100);
+    assert_eq!(stats.gaps, 1);
+    assert_eq!(stats.lost_frames, 30);
+    assert_eq!(stats.overruns, 0);
+}
+
+#[test]
+fn app_collects_stats() {
+    let mut app = App::new("test".to_string(), Positioned::new(8, 256, None));
+    app.analyze_all(|_| Ok(())).unwrap();
+    let stats = app.capture_stats();
+    assert_eq!(stats.packets, 8);
+    assert_eq!(stats.frames, 2048);
+    assert!(!stats.has_glitches());
+}
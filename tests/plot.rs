@@ -0,0 +1,114 @@
+use std::{fs, path::Path};
+
+use windows_cap_audio::{
+    colormap::Colormap,
+    plot::{numbered_path, Axes, Plot},
+    spectrogram::Spectrogram,
+};
+
+const AXES: Axes = Axes {
+    frequency_range: (20.0, 20000.0),
+    log_frequency: true,
+    value_range: (-120.0, 0.0),
+};
+
+fn spectrum() -> Vec<(f64, f64)> {
+    (1..100).map(|i| (i as f64 * 200.0, -(i as f64))).collect()
+}
+
+#[test]
+fn log_axis_clamps_low_frequencies() {
+    let axes = Axes {
+        frequency_range: (0.0, 20_000.0),
+        ..AXES
+    };
+    let range = axes.x_range();
+    assert!(range.start.is_finite() && range.end.is_finite());
+    assert_eq!(range.start, 0.0);
+    let axes = Axes {
+        frequency_range: (-10.0, 20_000.0),
+        ..AXES
+    };
+    assert_eq!(axes.x_range().start, 0.0);
+    assert_eq!(AXES.x_range().start, 20f64.log10());
+
+    // 0 Hz から描いても止まらない
+    let spectra = [spectrum()];
+    let plot = Plot::Spectrum {
+        axes,
+        spectra: &spectra,
+        peaks: &[],
+        average: &[],
+    };
+    let png = std::env::temp_dir().join("windows-cap-audio-plot-zero.png");
+    plot.save(&png, (320, 240)).unwrap();
+    fs::remove_file(&png).unwrap();
+}
+
+#[test]
+fn saves_spectrum_as_png_and_svg() {
+    let spectra = [spectrum()];
+    let plot = Plot::Spectrum {
+        axes: AXES,
+        spectra: &spectra,
+        peaks: &spectra,
+        average: &spectra,
+    };
+
+    let png = std::env::temp_dir().join("windows-cap-audio-plot.png");
+    plot.save(&png, (320, 240)).unwrap();
+    let bytes = fs::read(&png).unwrap();
+    assert_eq!(&bytes[..8], b"\x89PNG\r\n\x1a\n");
+    fs::remove_file(&png).unwrap();
+
+    let svg = std::env::temp_dir().join("windows-cap-audio-plot.svg");
+    plot.save(&svg, (320, 240)).unwrap();
+    let text = fs::read_to_string(&svg).unwrap();
+    assert!(text.contains("<svg"));
+    assert!(text.contains("<polyline"));
+    fs::remove_file(&svg).unwrap();
+}
+
+#[test]
+fn embeds_spectrogram_in_svg() {
+    let mut spectrogram = Spectrogram::new(10);
+    for _ in 0..10 {
+        spectrogram.push(spectrum());
+    }
+    let plot = Plot::Spectrogram {
+        axes: AXES,
+        colormap: Colormap::Magma,
+        spectrogram: &spectrogram,
+        time_range: (0.0, 1.0),
+    };
+    let svg = std::env::temp_dir().join("windows-cap-audio-spectrogram.svg");
+    plot.save(&svg, (320, 240)).unwrap();
+    let text = fs::read_to_string(&svg).unwrap();
+    assert!(text.contains("<image"));
+    fs::remove_file(&svg).unwrap();
+}
+
+#[test]
+fn rejects_unknown_extension() {
+    let plot = Plot::Spectrum {
+        axes: AXES,
+        spectra: &[],
+        peaks: &[],
+        average: &[],
+    };
+    let path = std::env::temp_dir().join("windows-cap-audio-plot.bmp2");
+    assert!(plot.save(&path, (320, 240)).is_err());
+    assert!(!path.exists());
+}
+
+#[test]
+fn numbers_frame_paths() {
+    assert_eq!(
+        numbered_path(Path::new("out/frame.png"), 12),
+        Path::new("out/frame_000012.png")
+    );
+    assert_eq!(
+        numbered_path(Path::new("frame"), 3),
+        Path::new("frame_000003")
+    );
+}
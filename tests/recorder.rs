@@ -0,0 +1,276 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use windows_cap_audio::{
+    format::WaveFormatEx,
+    packet::{Packet, PacketInfo},
+    recorder::{Limit, MemorySink, Recorder, RecorderState, WavFileSink},
+    source::{AudioSource, MemorySource, WavSource},
+};
+
+fn ramp(frames: usize) -> MemorySource {
+    let samples = (0..frames).map(|n| n as f32).collect::<Vec<_>>();
+    MemorySource::from_f32(1, 1_000, &samples)
+        .unwrap()
+        .with_frames_per_packet(10)
+}
+
+/// 壊れたフォーマットを名乗るソース
+struct Broken(WaveFormatEx);
+
+impl AudioSource for Broken {
+    fn wave_format(&self) -> &WaveFormatEx {
+        &self.0
+    }
+
+    fn get_buffer(&mut self) -> Result<Option<Vec<u8>>> {
+        Ok(Some(vec![0; 4]))
+    }
+}
+
+/// 10 フレームずつ、途中で位置が飛んでから開き直すソース
+struct Glitchy {
+    wave_format: WaveFormatEx,
+    packets: Vec<PacketInfo>,
+}
+
+impl Glitchy {
+    fn new() -> Glitchy {
+        let info = |position, reconnected| PacketInfo {
+            frames: 10,
+            device_position: Some(position),
+            reconnected,
+            ..PacketInfo::default()
+        };
+        Glitchy {
+            wave_format: WaveFormatEx::ieee_float(1, 1_000),
+            // 後ろから取り出す
+            packets: vec![
+                info(10, false),
+                info(0, true),
+                info(25, false),
+                info(10, false),
+                info(0, false),
+            ],
+        }
+    }
+}
+
+impl AudioSource for Glitchy {
+    fn wave_format(&self) -> &WaveFormatEx {
+        &self.wave_format
+    }
+
+    fn get_buffer(&mut self) -> Result<Option<Vec<u8>>> {
+        Ok(self.get_packet()?.map(|packet| packet.data))
+    }
+
+    fn get_packet(&mut self) -> Result<Option<Packet>> {
+        Ok(self.packets.pop().map(|info| Packet {
+            data: vec![0; info.frames as usize * 4],
+            info,
+            format: None,
+        }))
+    }
+
+    fn is_finished(&self) -> bool {
+        self.packets.is_empty()
+    }
+}
+
+/// 2 パケット目からフォーマットが `ieee_float(2, 2_000)` に変わるソース
+struct Switching {
+    wave_format: WaveFormatEx,
+    packets: Vec<Packet>,
+}
+
+impl Switching {
+    fn new() -> Switching {
+        let changed = WaveFormatEx::ieee_float(2, 2_000);
+        Switching {
+            wave_format: WaveFormatEx::ieee_float(1, 1_000),
+            // 後ろから取り出す
+            packets: vec![
+                Packet::new(vec![0; 80], 8),
+                Packet {
+                    format: Some(changed),
+                    ..Packet::new(vec![0; 80], 8)
+                },
+                Packet::new(vec![0; 40], 4),
+            ],
+        }
+    }
+}
+
+impl AudioSource for Switching {
+    fn wave_format(&self) -> &WaveFormatEx {
+        &self.wave_format
+    }
+
+    fn get_buffer(&mut self) -> Result<Option<Vec<u8>>> {
+        Ok(self.get_packet()?.map(|packet| packet.data))
+    }
+
+    fn get_packet(&mut self) -> Result<Option<Packet>> {
+        let packet = self.packets.pop();
+        if let Some(format) = packet.as_ref().and_then(|packet| packet.format.clone()) {
+            self.wave_format = format;
+        }
+        Ok(packet)
+    }
+
+    fn is_finished(&self) -> bool {
+        self.packets.is_empty()
+    }
+}
+
+#[test]
+fn records_until_source_ends() {
+    let mut sink = MemorySink::default();
+    let mut recorder = Recorder::new(ramp(95), &mut sink).unwrap();
+    recorder.run().unwrap();
+    assert_eq!(recorder.state(), RecorderState::Stopped);
+    assert_eq!(recorder.frames(), 95);
+    drop(recorder);
+    assert!(sink.finished);
+    assert_eq!(sink.data.len(), 95 * 4);
+    assert_eq!(sink.wave_format.unwrap().samples_per_sec, 1_000);
+}
+
+#[test]
+fn stops_at_frame_limit_mid_packet() {
+    let mut sink = MemorySink::default();
+    let mut recorder = Recorder::new(ramp(100), &mut sink)
+        .unwrap()
+        .with_limit(Limit::Frames(25));
+    recorder.run().unwrap();
+    assert_eq!(recorder.frames(), 25);
+    drop(recorder);
+    let last = f32::from_le_bytes(sink.data[24 * 4..].try_into().unwrap());
+    assert_eq!(last, 24.0);
+}
+
+#[test]
+fn duration_limit_uses_sample_rate() {
+    let mut sink = MemorySink::default();
+    let mut recorder = Recorder::new(ramp(1_000), &mut sink)
+        .unwrap()
+        .with_limit(Limit::Duration(Duration::from_millis(50)));
+    recorder.run().unwrap();
+    assert_eq!(recorder.frames(), 50);
+}
+
+#[test]
+fn paused_audio_is_dropped() {
+    let mut sink = MemorySink::default();
+    let mut source = ramp(30);
+    let mut recorder = Recorder::new(&mut source, &mut sink).unwrap();
+    recorder.start().unwrap();
+    recorder.pause();
+    assert_eq!(recorder.state(), RecorderState::Paused);
+    assert!(!recorder.poll().unwrap());
+    assert_eq!(recorder.frames(), 0);
+    assert_eq!(recorder.state(), RecorderState::Stopped);
+}
+
+#[test]
+fn rejects_zero_block_align() {
+    let source = Broken(WaveFormatEx::ieee_float(0, 1_000));
+    assert!(Recorder::new(source, MemorySink::default()).is_err());
+}
+
+#[test]
+fn cannot_start_twice() {
+    let mut recorder = Recorder::new(ramp(10), MemorySink::default()).unwrap();
+    recorder.start().unwrap();
+    assert!(recorder.start().is_err());
+}
+
+#[test]
+fn measures_loudness_of_recorded_part() {
+    let amplitude = 10f32.powf(-23.0 / 20.0);
+    let source = MemorySource::sine(1_000.0, amplitude, 2, 48_000, Duration::from_secs(5)).unwrap();
+    let mut recorder = Recorder::new(source, MemorySink::default())
+        .unwrap()
+        .with_limit(Limit::Duration(Duration::from_secs(2)))
+        .with_loudness();
+    assert!(recorder.loudness().is_none());
+    recorder.run().unwrap();
+    let summary = recorder.loudness().unwrap().summary();
+    assert!((summary.integrated.unwrap() + 23.0).abs() < 0.1);
+    // 3 秒に満たないので short-term はまだ無い
+    assert_eq!(summary.max_short_term, None);
+}
+
+#[test]
+fn marks_gaps_in_sink() {
+    let mut sink = MemorySink::default();
+    let mut recorder = Recorder::new(Glitchy::new(), &mut sink).unwrap();
+    recorder.run().unwrap();
+    assert_eq!(recorder.capture_stats().gaps, 1);
+    assert_eq!(recorder.capture_stats().reconnects, 1);
+    drop(recorder);
+    assert_eq!(
+        sink.gaps,
+        [
+            (20, "gap (5 frames lost)".to_string()),
+            (30, "reconnect".to_string())
+        ]
+    );
+}
+
+#[test]
+fn writes_gaps_as_wav_cues() {
+    let path = std::env::temp_dir().join(format!("recorder-cues-{}.wav", std::process::id()));
+    let mut recorder = Recorder::new(Glitchy::new(), WavFileSink::new(&path)).unwrap();
+    recorder.run().unwrap();
+    drop(recorder);
+    let bytes = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let u32_at = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+    let cue = bytes
+        .windows(4)
+        .position(|window| window == b"cue ")
+        .unwrap();
+    assert_eq!(u32_at(cue + 8), 2);
+    // dwSampleOffset は各キューの最後
+    assert_eq!(u32_at(cue + 12 + 20), 20);
+    assert_eq!(u32_at(cue + 12 + 24 + 20), 30);
+    let labl = bytes
+        .windows(4)
+        .position(|window| window == b"labl")
+        .unwrap();
+    assert_eq!(&bytes[labl + 12..labl + 31], b"gap (5 frames lost)");
+    assert_eq!(u32_at(4) as usize, bytes.len() - 8);
+
+    let mut source = WavSource::from_bytes(&bytes).unwrap();
+    let mut frames = 0;
+    while let Some(buffer) = source.get_buffer().unwrap() {
+        frames += buffer.len() / 4;
+    }
+    assert_eq!(frames, 50);
+}
+
+#[test]
+fn writes_numbered_file_after_format_change() {
+    let path = std::env::temp_dir().join(format!("recorder-split-{}.wav", std::process::id()));
+    let next = windows_cap_audio::plot::numbered_path(&path, 1);
+    let mut recorder = Recorder::new(Switching::new(), WavFileSink::new(&path)).unwrap();
+    recorder.run().unwrap();
+    assert_eq!(recorder.frames(), 30);
+    assert_eq!(recorder.recorded(), Duration::from_millis(20));
+    drop(recorder);
+
+    let first = WavSource::open(&path).unwrap();
+    assert_eq!(*first.wave_format(), WaveFormatEx::ieee_float(1, 1_000));
+    let mut second = WavSource::open(&next).unwrap();
+    assert_eq!(*second.wave_format(), WaveFormatEx::ieee_float(2, 2_000));
+    let mut bytes = 0;
+    while let Some(buffer) = second.get_buffer().unwrap() {
+        bytes += buffer.len();
+    }
+    assert_eq!(bytes, 160);
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&next).unwrap();
+}
@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use windows_cap_audio::{ring::RingBuffer, source::MemorySource, util::App};
+
+#[test]
+fn keeps_newest_values_on_overrun() {
+    let mut ring = RingBuffer::new(4);
+    ring.extend(&[1, 2, 3]);
+    assert_eq!(ring.overruns(), 0);
+    ring.extend(&[4, 5, 6]);
+    assert_eq!(ring.iter().collect::<Vec<_>>(), vec![3, 4, 5, 6]);
+    assert_eq!(ring.overruns(), 1);
+    assert_eq!(ring.overrun_samples(), 2);
+
+    ring.extend(&[7, 8, 9, 10, 11, 12]);
+    assert_eq!(ring.iter().collect::<Vec<_>>(), vec![9, 10, 11, 12]);
+    assert_eq!(ring.overrun_samples(), 8);
+}
+
+#[test]
+fn copies_oldest_and_latest() {
+    let mut ring = RingBuffer::new(5);
+    ring.extend(&[1, 2, 3, 4, 5, 6, 7]);
+    let mut out = [0; 3];
+    assert!(ring.copy_to(&mut out));
+    assert_eq!(out, [3, 4, 5]);
+    assert!(ring.copy_latest_to(&mut out));
+    assert_eq!(out, [5, 6, 7]);
+
+    ring.discard(3);
+    assert_eq!(ring.len(), 2);
+    assert!(!ring.copy_to(&mut out));
+}
+
+#[test]
+fn app_analyzes_every_hop() {
+    // 2048 + 1024 * 3 サンプルあれば 50% 重ねで 4 フレーム取れる
+    let frames = 2048 + 1024 * 3;
+    let source = MemorySource::sine(
+        440.0,
+        0.5,
+        1,
+        48_000,
+        Duration::from_secs_f64(frames as f64 / 48_000.0),
+    )
+    .unwrap();
+    let mut app = App::new("sine".to_string(), source).with_hop(1024);
+    app.on_tick().unwrap();
+    let stats = app.stats();
+    assert_eq!(stats.analyzed_frames, 4);
+    assert_eq!(stats.overruns, 0);
+    assert_eq!(stats.buffered, 2048 - 1024);
+}
+
+#[test]
+fn app_history_is_bounded() {
+    let source = MemorySource::sine(440.0, 0.5, 1, 48_000, Duration::from_secs(1)).unwrap();
+    let mut app = App::new("sine".to_string(), source)
+        .with_history(4096)
+        .with_hop(2048);
+    app.on_tick().unwrap();
+    let stats = app.stats();
+    assert!(stats.overrun_samples > 0);
+    assert!(stats.buffered < 4096);
+}
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use windows_cap_audio::{
+    scope::{correlation, goniometer, Oscilloscope, MAX_TIMEBASE, MIN_TIMEBASE},
+    source::MemorySource,
+    util::App,
+};
+
+fn sine(freq: f32, phase: f32, len: usize) -> Vec<f32> {
+    (0..len)
+        .map(|n| (std::f32::consts::TAU * freq * n as f32 / 48_000.0 + phase).sin())
+        .collect()
+}
+
+#[test]
+fn triggers_on_latest_rising_edge() {
+    let scope = Oscilloscope::new(Duration::from_millis(10), 0.0);
+    assert_eq!(scope.frames(48_000), 480);
+    // 100Hz なので 480 サンプル毎に立ち上がる。後ろに 480 個取れる一番新しいもの
+    let samples = sine(100.0, -0.1, 2_000);
+    let start = scope.trigger(&samples, 480).unwrap();
+    assert!(samples[start - 1] < 0.0 && samples[start] >= 0.0);
+    assert!(start + 480 <= samples.len());
+    assert!(start + 480 * 2 > samples.len());
+
+    let capture = scope.capture(&[samples.clone(), samples], 48_000);
+    assert!(capture.triggered);
+    assert_eq!(capture.channels.len(), 2);
+    assert_eq!(capture.channels[0].len(), 480);
+    assert!(capture.channels[0][0] >= 0.0 && capture.channels[0][0] < 0.01);
+}
+
+#[test]
+fn runs_free_without_edge() {
+    let scope = Oscilloscope::new(Duration::from_millis(10), 0.5);
+    let samples = vec![0.25; 1_000];
+    let capture = scope.capture(&[samples], 48_000);
+    assert!(!capture.triggered);
+    assert_eq!(capture.channels[0].len(), 480);
+
+    // 足りない時はあるだけ
+    let capture = scope.capture(&[vec![0.0; 100]], 48_000);
+    assert_eq!(capture.channels[0].len(), 100);
+}
+
+#[test]
+fn clamps_timebase() {
+    let mut scope = Oscilloscope::default();
+    scope.set_timebase(Duration::from_micros(10));
+    assert_eq!(scope.timebase(), MIN_TIMEBASE);
+    scope.set_timebase(Duration::from_secs(10));
+    assert_eq!(scope.timebase(), MAX_TIMEBASE);
+}
+
+#[test]
+fn goniometer_puts_mono_on_vertical_axis() {
+    let points = goniometer(&[0.5, -0.5], &[0.5, -0.5]);
+    for (x, y) in points {
+        assert!(x.abs() < 1e-6);
+        assert!((y.abs() - 0.5 * std::f32::consts::SQRT_2).abs() < 1e-6);
+    }
+    let (x, y) = goniometer(&[1.0], &[0.0])[0];
+    assert!(x < 0.0 && y > 0.0);
+}
+
+#[test]
+fn correlation_detects_phase() {
+    let left = sine(1_000.0, 0.0, 4_800);
+    let inverted = left.iter().map(|v| -v).collect::<Vec<_>>();
+    let quadrature = sine(1_000.0, std::f32::consts::FRAC_PI_2, 4_800);
+    assert!((correlation(&left, &left) - 1.0).abs() < 1e-9);
+    assert!((correlation(&left, &inverted) + 1.0).abs() < 1e-9);
+    assert!(correlation(&left, &quadrature).abs() < 1e-3);
+    assert_eq!(correlation(&[0.0; 10], &left[..10]), 0.0);
+}
+
+#[test]
+fn app_keeps_latest_samples() {
+    let samples = (0..3_000).map(|n| n as f32 / 3_000.0).collect::<Vec<_>>();
+    let source = MemorySource::from_f32(1, 1_000, &samples).unwrap();
+    let mut app = App::new("ramp".to_string(), source);
+    app.on_tick().unwrap();
+    assert_eq!(app.samples_per_sec(), 1_000);
+    let waveform = app.waveform(10);
+    assert_eq!(waveform.len(), 1);
+    assert_eq!(waveform[0], &samples[2_990..]);
+    // 2 秒分までしか残らない
+    assert_eq!(app.waveform(5_000)[0].len(), 2_000);
+}
@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+use windows_cap_audio::{
+    smoothing::{Ballistics, PeakHold, Smoother},
+    source::MemorySource,
+    spectrum::{Binning, Scale},
+    util::App,
+};
+
+fn spectrum(power: f64) -> Vec<Vec<(f64, f64)>> {
+    vec![vec![(1_000.0, power)]]
+}
+
+fn value(series: Vec<Vec<(f64, f64)>>) -> f64 {
+    series[0][0].1
+}
+
+#[test]
+fn follows_with_attack_and_release() {
+    let mut smoother = Smoother::new(
+        Ballistics::new(Duration::from_millis(100), Duration::from_secs(1)),
+        PeakHold::default(),
+    );
+    smoother.update(&spectrum(0.0), 0.1);
+    smoother.update(&spectrum(1.0), 0.1);
+    // 時定数 1 つ分で 1 - 1/e
+    assert!((value(smoother.smoothed()) - (1.0 - (-1.0f64).exp())).abs() < 1e-9);
+
+    let before = value(smoother.smoothed());
+    smoother.update(&spectrum(0.0), 0.1);
+    assert!((value(smoother.smoothed()) - before * (-0.1f64).exp()).abs() < 1e-9);
+}
+
+#[test]
+fn zero_time_constants_do_not_smooth() {
+    let mut smoother = Smoother::default();
+    smoother.update(&spectrum(0.5), 0.1);
+    smoother.update(&spectrum(0.25), 0.1);
+    assert_eq!(value(smoother.smoothed()), 0.25);
+}
+
+#[test]
+fn holds_then_falls() {
+    let mut smoother = Smoother::new(
+        Ballistics::default(),
+        PeakHold::new(Duration::from_millis(500), 10.0),
+    );
+    smoother.update(&spectrum(1.0), 0.1);
+    for _ in 0..5 {
+        smoother.update(&spectrum(0.0), 0.1);
+    }
+    assert_eq!(value(smoother.peaks()), 1.0);
+
+    // hold を過ぎてから 1 秒で 10dB 落ちる
+    for _ in 0..10 {
+        smoother.update(&spectrum(0.0), 0.1);
+    }
+    assert!((value(smoother.peaks()) - 0.1).abs() < 1e-9);
+
+    smoother.update(&spectrum(0.5), 0.1);
+    assert_eq!(value(smoother.peaks()), 0.5);
+}
+
+#[test]
+fn averages_until_reset() {
+    let mut smoother = Smoother::default();
+    for power in [1.0, 2.0, 3.0, 6.0] {
+        smoother.update(&spectrum(power), 0.1);
+    }
+    assert_eq!(smoother.count(), 4);
+    assert_eq!(value(smoother.average()), 3.0);
+
+    smoother.reset();
+    smoother.update(&spectrum(8.0), 0.1);
+    assert_eq!(value(smoother.average()), 8.0);
+}
+
+#[test]
+fn resets_when_bands_change() {
+    let mut smoother = Smoother::default();
+    smoother.update(&spectrum(1.0), 0.1);
+    smoother.update(&[vec![(500.0, 0.5), (1_000.0, 0.25)]], 0.1);
+    assert_eq!(smoother.count(), 1);
+    assert_eq!(smoother.peaks(), vec![vec![(500.0, 0.5), (1_000.0, 0.25)]]);
+}
+
+#[test]
+fn app_exposes_series_in_display_scale() {
+    let source = MemorySource::sine(1_000.0, 1.0, 1, 48_000, Duration::from_secs(1)).unwrap();
+    let mut app = App::new("sine".to_string(), source)
+        .with_binning(Binning::Octave)
+        .with_scale(Scale::default())
+        .with_ballistics(Ballistics::new(
+            Duration::from_millis(50),
+            Duration::from_millis(500),
+        ));
+    app.on_tick().unwrap();
+
+    let peak = |series: &[Vec<(f64, f64)>]| {
+        series[0]
+            .iter()
+            .map(|&(_, value)| value)
+            .fold(f64::MIN, f64::max)
+    };
+    assert!(peak(app.spectra()).abs() < 0.2);
+    assert!(peak(&app.smoothed()).abs() < 0.2);
+    assert!(peak(&app.peaks()).abs() < 0.2);
+    assert!(peak(&app.average()).abs() < 0.2);
+    assert_eq!(app.smoothed()[0].len(), app.data().len());
+}
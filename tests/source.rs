@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+use windows_cap_audio::{
+    format::{Guid, WaveFormatEx, WaveFormatExtensible, WAVE_FORMAT_EXTENSIBLE, WAVE_FORMAT_PCM},
+    source::{AudioSource, MemorySource, WavSource},
+    util::App,
+};
+
+#[test]
+fn memory_source_splits_into_packets() {
+    let mut source = MemorySource::from_f32(2, 48_000, &[0.0; 2 * 1000])
+        .unwrap()
+        .with_frames_per_packet(300);
+    let mut frames = vec![];
+    while let Some(buffer) = source.get_buffer().unwrap() {
+        frames.push(buffer.len() / 8);
+    }
+    assert_eq!(frames, vec![300, 300, 300, 100]);
+    assert!(source.is_finished());
+}
+
+#[test]
+fn memory_source_rejects_zero_block_align() {
+    assert!(MemorySource::new(WaveFormatEx::ieee_float(0, 48_000), vec![0; 8]).is_err());
+    assert!(MemorySource::from_f32(0, 48_000, &[]).is_err());
+}
+
+#[test]
+fn wav_source_reads_fmt_and_data() {
+    let mut bytes = b"RIFF\0\0\0\0WAVE".to_vec();
+    bytes.extend(b"fmt \x10\0\0\0");
+    bytes.extend(WAVE_FORMAT_PCM.to_le_bytes());
+    bytes.extend(1u16.to_le_bytes());
+    bytes.extend(8_000u32.to_le_bytes());
+    bytes.extend(16_000u32.to_le_bytes());
+    bytes.extend(2u16.to_le_bytes());
+    bytes.extend(16u16.to_le_bytes());
+    bytes.extend(b"data\x04\0\0\0\x01\x00\x02\x00");
+
+    let mut source = WavSource::from_bytes(&bytes).unwrap();
+    assert_eq!(source.wave_format().samples_per_sec, 8_000);
+    assert_eq!(source.wave_format().block_align, 2);
+    assert_eq!(source.get_buffer().unwrap(), Some(vec![1, 0, 2, 0]));
+    assert_eq!(source.get_buffer().unwrap(), None);
+}
+
+#[test]
+fn app_runs_on_synthetic_source() {
+    let source = MemorySource::sine(1_000.0, 0.5, 1, 48_000, Duration::from_millis(100)).unwrap();
+    let mut app = App::new("sine".to_string(), source);
+    app.on_tick().unwrap();
+    assert!(!app.data().is_empty());
+}
+
+#[test]
+fn wav_source_reads_extensible_fmt() {
+    let format = WaveFormatEx {
+        format_tag: WAVE_FORMAT_EXTENSIBLE,
+        channels: 2,
+        samples_per_sec: 48_000,
+        avg_bytes_per_sec: 288_000,
+        block_align: 6,
+        bits_per_sample: 24,
+        size: 22,
+        extensible: Some(WaveFormatExtensible {
+            valid_bits_per_sample: 24,
+            channel_mask: 0x3,
+            sub_format: Guid::KSDATAFORMAT_SUBTYPE_PCM,
+        }),
+    };
+    let fmt = format.to_bytes();
+    let mut bytes = b"RIFF\0\0\0\0WAVE".to_vec();
+    bytes.extend(b"fmt ");
+    bytes.extend((fmt.len() as u32).to_le_bytes());
+    bytes.extend(&fmt);
+    bytes.extend(b"data\x06\0\0\0\0\0\0\0\0\0");
+
+    let source = WavSource::from_bytes(&bytes).unwrap();
+    assert_eq!(source.wave_format(), &format);
+}
+
+#[test]
+fn wav_source_streams_file_with_chunks_in_any_order() {
+    // data が fmt より前にあり、後ろに別のチャンクが続いても data の中身だけを返す
+    let mut bytes = b"RIFF\0\0\0\0WAVE".to_vec();
+    bytes.extend(b"data\x05\0\0\0\x01\x00\x02\x00\x03\0");
+    bytes.extend(b"fmt \x10\0\0\0");
+    bytes.extend(WAVE_FORMAT_PCM.to_le_bytes());
+    bytes.extend(1u16.to_le_bytes());
+    bytes.extend(8_000u32.to_le_bytes());
+    bytes.extend(16_000u32.to_le_bytes());
+    bytes.extend(2u16.to_le_bytes());
+    bytes.extend(16u16.to_le_bytes());
+    bytes.extend(b"LIST\x04\0\0\0INFO");
+    let path = std::env::temp_dir().join(format!("wav-source-{}.wav", std::process::id()));
+    std::fs::write(&path, &bytes).unwrap();
+
+    let mut source = WavSource::open(&path).unwrap().with_frames_per_packet(1);
+    let mut data = vec![];
+    while let Some(buffer) = source.get_buffer().unwrap() {
+        data.extend(buffer);
+    }
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(data, [1, 0, 2, 0]);
+    assert!(source.is_finished());
+}
+
+#[test]
+fn wav_source_rejects_zero_block_align() {
+    let mut bytes = b"RIFF\0\0\0\0WAVE".to_vec();
+    bytes.extend(b"fmt \x10\0\0\0");
+    bytes.extend(WAVE_FORMAT_PCM.to_le_bytes());
+    bytes.extend(1u16.to_le_bytes());
+    bytes.extend(8_000u32.to_le_bytes());
+    bytes.extend(16_000u32.to_le_bytes());
+    bytes.extend(0u16.to_le_bytes());
+    bytes.extend(16u16.to_le_bytes());
+    bytes.extend(b"data\x04\0\0\0\x01\x00\x02\x00");
+
+    assert!(WavSource::from_bytes(&bytes).is_err());
+}
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use windows_cap_audio::{
+    colormap::Colormap,
+    source::MemorySource,
+    spectrogram::{Rendering, Spectrogram},
+    spectrum::{Binning, Scale},
+    util::App,
+};
+
+#[test]
+fn colormaps_cover_their_ends() {
+    assert_eq!(Colormap::Viridis.color(0.0), 0x440154);
+    assert_eq!(Colormap::Viridis.color(1.0), 0xfde725);
+    assert_eq!(Colormap::Magma.color(0.0), 0x000004);
+    assert_eq!(Colormap::Magma.color(2.0), 0xfcfdbf);
+    assert_eq!(Colormap::Grayscale.color(0.5), 0x808080);
+    assert_eq!(Colormap::Grayscale.color(f64::NAN), 0x000000);
+}
+
+#[test]
+fn parses_colormap_names() {
+    for colormap in Colormap::VARIANTS {
+        assert_eq!(colormap.name().parse::<Colormap>().unwrap(), colormap);
+    }
+    assert!("jet".parse::<Colormap>().is_err());
+    assert_eq!(Colormap::Grayscale.next(), Colormap::Viridis);
+}
+
+#[test]
+fn keeps_newest_rows_first() {
+    let mut spectrogram = Spectrogram::new(2);
+    for value in [1.0, 2.0, 3.0] {
+        spectrogram.push(vec![(100.0, value)]);
+    }
+    assert_eq!(spectrogram.len(), 2);
+    let rows = spectrogram.rows().map(|row| row[0].1).collect::<Vec<_>>();
+    assert_eq!(rows, [3.0, 2.0]);
+}
+
+#[test]
+fn renders_newest_row_at_top() {
+    let mut spectrogram = Spectrogram::new(4);
+    spectrogram.push(vec![(100.0, 0.0), (300.0, 1.0)]);
+    spectrogram.push(vec![(100.0, 1.0), (300.0, 0.0)]);
+    let rendering = Rendering {
+        frequency_range: (0.0, 400.0),
+        log_frequency: false,
+        value_range: (0.0, 1.0),
+        colormap: Colormap::Grayscale,
+    };
+    let image = spectrogram.render(4, 8, &rendering);
+    assert_eq!(image.len(), 32);
+    // 0..200Hz は 100Hz の帯域、200..400Hz は 300Hz の帯域
+    assert_eq!(&image[0..4], &[0xffffff, 0xffffff, 0, 0]);
+    assert_eq!(&image[8..12], &[0, 0, 0xffffff, 0xffffff]);
+    // まだ無い行は黒
+    assert!(image[16..].iter().all(|&pixel| pixel == 0));
+}
+
+#[test]
+fn log_frequency_widens_low_bands() {
+    let mut spectrogram = Spectrogram::new(1);
+    spectrogram.push(vec![(100.0, 1.0), (10_000.0, 0.0)]);
+    let mut rendering = Rendering {
+        frequency_range: (10.0, 100_000.0),
+        log_frequency: false,
+        value_range: (0.0, 1.0),
+        colormap: Colormap::Grayscale,
+    };
+    let white = |image: Vec<u32>| image.iter().filter(|&&pixel| pixel == 0xffffff).count();
+    assert_eq!(white(spectrogram.render(100, 1, &rendering)), 5);
+    rendering.log_frequency = true;
+    assert_eq!(white(spectrogram.render(100, 1, &rendering)), 50);
+}
+
+#[test]
+fn app_records_spectrogram() {
+    let source = MemorySource::sine(1_000.0, 1.0, 1, 48_000, Duration::from_secs(1)).unwrap();
+    let mut app = App::new("sine".to_string(), source)
+        .with_binning(Binning::Octave)
+        .with_scale(Scale::default())
+        .with_hop(480)
+        .with_spectrogram_rows(4);
+    app.on_tick().unwrap();
+    assert_eq!(app.frame_interval(), 0.01);
+    assert_eq!(app.spectrogram().len(), 4);
+    assert_eq!(app.spectrogram().rows().next().unwrap(), app.data());
+}
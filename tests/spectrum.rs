@@ -0,0 +1,138 @@
+use std::time::Duration;
+
+use windows_cap_audio::{
+    source::MemorySource,
+    spectrum::{bin_power, Band, Binning, Scale},
+    util::App,
+};
+
+#[test]
+fn linear_bands_cover_range() {
+    let bands = Binning::Linear { bins: 4 }.bands(0.0, 400.0);
+    assert_eq!(bands.len(), 4);
+    assert_eq!(
+        bands[1],
+        Band {
+            center: 150.0,
+            low: 100.0,
+            high: 200.0
+        }
+    );
+}
+
+#[test]
+fn log_bands_are_geometric() {
+    let bands = Binning::Log { bins: 3 }.bands(10.0, 10_000.0);
+    assert_eq!(bands.len(), 3);
+    assert!((bands[0].high - 100.0).abs() < 1e-9);
+    assert!((bands[1].center - 100.0 * 10f64.sqrt()).abs() < 1e-9);
+    assert!((bands[2].high - 10_000.0).abs() < 1e-9);
+}
+
+#[test]
+fn octave_bands_follow_iso_centers() {
+    let centers = Binning::Octave
+        .bands(20.0, 20_000.0)
+        .iter()
+        .map(|band| band.center.round())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        centers,
+        vec![31.0, 63.0, 125.0, 250.0, 500.0, 1_000.0, 2_000.0, 4_000.0, 8_000.0, 16_000.0]
+    );
+
+    let third = Binning::ThirdOctave.bands(20.0, 20_000.0);
+    assert_eq!(third.len(), 31);
+    // 隣り合う帯域は隙間なく繋がっている
+    for pair in third.windows(2) {
+        assert!((pair[0].high - pair[1].low).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn bin_power_sums_energy() {
+    // 10Hz 間隔のビンにそれぞれ 1.0
+    let power = vec![1.0; 101];
+    let bands = Binning::Linear { bins: 2 }.bands(0.0, 1_000.0);
+    let binned = bin_power(&power, 10.0, &bands);
+    assert_eq!(binned, vec![(250.0, 50.0), (750.0, 50.0)]);
+
+    // ビンより狭い帯域は重なった割合だけ
+    let narrow = [Band {
+        center: 102.5,
+        low: 100.0,
+        high: 105.0,
+    }];
+    assert_eq!(bin_power(&power, 10.0, &narrow), vec![(102.5, 0.5)]);
+}
+
+#[test]
+fn parses_binning() {
+    assert_eq!(
+        "linear:128".parse::<Binning>().unwrap(),
+        Binning::Linear { bins: 128 }
+    );
+    assert_eq!("log".parse::<Binning>().unwrap(), Binning::Log { bins: 64 });
+    assert_eq!("octave".parse::<Binning>().unwrap(), Binning::Octave);
+    assert_eq!(
+        "third-octave".parse::<Binning>().unwrap(),
+        Binning::ThirdOctave
+    );
+    assert!("octave:3".parse::<Binning>().is_err());
+    assert!("log:0".parse::<Binning>().is_err());
+    assert!("mel".parse::<Binning>().is_err());
+}
+
+#[test]
+fn app_sums_whole_spectrum_into_bands() {
+    let source = MemorySource::sine(1_000.0, 0.5, 1, 48_000, Duration::from_millis(100)).unwrap();
+    let mut app = App::new("sine".to_string(), source)
+        .with_binning(Binning::Octave)
+        .with_scale(Scale::Power);
+    app.on_tick().unwrap();
+    let data = app.data();
+    assert_eq!(data.len(), 10);
+    let total = data.iter().map(|(_, power)| power).sum::<f64>();
+    let (center, power) = data[5];
+    assert_eq!(center, 1_000.0);
+    assert!(power / total > 0.99);
+}
+
+fn peak_dbfs(amplitude: f32, frequency: f32, scale: Scale) -> f64 {
+    let source =
+        MemorySource::sine(frequency, amplitude, 1, 48_000, Duration::from_millis(100)).unwrap();
+    let mut app = App::new("sine".to_string(), source)
+        .with_binning(Binning::ThirdOctave)
+        .with_scale(scale);
+    app.on_tick().unwrap();
+    app.data()
+        .iter()
+        .map(|(_, value)| *value)
+        .fold(f64::MIN, f64::max)
+}
+
+#[test]
+fn full_scale_sine_is_0_dbfs() {
+    let peak = peak_dbfs(1.0, 1_000.0, Scale::default());
+    assert!(peak.abs() < 0.1, "{peak}");
+    // ビンの真ん中から外れていても帯域で合計すれば変わらない
+    let peak = peak_dbfs(1.0, 1_011.7, Scale::default());
+    assert!(peak.abs() < 0.1, "{peak}");
+}
+
+#[test]
+fn decibel_scale_uses_reference_and_floor() {
+    let peak = peak_dbfs(0.1, 1_000.0, Scale::default());
+    assert!((peak + 20.0).abs() < 0.1, "{peak}");
+
+    let scale = Scale::Decibel {
+        reference: -20.0,
+        floor: -60.0,
+    };
+    let peak = peak_dbfs(0.1, 1_000.0, scale);
+    assert!(peak.abs() < 0.1, "{peak}");
+    assert_eq!(scale.apply(0.0), -60.0);
+    // フルスケールが +20 dB になる
+    assert_eq!(scale.range(), (-60.0, 20.0));
+    assert_eq!(Scale::default().range(), (-120.0, 0.0));
+}
@@ -0,0 +1,157 @@
+use std::io::{Cursor, Seek, SeekFrom, Write};
+
+use windows_cap_audio::{
+    format::{WaveFormatEx, WAVE_FORMAT_EXTENSIBLE, WAVE_FORMAT_IEEE_FLOAT, WAVE_FORMAT_PCM},
+    source::{AudioSource, WavSource},
+    writer::WavWriter,
+};
+
+fn write_to_vec(wave_format: &WaveFormatEx, packets: &[&[u8]]) -> Vec<u8> {
+    let mut cursor = Cursor::new(vec![]);
+    let mut writer = WavWriter::new(&mut cursor, wave_format).unwrap();
+    for packet in packets {
+        writer.write(packet).unwrap();
+    }
+    writer.finalize().unwrap();
+    cursor.into_inner()
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn find_chunk(bytes: &[u8], id: &[u8; 4]) -> Option<usize> {
+    bytes.windows(4).position(|window| window == id)
+}
+
+#[test]
+fn writes_pcm16_with_correct_sizes() {
+    let format = WaveFormatEx::pcm(2, 44_100, 16);
+    let bytes = write_to_vec(&format, &[&[1, 0, 2, 0], &[3, 0, 4, 0]]);
+
+    assert_eq!(&bytes[0..4], b"RIFF");
+    assert_eq!(u32_at(&bytes, 4) as usize, bytes.len() - 8);
+    let fmt = find_chunk(&bytes, b"fmt ").unwrap();
+    assert_eq!(u32_at(&bytes, fmt + 4), 16);
+    assert_eq!(find_chunk(&bytes, b"fact"), None);
+    let data = find_chunk(&bytes, b"data").unwrap();
+    assert_eq!(u32_at(&bytes, data + 4), 8);
+
+    let mut source = WavSource::from_bytes(&bytes).unwrap();
+    assert_eq!(source.wave_format(), &format);
+    assert_eq!(
+        source.get_buffer().unwrap(),
+        Some(vec![1, 0, 2, 0, 3, 0, 4, 0])
+    );
+}
+
+#[test]
+fn writes_float_with_fact_chunk() {
+    let format = WaveFormatEx::ieee_float(1, 48_000);
+    let samples = [0.5f32, -0.5, 0.25]
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect::<Vec<_>>();
+    let bytes = write_to_vec(&format, &[&samples]);
+
+    let fmt = find_chunk(&bytes, b"fmt ").unwrap();
+    assert_eq!(
+        u16::from_le_bytes([bytes[fmt + 8], bytes[fmt + 9]]),
+        WAVE_FORMAT_IEEE_FLOAT
+    );
+    let fact = find_chunk(&bytes, b"fact").unwrap();
+    assert_eq!(u32_at(&bytes, fact + 8), 3);
+
+    let source = WavSource::from_bytes(&bytes).unwrap();
+    assert_eq!(source.wave_format(), &format);
+}
+
+#[test]
+fn writes_extensible_header_for_24bit_pcm() {
+    let format = WaveFormatEx::pcm(2, 48_000, 24);
+    let bytes = write_to_vec(&format, &[&[0; 6]]);
+
+    let source = WavSource::from_bytes(&bytes).unwrap();
+    let written = source.wave_format();
+    assert_eq!(written.format_tag, WAVE_FORMAT_EXTENSIBLE);
+    assert_eq!(written.effective_format_tag(), Some(WAVE_FORMAT_PCM));
+    assert_eq!(
+        written.extensible.as_ref().unwrap().valid_bits_per_sample,
+        24
+    );
+    assert_eq!(written.extensible.as_ref().unwrap().channel_mask, 0x3);
+}
+
+#[test]
+fn pads_odd_sized_data() {
+    let format = WaveFormatEx::pcm(1, 8_000, 8);
+    let bytes = write_to_vec(&format, &[&[1, 2, 3]]);
+    assert_eq!(bytes.len() % 2, 0);
+    let data = find_chunk(&bytes, b"data").unwrap();
+    assert_eq!(u32_at(&bytes, data + 4), 3);
+    assert_eq!(u32_at(&bytes, 4) as usize, bytes.len() - 8);
+}
+
+/// 中身は捨てて先頭のヘッダ部分だけ覚えておく書き込み先
+#[derive(Default)]
+struct HeaderOnly {
+    header: Vec<u8>,
+    position: u64,
+    len: u64,
+}
+
+impl Write for HeaderOnly {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let start = self.position as usize;
+        if start < 256 {
+            let end = (start + buf.len()).min(256);
+            if self.header.len() < end {
+                self.header.resize(end, 0);
+            }
+            self.header[start..end].copy_from_slice(&buf[..end - start]);
+        }
+        self.position += buf.len() as u64;
+        self.len = self.len.max(self.position);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for HeaderOnly {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.position = match pos {
+            SeekFrom::Start(position) => position,
+            SeekFrom::End(offset) => (self.len as i64 + offset) as u64,
+            SeekFrom::Current(offset) => (self.position as i64 + offset) as u64,
+        };
+        Ok(self.position)
+    }
+}
+
+#[test]
+fn switches_to_rf64_past_4gib() {
+    let format = WaveFormatEx::ieee_float(2, 48_000);
+    let mut sink = HeaderOnly::default();
+    let mut writer = WavWriter::new(&mut sink, &format).unwrap();
+    let packet = vec![0; 1 << 20];
+    for _ in 0..4097 {
+        writer.write(&packet).unwrap();
+    }
+    let data_bytes = writer.data_bytes();
+    let frames = writer.frames();
+    writer.finalize().unwrap();
+
+    let header = &sink.header;
+    assert_eq!(&header[0..4], b"RF64");
+    assert_eq!(u32_at(header, 4), u32::MAX);
+    assert_eq!(&header[12..16], b"ds64");
+    let u64_at = |offset: usize| u64::from_le_bytes(header[offset..offset + 8].try_into().unwrap());
+    assert_eq!(u64_at(20), sink.len - 8);
+    assert_eq!(u64_at(28), data_bytes);
+    assert_eq!(u64_at(36), frames);
+    let data = find_chunk(header, b"data").unwrap();
+    assert_eq!(u32_at(header, data + 4), u32::MAX);
+}